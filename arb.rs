@@ -1,40 +1,276 @@
-use solana_sdk::{
-    pubkey::Pubkey,
-    signature::{Keypair, Signer},
-    transaction::Transaction,
-    instruction::Instruction,
-};
+use solana_sdk::pubkey::Pubkey;
 use std::{
-    str::FromStr, rc::Rc, collections::HashSet,
+    fmt, error::Error,
+    io::Write,
+    collections::{HashMap, HashSet},
 };
-use crate::pool::*;
+use tracing::{debug, warn, info_span};
+use crate::{pool::*, flash_loan::FlashLoan, registry::PoolType, metrics::metrics};
+
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub path: Vec<usize>,
+    pub mint_path: Vec<Pubkey>,
+    pub pool_path: Vec<Pubkey>,
+    pub venues: Vec<PoolType>,
+    //amounts[0] 是输入，amounts[i] 是第 i 跳的输出
+    pub amounts: Vec<u64>,
+    pub slot_range: Option<SlotRange>,
+}
+
+impl Opportunity {
+    pub fn init_balance(&self) -> u64 {
+        self.amounts[0]
+    }
+
+    pub fn final_balance(&self) -> u64 {
+        self.amounts[self.amounts.len() - 1]
+    }
+
+    pub fn profit(&self) -> u64 {
+        self.final_balance().saturating_sub(self.init_balance())
+    }
+
+    pub fn touches(&self, pool_ids: &HashSet<Pubkey>) -> bool {
+        self.pool_path.iter().any(|pool_id| pool_ids.contains(pool_id))
+    }
+}
+
+impl fmt::Display for Opportunity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "found arbitrage: {} -> {}, profit: {}, path: {:?}, pool_path:{:?}",
+            self.init_balance(), self.final_balance(), self.profit(), self.path, self.pool_path)?;
+        if let Some(range) = self.slot_range {
+            write!(f, ", slots: {}..={}", range.min, range.max)?;
+        }
+        Ok(())
+    }
+}
+
+//用池子当前状态沿同一条路径重新报价，返回每一跳的数量；池子不存在或报价失败返回 None
+pub fn quote_path(pools: &HashMap<Pubkey, PoolRef>, opportunity: &Opportunity, amount_in: u64) -> Option<Vec<u64>> {
+    let mut amounts = vec![amount_in];
+    for (hop, pool_id) in opportunity.pool_path.iter().enumerate() {
+        let pool = pools.get(pool_id)?.borrow();
+        let a_to_b = pool.get_mints()[0] == opportunity.mint_path[hop];
+        let amount_out = pool.calc_quote(a_to_b, amounts[hop]);
+        if amount_out == 0 {
+            return None;
+        }
+        amounts.push(amount_out);
+    }
+    Some(amounts)
+}
+
+//lamports 的手续费只能从 WSOL 起点的利润里扣，其它 mint 没有价格，不扣
+pub fn fee_in_mint(mint: &Pubkey, lamports: u64) -> u64 {
+    if *mint == spl_token::native_mint::ID {
+        lamports
+    } else {
+        0
+    }
+}
 
 pub struct Arbitrager {
     pub token_mints: Vec<Pubkey>,
+    pub mint2idx: HashMap<Pubkey, usize>,
+    pub pools: HashMap<Pubkey, PoolRef>,
     pub graph_edges: Vec<HashSet<usize>>,
     pub graph: PoolGraph,
+    pub max_hops: usize,
+    pub min_profit: u64,
+    //每笔交易预计的手续费（签名费 + 优先费，lamports），搜索时从 WSOL 起点的利润里扣掉
+    pub tx_fee: u64,
+    //有 reserve 的 base mint 借款，手续费也从利润里扣
+    pub flash_loan: Option<FlashLoan>,
+    //路径上各池子数据的 slot 差超过这个值就丢弃，None 表示不检查（比如账户流模式）
+    pub max_slot_spread: Option<u64>,
 }
 
 impl Arbitrager {
+    //按池子出现的顺序给 mint 编号，每个池子在两个方向上各加一条边
+    pub fn new(
+        pools: Vec<PoolRef>,
+        max_hops: usize,
+        min_profit: u64,
+        tx_fee: u64,
+        max_slot_spread: Option<u64>,
+    ) -> Self {
+        let mut mint2idx = HashMap::new();
+        let mut token_mints = vec![];
+        let mut graph_edges = vec![];
+        let mut graph = PoolGraph::new();
+        let mut pools_by_id = HashMap::new();
+
+        for pool in pools {
+            pools_by_id.insert(pool.borrow().get_pool_id(), pool.clone());
+            let idxs = pool.borrow().get_mints()
+                .into_iter()
+                .map(|mint|
+                    if let Some(&idx) = mint2idx.get(&mint) {
+                        idx
+                    } else {
+                        let idx = token_mints.len();
+                        mint2idx.insert(mint, idx);
+                        token_mints.push(mint);
+                        graph_edges.push(HashSet::new());
+                        idx
+                    }
+                )
+                .collect::<Vec<usize>>();
+
+            let idx0 = idxs[0];
+            let idx1 = idxs[1];
+            if !graph_edges[idx0].contains(&idx1) {
+                graph_edges[idx0].insert(idx1);
+            }
+            if !graph_edges[idx1].contains(&idx0) {
+                graph_edges[idx1].insert(idx0);
+            }
+
+            graph.add_pool(idx0, idx1, pool.clone());
+            graph.add_pool(idx1, idx0, pool);
+        }
+
+        Self {
+            token_mints,
+            mint2idx,
+            pools: pools_by_id,
+            graph_edges,
+            graph,
+            max_hops,
+            min_profit,
+            tx_fee,
+            flash_loan: None,
+            max_slot_spread,
+        }
+    }
+
+    pub fn flash_loan_fee(&self, mint: &Pubkey, amount: u64) -> u64 {
+        self.flash_loan
+            .as_ref()
+            .and_then(|flash_loan| flash_loan.reserve(mint))
+            .map_or(0, |reserve| reserve.fee(amount))
+    }
+
+    pub fn is_profitable(&self, start_mint: &Pubkey, init_balance: u64, final_balance: u64) -> bool {
+        final_balance > init_balance + self.min_profit + fee_in_mint(start_mint, self.tx_fee) + self.flash_loan_fee(start_mint, init_balance)
+    }
+
+    pub fn quote_path(&self, opportunity: &Opportunity, amount_in: u64) -> Option<Vec<u64>> {
+        quote_path(&self.pools, opportunity, amount_in)
+    }
+
+    //从 base mint 出发、不超过 max_hops 跳能回来的环上可能出现的 mint：
+    //环上任意一点离起点最多 max_hops / 2 跳
+    pub fn path_mints(&self, base_mints: &[Pubkey]) -> Vec<Pubkey> {
+        let mut seen = HashSet::new();
+        let mut frontier = base_mints
+            .iter()
+            .filter_map(|mint| self.mint2idx.get(mint).copied())
+            .filter(|idx| seen.insert(*idx))
+            .collect::<Vec<_>>();
+        for _ in 0..self.max_hops / 2 {
+            frontier = frontier
+                .iter()
+                .flat_map(|idx| self.graph_edges[*idx].iter().copied())
+                .filter(|idx| seen.insert(*idx))
+                .collect();
+        }
+        let mut mints = seen.into_iter().map(|idx| self.token_mints[idx]).collect::<Vec<_>>();
+        mints.sort();
+        mints
+    }
+
+    pub fn mint_count(&self) -> usize {
+        self.token_mints.len()
+    }
+
+    pub fn pool_count(&self) -> usize {
+        self.graph.0.values()
+            .flat_map(|edges| edges.0.values())
+            .map(|vec| vec.len())
+            .sum()
+    }
+
+    //touched 不为空时只报告经过这些池子的路径
+    pub fn search_and_report(
+        &self,
+        base_mints: &[Pubkey],
+        init_balance: u64,
+        error_pools: &mut HashSet<Pubkey>,
+        touched: Option<&HashSet<Pubkey>>,
+        sinks: &mut [Box<dyn Write>],
+    ) -> Result<Vec<Opportunity>, Box<dyn Error>> {
+        let _span = info_span!("search", base_mints = base_mints.len(), touched = touched.map(|touched| touched.len())).entered();
+        let timer = metrics().search_duration.start_timer();
+        let mut reported = vec![];
+        for start_mint in base_mints {
+            let Some(&start_mint_idx) = self.mint2idx.get(start_mint) else {
+                warn!(%start_mint, "base mint not in any loaded pool");
+                continue;
+            };
+            let opportunities = self.search(start_mint_idx, init_balance, error_pools);
+            for opportunity in opportunities {
+                if touched.is_some_and(|touched| !opportunity.touches(touched)) {
+                    continue;
+                }
+                for sink in sinks.iter_mut() {
+                    writeln!(sink, "{}", opportunity)?;
+                }
+                reported.push(opportunity);
+            }
+        }
+        let elapsed = timer.stop_and_record();
+        debug!(opportunities = reported.len(), error_pools = error_pools.len(), elapsed_ms = elapsed * 1000.0, "search finished");
+        metrics().opportunities_found.inc_by(reported.len() as u64);
+        for sink in sinks.iter_mut() {
+            sink.flush()?;
+        }
+        Ok(reported)
+    }
+
+    pub fn search(
+        &self,
+        start_mint_idx: usize,
+        init_balance: u64,
+        error_pools: &mut HashSet<Pubkey>,
+    ) -> Vec<Opportunity> {
+        let mut opportunities = vec![];
+        self.brute_force_search(
+            start_mint_idx,
+            init_balance,
+            vec![init_balance],
+            vec![start_mint_idx],
+            vec![],
+            error_pools,
+            &mut opportunities,
+        );
+        opportunities
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn brute_force_search(
         &self,
         start_mint_idx: usize,
         init_balance: u64,
-        curr_balance: u64,
+        amounts: Vec<u64>,
         path: Vec<usize>,
-        pool_path: Vec<Rc<Box<dyn PoolOperations>>>,
+        pool_path: Vec<PoolRef>,
         error_pools: &mut HashSet<Pubkey>,
+        opportunities: &mut Vec<Opportunity>,
     ) {
-        if path.len() == 4 {
+        if path.len() == self.max_hops + 1 {
             return;
         };
+        let curr_balance = amounts[amounts.len() - 1];
         let src_curr = path[path.len() - 1];
         let src_mint = self.token_mints[src_curr];
         let edges = &self.graph_edges[src_curr];
         //edges hashset中只有一个值{0} 应该去掉。并且pool_len=1
 
         for &dst_mint_idx in edges {
-            if path.len() == 3 && dst_mint_idx != start_mint_idx {
+            if path.len() == self.max_hops && dst_mint_idx != start_mint_idx {
                 continue;
             }
             if path.contains(&dst_mint_idx) && dst_mint_idx != start_mint_idx {
@@ -46,46 +282,93 @@ impl Arbitrager {
                 .unwrap().0
                 .get(&dst_mint_idx)
                 .unwrap();        
-            for pool in pools {
+            for pool_ref in pools {
+                let pool = pool_ref.borrow();
                 let pool_id = pool.get_pool_id(); 
                 //println!("pool_id: {}", pool_id);
                 if error_pools.contains(&pool_id) {
                     continue;
                 }
-                if let Some(_) = pool_path.iter().find(|pool| pool.get_pool_id() == pool_id) {
+                if pool_path.iter().any(|pool| pool.borrow().get_pool_id() == pool_id) {
                     continue;
                 }
                
                 let mut new_path = path.clone();
                 new_path.push(dst_mint_idx);
                 let mut new_pool_path = pool_path.clone();
-                new_pool_path.push(pool.clone());
+                new_pool_path.push(pool_ref.clone());
           
                 let pool_mints = pool.get_mints();
                 let a_to_b = pool_mints[0] == src_mint;
                 let new_balance = pool.calc_quote(a_to_b, curr_balance);
+                metrics().paths_explored.inc();
                 if new_balance == 0 {
                     error_pools.insert(pool_id);
                     continue;
                 }
+                let mut new_amounts = amounts.clone();
+                new_amounts.push(new_balance);
 
                 if dst_mint_idx == start_mint_idx {
-                    if new_balance > init_balance + 500_000 {
-                        println!("found arbitrage: {} -> {}, profit: {}, path: {:?}, pool_path:{:?}", 
-                            init_balance, new_balance, new_balance - init_balance, new_path,
-                            new_pool_path.iter().map(|pool| pool.get_pool_id()).collect::<Vec<_>>());
+                    if self.is_profitable(&self.token_mints[start_mint_idx], init_balance, new_balance) {
+                        let slot_range = new_pool_path
+                            .iter()
+                            .filter_map(|pool| pool.borrow().slot_range())
+                            .reduce(SlotRange::merge);
+                        let inconsistent = match (self.max_slot_spread, slot_range) {
+                            (Some(max_spread), Some(range)) => range.spread() > max_spread,
+                            _ => false,
+                        };
+                        if inconsistent {
+                            continue;
+                        }
+                        opportunities.push(Opportunity {
+                            mint_path: new_path.iter().map(|&idx| self.token_mints[idx]).collect(),
+                            path: new_path,
+                            pool_path: new_pool_path.iter().map(|pool| pool.borrow().get_pool_id()).collect(),
+                            venues: new_pool_path.iter().map(|pool| pool.borrow().get_venue()).collect(),
+                            amounts: new_amounts,
+                            slot_range,
+                        });
                     }
                 } else {
                     self.brute_force_search(
                         start_mint_idx,
                         init_balance,
-                        new_balance,
+                        new_amounts,
                         new_path,
                         new_pool_path,
                         error_pools,
+                        opportunities,
                     );
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::mock::MockPool;
+
+    #[test]
+    fn path_mints_within_half_max_hops() {
+        //base - a - b - c 一条链，另外 d - e 不和 base 连通
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        let [base, a, b, c, d, e] = mints;
+        let pools = [(base, a), (a, b), (b, c), (d, e)]
+            .into_iter()
+            .map(|(x, y)| MockPool::new(Pubkey::new_unique(), [x, y], [1_000, 1_000]).into_ref())
+            .collect::<Vec<_>>();
+        let sorted = |mut mints: Vec<Pubkey>| {
+            mints.sort();
+            mints
+        };
+        let arbitrager = |max_hops| Arbitrager::new(pools.clone(), max_hops, 0, 0, None);
+        assert_eq!(arbitrager(3).path_mints(&[base]), sorted(vec![base, a]));
+        assert_eq!(arbitrager(4).path_mints(&[base]), sorted(vec![base, a, b]));
+        assert_eq!(arbitrager(6).path_mints(&[base]), sorted(vec![base, a, b, c]));
+        //不在图里的 base mint 没有路径
+        assert!(arbitrager(6).path_mints(&[Pubkey::new_unique()]).is_empty());
+    }
+}
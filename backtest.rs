@@ -0,0 +1,484 @@
+use solana_sdk::pubkey::Pubkey;
+use serde::Serialize;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::{
+    error::Error, fmt, sync::Arc,
+    collections::{BTreeMap, HashMap, HashSet},
+};
+
+use crate::{
+    config::{BacktestConfig, Config},
+    registry::Registry,
+    arb::{Arbitrager, Opportunity, fee_in_mint},
+    fetch::{AccountFetcher, ReplayFetcher},
+    loader::load_all,
+    pool::PoolRef,
+    snapshot::SnapshotAccount,
+    stream::{AccountUpdate, WatchIndex},
+};
+use tracing::warn;
+
+//同一条池子路径算同一个机会
+type PathKey = Vec<Pubkey>;
+
+struct LiveOpportunity {
+    opportunity: Opportunity,
+    first_slot: u64,
+    last_slot: u64,
+}
+
+struct PendingTrade {
+    opportunity: Opportunity,
+    land_slot: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MintStats {
+    pub submitted: usize,
+    pub included: usize,
+    pub hits: usize,
+    pub reverted: usize,
+    pub expected_profit: u128,
+    pub realized_pnl: i128,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Distribution {
+    pub count: usize,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub max: f64,
+}
+
+impl Distribution {
+    pub fn of(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let count = values.len();
+        let percentile = |p: f64| values[((count - 1) as f64 * p).round() as usize];
+        Self {
+            count,
+            mean: values.iter().sum::<f64>() / count as f64,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            max: values[count - 1],
+        }
+    }
+}
+
+impl fmt::Display for Distribution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "n {}, mean {:.3}, p50 {:.3}, p90 {:.3}, max {:.3}",
+            self.count, self.mean, self.p50, self.p90, self.max)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BacktestReport {
+    pub start_slot: u64,
+    pub end_slot: u64,
+    pub updates: usize,
+    //start_slot 之后才出现、没放进初始状态的账户数
+    pub late_accounts: usize,
+    //不同路径的机会个数
+    pub opportunities: usize,
+    pub by_mint: BTreeMap<String, MintStats>,
+    //打包的交易里 realized_pnl > 0 的比例
+    pub hit_rate: f64,
+    //机会从出现到消失持续的 slot 数
+    pub lifetime_slots: Distribution,
+    //落地时实际利润 / 发现时预期利润
+    pub profit_decay: Distribution,
+}
+
+impl fmt::Display for BacktestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "slots {}..={}, updates {}, late accounts {}, opportunities {}",
+            self.start_slot, self.end_slot, self.updates, self.late_accounts, self.opportunities)?;
+        for (mint, stats) in &self.by_mint {
+            writeln!(f, "{}: submitted {}, included {}, hits {}, reverted {}, expected {}, pnl {}",
+                mint, stats.submitted, stats.included, stats.hits, stats.reverted,
+                stats.expected_profit, stats.realized_pnl)?;
+        }
+        writeln!(f, "hit rate {:.3}", self.hit_rate)?;
+        writeln!(f, "lifetime slots: {}", self.lifetime_slots)?;
+        write!(f, "profit decay: {}", self.profit_decay)
+    }
+}
+
+//start_slot 时每个账户最新的一条记录，返回 (start_slot, 初始状态, start_slot 之后才出现的账户数)。
+//之后才出现的账户不放进初始状态，否则就是拿未来的数据建池子，依赖它们的池子不加载。
+//没有指定 start_slot 时从所有账户都出现过的 slot 开始，loader 分批拉取时晚几个 slot 才录到的账户不会缺
+fn initial_state(records: &[SnapshotAccount], start_slot: Option<u64>) -> (u64, HashMap<Pubkey, SnapshotAccount>, usize) {
+    let mut first_seen: HashMap<Pubkey, u64> = HashMap::new();
+    for record in records {
+        first_seen.entry(record.pubkey).or_insert(record.slot);
+    }
+    let start_slot = start_slot.unwrap_or_else(|| first_seen.values().copied().max().unwrap_or_default());
+    let initial = records
+        .iter()
+        .filter(|record| record.slot <= start_slot)
+        .map(|record| (record.pubkey, record.clone()))
+        .collect::<HashMap<_, _>>();
+    let late_accounts = first_seen.len() - initial.len();
+    (start_slot, initial, late_accounts)
+}
+
+pub struct Backtest {
+    arbitrager: Arbitrager,
+    watch_index: WatchIndex,
+    params: BacktestConfig,
+    base_mints: Vec<Pubkey>,
+    init_balance: u64,
+    rng: StdRng,
+    live: HashMap<PathKey, LiveOpportunity>,
+    pending: Vec<PendingTrade>,
+    error_pools: HashSet<Pubkey>,
+    report: BacktestReport,
+    lifetimes: Vec<f64>,
+    decays: Vec<f64>,
+}
+
+impl Backtest {
+    //records 按 slot 排序后，start_slot 及之前的作为初始状态，之后的按 slot 逐步回放
+    pub fn run(
+        config: &Config,
+        registry: &Registry,
+        mut records: Vec<SnapshotAccount>,
+    ) -> Result<BacktestReport, Box<dyn Error>> {
+        records.sort_by_key(|record| record.slot);
+        if records.is_empty() {
+            return Err("snapshot is empty".into());
+        }
+        let (start_slot, initial, late_accounts) = initial_state(&records, config.backtest.start_slot);
+        if late_accounts > 0 {
+            warn!(late_accounts, start_slot, "accounts first recorded after start_slot are left out of the initial state");
+        }
+        let fetcher: Arc<dyn AccountFetcher> = Arc::new(ReplayFetcher::new(initial));
+        let pools = load_all(config, registry, &fetcher)?;
+        let mut report = Self::replay(config, pools, records, start_slot);
+        report.late_accounts = late_accounts;
+        Ok(report)
+    }
+
+    //pools 是 start_slot 时的状态，records 按 slot 排序
+    fn replay(config: &Config, pools: Vec<PoolRef>, records: Vec<SnapshotAccount>, start_slot: u64) -> BacktestReport {
+        let params = config.backtest.clone();
+        let end_slot = params.end_slot.unwrap_or(u64::MAX);
+        let mut watch_index = WatchIndex::new();
+        for pool in &pools {
+            watch_index.add_pool(pool);
+        }
+        //回放的是同一条账户流，不检查 slot 差；手续费按 tx_cost 从 WSOL 起点的利润里扣
+        let arbitrager = Arbitrager::new(
+            pools,
+            config.search.max_hops,
+            config.search.min_profit,
+            params.tx_cost,
+            None,
+        );
+
+        let mut backtest = Backtest {
+            arbitrager,
+            watch_index,
+            rng: StdRng::seed_from_u64(params.seed),
+            params,
+            base_mints: config.search.base_mints.clone(),
+            init_balance: config.search.init_balance,
+            live: HashMap::new(),
+            pending: vec![],
+            error_pools: HashSet::new(),
+            report: BacktestReport {
+                start_slot,
+                end_slot: start_slot,
+                ..BacktestReport::default()
+            },
+            lifetimes: vec![],
+            decays: vec![],
+        };
+        backtest.step(start_slot, &[], false);
+
+        let updates = records
+            .into_iter()
+            .filter(|record| record.slot > start_slot && record.slot <= end_slot)
+            .collect::<Vec<_>>();
+        for chunk in updates.chunk_by(|a, b| a.slot == b.slot) {
+            let slot = chunk[0].slot;
+            backtest.step(slot, chunk, true);
+        }
+        backtest.finish()
+    }
+
+    //touched_only 时只看经过本 slot 有变化的池子的路径
+    fn step(&mut self, slot: u64, records: &[SnapshotAccount], touched_only: bool) {
+        //先在 slot 开始时的状态上结算已经落地的交易
+        self.settle(slot);
+
+        let mut touched = HashSet::new();
+        for record in records {
+            let update = AccountUpdate {
+                pubkey: record.pubkey,
+                account: record.to_account(),
+                slot: record.slot,
+            };
+            touched.extend(self.watch_index.apply(&update).touched);
+        }
+        self.report.updates += records.len();
+        self.report.end_slot = slot;
+        self.error_pools.retain(|pool_id| !touched.contains(pool_id));
+
+        self.expire_live(slot);
+        if touched_only && touched.is_empty() {
+            return;
+        }
+        let touched = touched_only.then_some(&touched);
+        for start_mint in self.base_mints.clone() {
+            let Some(&start_mint_idx) = self.arbitrager.mint2idx.get(&start_mint) else {
+                continue;
+            };
+            let opportunities = self.arbitrager.search(start_mint_idx, self.init_balance, &mut self.error_pools);
+            for opportunity in opportunities {
+                if touched.is_some_and(|touched| !opportunity.touches(touched)) {
+                    continue;
+                }
+                self.observe(slot, opportunity);
+            }
+        }
+        if self.params.latency_slots == 0 {
+            self.settle(slot);
+        }
+    }
+
+    //新出现的路径提交一次，已经存在的只延长寿命
+    fn observe(&mut self, slot: u64, opportunity: Opportunity) {
+        if let Some(live) = self.live.get_mut(&opportunity.pool_path) {
+            live.last_slot = slot;
+            return;
+        }
+        self.live.insert(opportunity.pool_path.clone(), LiveOpportunity {
+            opportunity: opportunity.clone(),
+            first_slot: slot,
+            last_slot: slot,
+        });
+        self.report.opportunities += 1;
+        let stats = self.report.by_mint.entry(opportunity.mint_path[0].to_string()).or_default();
+        stats.submitted += 1;
+        stats.expected_profit += opportunity.profit() as u128;
+        self.pending.push(PendingTrade {
+            land_slot: slot + self.params.latency_slots,
+            opportunity,
+        });
+    }
+
+    //用当前状态重新报价，不再有利润的机会记为消失
+    fn expire_live(&mut self, slot: u64) {
+        let expired = self.live
+            .iter()
+            .filter(|(_, live)| {
+                let opportunity = &live.opportunity;
+                let still_profitable = self.arbitrager
+                    .quote_path(opportunity, opportunity.init_balance())
+                    .is_some_and(|amounts| {
+                        self.arbitrager.is_profitable(&opportunity.mint_path[0], amounts[0], amounts[amounts.len() - 1])
+                    });
+                !still_profitable
+            })
+            .map(|(pool_path, _)| pool_path.clone())
+            .collect::<Vec<_>>();
+        for pool_path in expired {
+            let live = self.live.remove(&pool_path).unwrap();
+            self.lifetimes.push((live.last_slot - live.first_slot + 1) as f64);
+        }
+        for live in self.live.values_mut() {
+            live.last_slot = slot;
+        }
+    }
+
+    fn settle(&mut self, slot: u64) {
+        let (landed, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|trade| trade.land_slot <= slot);
+        self.pending = pending;
+        for trade in landed {
+            self.settle_trade(trade);
+        }
+    }
+
+    fn settle_trade(&mut self, trade: PendingTrade) {
+        let included = self.rng.gen_bool(self.params.inclusion_prob);
+        let opportunity = &trade.opportunity;
+        let realized = self.arbitrager.quote_path(opportunity, opportunity.init_balance());
+        let stats = self.report.by_mint.entry(opportunity.mint_path[0].to_string()).or_default();
+        if !included {
+            return;
+        }
+        stats.included += 1;
+        let tx_cost = fee_in_mint(&opportunity.mint_path[0], self.params.tx_cost) as i128;
+        match realized {
+            //落地时亏损的交易会被链上的利润检查拒绝，只损失手续费
+            Some(amounts) if amounts[amounts.len() - 1] > amounts[0] => {
+                let profit = (amounts[amounts.len() - 1] - amounts[0]) as i128;
+                stats.realized_pnl += profit - tx_cost;
+                if profit > tx_cost {
+                    stats.hits += 1;
+                }
+                self.decays.push(profit as f64 / opportunity.profit().max(1) as f64);
+            }
+            _ => {
+                stats.reverted += 1;
+                stats.realized_pnl -= tx_cost;
+                self.decays.push(0.0);
+            }
+        }
+    }
+
+    fn finish(mut self) -> BacktestReport {
+        //回放结束时还没落地的交易按最终状态结算，还存活的机会按结束 slot 计算寿命
+        let pending = std::mem::take(&mut self.pending);
+        for trade in pending {
+            self.settle_trade(trade);
+        }
+        for live in self.live.values() {
+            self.lifetimes.push((live.last_slot - live.first_slot + 1) as f64);
+        }
+        let included: usize = self.report.by_mint.values().map(|stats| stats.included).sum();
+        let hits: usize = self.report.by_mint.values().map(|stats| stats.hits).sum();
+        self.report.hit_rate = if included > 0 { hits as f64 / included as f64 } else { 0.0 };
+        self.report.lifetime_slots = Distribution::of(self.lifetimes);
+        self.report.profit_decay = Distribution::of(self.decays);
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::account::Account;
+    use crate::pool::mock::MockPool;
+
+    const WSOL: Pubkey = spl_token::native_mint::ID;
+
+    fn record(pubkey: Pubkey, amounts: [u64; 2], slot: u64) -> SnapshotAccount {
+        SnapshotAccount::new(pubkey, &Account { data: MockPool::data(amounts), ..Account::default() }, slot)
+    }
+
+    //WSOL -> a -> WSOL：slot 101 第二个池子出现 97 的利润，102 缩到 47，103 消失
+    fn fixture() -> (Vec<PoolRef>, Vec<SnapshotAccount>) {
+        let a = Pubkey::new_unique();
+        let (first, second) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = vec![
+            MockPool::new(first, [WSOL, a], [1_000_000, 1_000_000]).into_ref(),
+            MockPool::new(second, [a, WSOL], [1_000_000, 1_000_000]).into_ref(),
+        ];
+        let records = vec![
+            record(first, [1_000_000, 1_000_000], 100),
+            record(second, [1_000_000, 1_000_000], 100),
+            record(second, [1_000_000, 1_100_000], 101),
+            record(second, [1_000_000, 1_050_000], 102),
+            record(second, [1_000_000, 1_000_000], 103),
+        ];
+        (pools, records)
+    }
+
+    fn run(latency_slots: u64, inclusion_prob: f64) -> BacktestReport {
+        let mut config = Config::default();
+        config.search.base_mints = vec![WSOL];
+        config.search.init_balance = 1_000;
+        config.search.max_hops = 2;
+        config.search.min_profit = 0;
+        config.backtest = BacktestConfig { latency_slots, inclusion_prob, tx_cost: 7, ..BacktestConfig::default() };
+        let (pools, records) = fixture();
+        Backtest::replay(&config, pools, records, 100)
+    }
+
+    fn wsol_stats(report: &BacktestReport) -> &MintStats {
+        &report.by_mint[&WSOL.to_string()]
+    }
+
+    #[test]
+    fn lands_at_discovery_slot_without_latency() {
+        let report = run(0, 1.0);
+        assert_eq!((report.start_slot, report.end_slot, report.updates), (100, 103, 3));
+        //同一条路径只提交一次
+        assert_eq!(report.opportunities, 1);
+        let stats = wsol_stats(&report);
+        assert_eq!((stats.submitted, stats.included, stats.hits, stats.reverted), (1, 1, 1, 0));
+        assert_eq!(stats.expected_profit, 97);
+        assert_eq!(stats.realized_pnl, 97 - 7);
+        assert_eq!(report.hit_rate, 1.0);
+        assert_eq!(report.profit_decay.mean, 1.0);
+        //101、102 两个 slot 有利润
+        assert_eq!(report.lifetime_slots.count, 1);
+        assert_eq!(report.lifetime_slots.max, 2.0);
+    }
+
+    #[test]
+    fn latency_settles_on_later_state() {
+        //103 开始时还是 102 的状态
+        let report = run(2, 1.0);
+        let stats = wsol_stats(&report);
+        assert_eq!((stats.included, stats.hits, stats.reverted), (1, 1, 0));
+        assert_eq!(stats.realized_pnl, 47 - 7);
+        assert_eq!(report.profit_decay.mean, 47.0 / 97.0);
+
+        //回放结束还没落地的按最终状态结算，利润没了只亏手续费
+        let report = run(5, 1.0);
+        let stats = wsol_stats(&report);
+        assert_eq!((stats.included, stats.hits, stats.reverted), (1, 0, 1));
+        assert_eq!(stats.realized_pnl, -7);
+        assert_eq!(report.hit_rate, 0.0);
+        assert_eq!(report.profit_decay.max, 0.0);
+    }
+
+    #[test]
+    fn excluded_trades_cost_nothing() {
+        let report = run(0, 0.0);
+        let stats = wsol_stats(&report);
+        assert_eq!((stats.submitted, stats.included, stats.hits, stats.reverted), (1, 0, 0, 0));
+        assert_eq!(stats.realized_pnl, 0);
+        assert_eq!(report.hit_rate, 0.0);
+        assert_eq!(report.profit_decay.count, 0);
+        assert_eq!(report.lifetime_slots.count, 1);
+    }
+
+    #[test]
+    fn initial_state_has_no_future_records() {
+        let (early, late) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let records = vec![
+            record(early, [1, 1], 100),
+            record(early, [2, 2], 102),
+            record(late, [3, 3], 103),
+            record(early, [4, 4], 105),
+        ];
+        //指定的 start_slot 之后才出现的账户不放进初始状态
+        let (start_slot, initial, late_accounts) = initial_state(&records, Some(102));
+        assert_eq!(start_slot, 102);
+        assert_eq!(initial.len(), 1);
+        assert_eq!(initial[&early].slot, 102);
+        assert_eq!(late_accounts, 1);
+
+        //默认从所有账户都出现过的 slot 开始
+        let (start_slot, initial, late_accounts) = initial_state(&records, None);
+        assert_eq!(start_slot, 103);
+        assert_eq!(initial[&early].slot, 102);
+        assert_eq!(initial[&late].slot, 103);
+        assert_eq!(late_accounts, 0);
+    }
+
+    #[test]
+    fn distribution_percentiles() {
+        assert_eq!(Distribution::of(vec![]).count, 0);
+        let distribution = Distribution::of((1..=10).rev().map(f64::from).collect());
+        assert_eq!(distribution.count, 10);
+        assert_eq!(distribution.mean, 5.5);
+        //下标按 (n - 1) * p 四舍五入
+        assert_eq!(distribution.p50, 6.0);
+        assert_eq!(distribution.p90, 9.0);
+        assert_eq!(distribution.max, 10.0);
+        let single = Distribution::of(vec![3.0]);
+        assert_eq!((single.p50, single.p90, single.max), (3.0, 3.0, 3.0));
+    }
+}
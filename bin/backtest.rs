@@ -0,0 +1,69 @@
+use std::{error::Error, fs, path::PathBuf};
+use clap::Parser;
+use tracing::info;
+
+use arbitrage::{
+    config::*, backtest::*, logging,
+    snapshot::SnapshotReader,
+};
+
+//按 slot 回放快照里的账户更新，统计不同参数下的收益；快照用 --replay 指定
+#[derive(Debug, Parser)]
+#[command(name = "backtest", about = "Replay a recorded account stream and simulate execution")]
+struct Args {
+    #[command(flatten)]
+    cli: Cli,
+    #[arg(long)]
+    latency_slots: Option<u64>,
+    #[arg(long)]
+    inclusion_prob: Option<f64>,
+    #[arg(long)]
+    tx_cost: Option<u64>,
+    #[arg(long)]
+    seed: Option<u64>,
+    #[arg(long)]
+    start_slot: Option<u64>,
+    #[arg(long)]
+    end_slot: Option<u64>,
+    //报告另外写成 json
+    #[arg(long)]
+    json: Option<PathBuf>,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mut config = Config::load(&args.cli)?;
+    let snapshot = config.snapshot.replay.clone().ok_or("backtest needs a snapshot, use --replay")?;
+    let backtest = &mut config.backtest;
+    if let Some(latency_slots) = args.latency_slots {
+        backtest.latency_slots = latency_slots;
+    }
+    if let Some(inclusion_prob) = args.inclusion_prob {
+        backtest.inclusion_prob = inclusion_prob;
+    }
+    if let Some(tx_cost) = args.tx_cost {
+        backtest.tx_cost = tx_cost;
+    }
+    if let Some(seed) = args.seed {
+        backtest.seed = seed;
+    }
+    if args.start_slot.is_some() {
+        backtest.start_slot = args.start_slot;
+    }
+    if args.end_slot.is_some() {
+        backtest.end_slot = args.end_slot;
+    }
+    config.validate()?;
+    logging::init(&config.log)?;
+
+    let registry = config.load_registry()?;
+    let records = SnapshotReader::open(&snapshot)?.read_all()?;
+    info!(records = records.len(), path = %snapshot.display(), "loaded snapshot");
+
+    let report = Backtest::run(&config, &registry, records)?;
+    println!("{}", report);
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,15 @@
+use std::error::Error;
+use clap::Parser;
+
+use arbitrage::{config::*, bot, logging};
+
+//和 scanner 一样的主循环，但没配置 executor 时直接退出，而不是只扫描不发送
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+    if config.execution.executor == ExecutorKind::None {
+        return Err("executor needs execution.executor (rpc, jito or paper), use scanner to only search".into());
+    }
+    logging::init(&config.log)?;
+    bot::run(config)
+}
@@ -0,0 +1,73 @@
+use solana_sdk::pubkey::Pubkey;
+use std::{error::Error, sync::Arc};
+use clap::Parser;
+use tracing::{info, warn};
+
+use arbitrage::{
+    config::*, fetch::*, loader::*, rpc_pool::*, logging,
+    registry::{PairData, PoolType},
+};
+
+//加载几个池子，打印 mint、依赖账户和两个方向的报价
+#[derive(Debug, Parser)]
+#[command(name = "pool-info", about = "Load pools and print their state and quotes")]
+struct Args {
+    #[command(flatten)]
+    cli: Cli,
+    #[arg(long)]
+    venue: PoolType,
+    #[arg(long, value_delimiter = ',', required = true)]
+    pools: Vec<Pubkey>,
+    #[arg(long, default_value_t = 1_000_000)]
+    amount: u64,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let config = Config::load(&args.cli)?;
+    logging::init(&config.log)?;
+    let fetcher: Arc<dyn AccountFetcher> = match &config.snapshot.replay {
+        Some(path) => Arc::new(ReplayFetcher::open(path, config.snapshot.replay_until_slot)?),
+        None => Arc::new(BatchFetcher {
+            rpc_client: Arc::new(RpcPool::from_config(&config.rpc, config.commitment())?),
+            commitment: config.commitment(),
+            batch_size: config.rpc.batch_size,
+            batch_retries: config.rpc.batch_retries,
+        }),
+    };
+    let pairs = args.pools
+        .iter()
+        .map(|&pool_id| PairData {
+            venue: args.venue,
+            pool_id,
+            mint_a: None,
+            mint_b: None,
+            decimals_a: None,
+            decimals_b: None,
+            symbol_a: None,
+            symbol_b: None,
+            tags: vec![],
+            enabled: true,
+        })
+        .collect::<Vec<_>>();
+    let handle = spawn_loader(args.venue, fetcher, pairs)
+        .ok_or_else(|| format!("venue {} is not compiled in, rebuild with feature \"{}\"", args.venue, args.venue.feature()))?;
+    let (pools, summary) = handle.join()?;
+    info!("{}", summary);
+    for (pool_id, reason) in &summary.skipped {
+        warn!(%pool_id, %reason, "skip pool");
+    }
+    for pool in pools {
+        let pool = pool.borrow();
+        let mints = pool.get_mints();
+        println!("pool {}", pool.get_pool_id());
+        println!("  mints: {} / {}", mints[0], mints[1]);
+        println!("  watch: {:?}", pool.accounts_to_watch());
+        if let Some(range) = pool.slot_range() {
+            println!("  slots: {}..={}", range.min, range.max);
+        }
+        println!("  quote a->b {}: {}", args.amount, pool.calc_quote(true, args.amount));
+        println!("  quote b->a {}: {}", args.amount, pool.calc_quote(false, args.amount));
+    }
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use std::{error::Error, path::PathBuf};
+use clap::{Parser, Subcommand};
+
+use arbitrage::registry::{PoolType, Registry};
+
+//检查、合并、转换池子注册表
+#[derive(Debug, Parser)]
+#[command(name = "registry-tool", about = "Inspect, merge and convert pool registries")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    //按 venue 统计池子数量
+    Check {
+        //path 或 venue=path，venue 只用于没有 venue 字段的旧格式文件
+        inputs: Vec<String>,
+    },
+    //多个文件合并成一个，同一个 pool_id 以后面的为准；输出格式按扩展名
+    Merge {
+        #[arg(short, long)]
+        output: PathBuf,
+        inputs: Vec<String>,
+    },
+}
+
+//venue 名里没有 =，只在第一个 = 处切开，路径里可以有 : 和 =；前缀不是 venue 时整个当作路径
+fn parse_input(input: &str) -> (PathBuf, Option<PoolType>) {
+    match input.split_once('=').and_then(|(venue, path)| Some((path, venue.parse().ok()?))) {
+        Some((path, venue)) => (PathBuf::from(path), Some(venue)),
+        None => (PathBuf::from(input), None),
+    }
+}
+
+fn load(inputs: &[String]) -> Result<Registry, Box<dyn Error>> {
+    let sources = inputs.iter().map(|input| parse_input(input)).collect::<Vec<_>>();
+    Registry::load_all(&sources)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Check { inputs } => {
+            let registry = load(&inputs)?;
+            for venue in PoolType::ALL {
+                let total = registry.pools.iter().filter(|pair| pair.venue == venue).count();
+                println!("{}: {} pools, {} enabled", venue, total, registry.by_venue(venue).len());
+            }
+        }
+        Command::Merge { output, inputs } => {
+            let registry = load(&inputs)?;
+            registry.save(&output)?;
+            println!("wrote {} pools to {}", registry.pools.len(), output.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_input_keeps_colons_in_paths() {
+        assert_eq!(parse_input("pools.json"), (PathBuf::from("pools.json"), None));
+        assert_eq!(parse_input("orca=pools.json"), (PathBuf::from("pools.json"), Some(PoolType::Orca)));
+        assert_eq!(parse_input("C:/data/pools.json"), (PathBuf::from("C:/data/pools.json"), None));
+        assert_eq!(parse_input("/tmp/a:b/x=y.json"), (PathBuf::from("/tmp/a:b/x=y.json"), None));
+        assert_eq!(parse_input("raydium=/tmp/a:b/x=y.json"), (PathBuf::from("/tmp/a:b/x=y.json"), Some(PoolType::RayAmm)));
+    }
+}
@@ -0,0 +1,11 @@
+use std::error::Error;
+use clap::Parser;
+
+use arbitrage::{config::*, bot, logging};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let config = Config::load(&cli)?;
+    logging::init(&config.log)?;
+    bot::run(config)
+}
@@ -0,0 +1,383 @@
+use solana_sdk::{pubkey::Pubkey, signature::Signer};
+use std::{
+    error::Error, sync::Arc,
+    collections::{HashMap, HashSet},
+    sync::mpsc::channel,
+    time::{Duration, Instant},
+};
+use tracing::{info, warn, debug, info_span};
+
+use crate::{
+    config::*, fetch::*, loader::*, rpc_pool::*, arb::*, stream::*, source::*, snapshot::*, execution::*,
+    executor::*, lookup_table::*, flash_loan::*, wallet::*, paper::*, journal::*, metrics::*,
+};
+
+//模拟通过的交易交给 executor 发送，模拟失败的打印日志方便排查
+struct Execution {
+    preflight: Preflight,
+    executor: Option<Box<dyn Executor>>,
+    tracker: SubmissionTracker,
+    rpc_client: Arc<RpcPool>,
+    wallet: Wallet,
+    base_mints: Vec<Pubkey>,
+    wallet_interval: Duration,
+    last_maintained: Option<Instant>,
+}
+
+impl Execution {
+    fn maintain_wallet(&mut self, arbitrager: &Arbitrager) {
+        if self.last_maintained.is_some_and(|last| last.elapsed() < self.wallet_interval) {
+            return;
+        }
+        self.wallet.maintain(arbitrager, &self.base_mints);
+        self.last_maintained = Some(Instant::now());
+    }
+
+    fn poll(&mut self, journal: &mut Journal) {
+        if let Some(executor) = &self.executor {
+            for (submission, status) in self.tracker.poll(executor.as_ref()) {
+                info!(executor = executor.name(), submission = %submission.id, signature = %submission.signature, %status, "submission finished");
+                metrics().opportunities_executed.with_label_values(&[executor.name(), status.label()]).inc();
+                journal.resolve(&submission.id, &status);
+            }
+            journal.flush();
+        }
+    }
+
+    //退出前等已经提交的交易出结果或超时
+    fn finish(&mut self, journal: &mut Journal) {
+        while !self.tracker.is_empty() {
+            std::thread::sleep(Duration::from_millis(500));
+            self.poll(journal);
+        }
+        if let Some(summary) = self.executor.as_ref().and_then(|executor| executor.summary()) {
+            info!("{}", summary);
+        }
+    }
+
+    //每个机会在 journal 里记一行，提交了的等有结果再写
+    fn handle(&mut self, arbitrager: &Arbitrager, opportunities: &[Opportunity], journal: &mut Journal) {
+        self.poll(journal);
+        self.maintain_wallet(arbitrager);
+        if opportunities.is_empty() {
+            return;
+        }
+        let recent_blockhash = match self.rpc_client.call(|client| client.get_latest_blockhash()) {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!(error = %e, "get latest blockhash failed");
+                metrics().opportunities_skipped.with_label_values(&["blockhash"]).inc_by(opportunities.len() as u64);
+                for opportunity in opportunities {
+                    journal.record(&JournalEntry::new(opportunity).with_result(false, format!("get latest blockhash failed: {}", e)));
+                }
+                journal.flush();
+                return;
+            }
+        };
+        let live = self.executor.as_ref().is_none_or(|executor| executor.is_live());
+        for opportunity in opportunities {
+            let _span = info_span!(
+                "execute",
+                pool_path = ?opportunity.pool_path,
+                slot = ?opportunity.slot_range.map(|range| range.max),
+            ).entered();
+            let entry = JournalEntry::new(opportunity);
+            //用钱包余额做的路径要先有足够的起始 token，借款的不用
+            let start_mint = &opportunity.mint_path[0];
+            let borrowed = self.preflight.flash_loan.as_ref().is_some_and(|flash_loan| flash_loan.reserve(start_mint).is_some());
+            if live && !borrowed && self.wallet.balance(start_mint) < opportunity.init_balance() {
+                let message = format!("wallet has {} of {}, need {}", self.wallet.balance(start_mint), start_mint, opportunity.init_balance());
+                info!(%start_mint, "{}", message);
+                metrics().opportunities_skipped.with_label_values(&["wallet_balance"]).inc();
+                journal.record(&entry.with_result(false, message));
+                continue;
+            }
+            let checked = match self.preflight.check(arbitrager, opportunity, recent_blockhash) {
+                Ok(checked) => checked,
+                Err(e) => {
+                    warn!(error = %e, "build transaction failed");
+                    metrics().opportunities_skipped.with_label_values(&["build_failed"]).inc();
+                    journal.record(&entry.with_result(false, format!("build transaction failed: {}", e)));
+                    continue;
+                }
+            };
+            info!(profit = opportunity.profit(), "{}", checked);
+            if let Verdict::Failed(_) = checked.verdict {
+                for log in &checked.simulation.logs {
+                    debug!(log = %log, "simulation log");
+                }
+            }
+            let Some(executor) = self.executor.as_ref().filter(|_| !live || checked.is_accepted()) else {
+                let reason = if self.executor.is_some() { "simulation" } else { "no_executor" };
+                metrics().opportunities_skipped.with_label_values(&[reason]).inc();
+                journal.record(&entry.with_result(false, &checked));
+                continue;
+            };
+            match executor.submit(&checked, &self.preflight.payer) {
+                Ok(submission) => {
+                    info!(executor = executor.name(), submission = %submission.id, tip = submission.tip, "submitted");
+                    metrics().opportunities_submitted.with_label_values(&[executor.name()]).inc();
+                    journal.defer(&submission.id, entry);
+                    self.tracker.push(submission);
+                }
+                Err(e) => {
+                    warn!(executor = executor.name(), error = %e, "submit failed");
+                    metrics().opportunities_skipped.with_label_values(&["submit_failed"]).inc();
+                    journal.record(&entry.with_result(false, format!("submit failed: {}", e)));
+                }
+            }
+        }
+        journal.flush();
+    }
+}
+
+//scanner 和 executor 共用的主循环：拉池子、建图、搜索，配置了 executor 时提交交易
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    let registry = config.load_registry()?;
+    if let Some(listen) = &config.metrics.listen {
+        serve(listen)?;
+        info!(%listen, "serving metrics on /metrics");
+    }
+    if let Some(listen) = &config.metrics.admin_listen {
+        serve_admin(listen)?;
+        info!(%listen, "serving log filter on /log");
+    }
+
+    //回放时不创建 rpc
+    let mut rpc_client: Option<Arc<RpcPool>> = None;
+    let mut fetcher: Arc<dyn AccountFetcher> = match &config.snapshot.replay {
+        Some(path) => {
+            let replay = ReplayFetcher::open(path, config.snapshot.replay_until_slot)?;
+            info!(accounts = replay.len(), path = %path.display(), "replay snapshot");
+            Arc::new(replay)
+        }
+        None => {
+            let rpc = Arc::new(RpcPool::from_config(&config.rpc, config.commitment())?);
+            rpc_client = Some(Arc::clone(&rpc));
+            Arc::new(BatchFetcher {
+                rpc_client: rpc,
+                commitment: config.commitment(),
+                batch_size: config.rpc.batch_size,
+                batch_retries: config.rpc.batch_retries,
+            })
+        }
+    };
+    let recorder = match &config.snapshot.record {
+        Some(path) => Some(Recorder::create(path)?),
+        None => None,
+    };
+    if let Some(recorder) = &recorder {
+        fetcher = Arc::new(RecordingFetcher::new(fetcher, recorder.clone()));
+    }
+
+    let all_pools = load_all(&config, &registry, &fetcher)?;
+    for stats in rpc_client.iter().flat_map(|rpc| rpc.stats()) {
+        info!("rpc {}", stats);
+    }
+
+    let mut watch_index = WatchIndex::new();
+    for pool in &all_pools {
+        watch_index.add_pool(pool);
+    }
+    let max_slot_spread = config.search.max_slot_spread;
+    align_slots(&all_pools, &mut watch_index, fetcher.as_ref(), max_slot_spread, config.search.max_refetch_rounds);
+
+    let mut arbitrager = Arbitrager::new(
+        all_pools,
+        config.search.max_hops,
+        config.search.min_profit,
+        config.execution.tx_fee(),
+        Some(max_slot_spread),
+    );
+    info!(pools = arbitrager.pool_count(), mints = arbitrager.mint_count(), "graph built");
+    metrics().graph_nodes.set(arbitrager.mint_count() as i64);
+    metrics().graph_edges.set(arbitrager.pool_count() as i64);
+
+    let init_balance = config.init_balance();
+    if config.execution.flash_loan.enabled {
+        let flash_loan_config = &config.execution.flash_loan;
+        let flash_loan = FlashLoan::load(flash_loan_config.program_id, fetcher.as_ref(), &flash_loan_config.reserves)?;
+        for (mint, reserve) in &flash_loan.reserves {
+            info!(
+                reserve = %reserve.key, %mint, available = reserve.available_amount,
+                fee = reserve.fee(init_balance), init_balance, "flash loan reserve",
+            );
+        }
+        arbitrager.flash_loan = Some(flash_loan);
+    }
+
+    let mut execution = match (&config.execution.keypair, &rpc_client) {
+        (Some(path), Some(rpc)) if config.execution.simulate => {
+            let wallet = Wallet::new(
+                read_keypair(path)?,
+                BatchFetcher {
+                    rpc_client: rpc.clone(),
+                    commitment: config.commitment(),
+                    batch_size: config.rpc.batch_size,
+                    batch_retries: config.rpc.batch_retries,
+                },
+                config.execution.wallet.clone(),
+            );
+            let payer = wallet.keypair.clone();
+            let profit_guard = Some(config.execution.profit_guard.program_id).filter(|_| config.execution.profit_guard.enabled);
+            let mut lookup_tables = LookupTableManager::load(
+                rpc.clone(),
+                &config.execution.lookup_tables.addresses,
+                config.execution.lookup_tables.state_file.as_deref(),
+            )?;
+            if config.execution.lookup_tables.manage {
+                let mut static_accounts = arbitrager.pools
+                    .values()
+                    .flat_map(|pool| pool.borrow().static_accounts())
+                    .collect::<Vec<_>>();
+                static_accounts.extend(arbitrager.flash_loan.iter().flat_map(|flash_loan| flash_loan.static_accounts()));
+                static_accounts.extend(profit_guard.iter().flat_map(|program_id| {
+                    [*program_id, profit_guard::guard_address(program_id, &payer.pubkey()).0]
+                }));
+                let added = lookup_tables.sync(&payer, static_accounts)?;
+                info!(added, total = lookup_tables.len(), "lookup tables synced");
+            }
+            let executor: Option<Box<dyn Executor>> = match config.execution.executor {
+                ExecutorKind::None => None,
+                ExecutorKind::Rpc => Some(Box::new(RpcExecutor { rpc_client: rpc.clone() })),
+                ExecutorKind::Jito => Some(Box::new(JitoExecutor::new(&config.execution.jito, config.execution.fees.base_fee)?)),
+                ExecutorKind::Paper => {
+                    //WSOL 是 base mint 时和付手续费的 SOL 记在一起
+                    let mut initial = HashMap::new();
+                    for mint in &config.search.base_mints {
+                        initial.insert(*mint, init_balance);
+                    }
+                    *initial.entry(spl_token::native_mint::ID).or_default() += config.execution.paper.sol_balance;
+                    Some(Box::new(PaperExecutor::new(config.execution.paper.clone(), &arbitrager, initial)))
+                }
+            };
+            let preflight = Preflight {
+                simulator: Box::new(RpcSimulator {
+                    rpc_client: Arc::clone(rpc),
+                    commitment: config.commitment(),
+                }),
+                fee_source: rpc.clone(),
+                payer,
+                min_profit: config.search.min_profit,
+                fees: config.execution.fees.clone(),
+                lookup_tables: lookup_tables.tables(),
+                flash_loan: arbitrager.flash_loan.clone(),
+                profit_guard,
+            };
+            if preflight.init_profit_guard(rpc)? {
+                info!("created profit guard account");
+            }
+            Some(Execution {
+                preflight,
+                executor,
+                tracker: SubmissionTracker::new(Duration::from_millis(config.execution.confirm_timeout_ms)),
+                rpc_client: rpc.clone(),
+                wallet,
+                base_mints: config.search.base_mints.clone(),
+                wallet_interval: Duration::from_millis(config.execution.wallet.interval_ms),
+                last_maintained: None,
+            })
+        }
+        _ => None,
+    };
+
+    let mut sinks = config.output.open_sinks()?;
+    let mut journal = Journal::open(&config.journal)?;
+    let mut error_pools = HashSet::new();
+    let base_mints = &config.search.base_mints;
+
+    info!("start search arbitrage");
+    let opportunities = arbitrager.search_and_report(base_mints, init_balance, &mut error_pools, None, &mut sinks)?;
+    metrics().error_pools.with_label_values(&["zero_quote"]).set(error_pools.len() as i64);
+    match &mut execution {
+        Some(execution) => execution.handle(&arbitrager, &opportunities, &mut journal),
+        None => journal.record_all(&opportunities),
+    }
+
+    if config.stream.enabled {
+        //流式更新下未变化的账户在最新 slot 仍然有效，不再检查 slot 差
+        arbitrager.max_slot_spread = None;
+        let owners = arbitrager.pools.values()
+            .map(|pool| pool.borrow().get_venue().program_id())
+            .collect::<HashSet<_>>();
+        let source = build_source(&config, rpc_client.clone().ok_or("stream mode needs rpc")?, owners.into_iter().collect())?;
+        info!(source = source.name(), "start account stream");
+        let (tx, rx) = channel();
+        let source = SourceHandle::start(source, watch_index.keys(), tx);
+
+        //每次把 channel 里积压的更新全部应用完再搜索
+        let mut latest_slot: u64 = 0;
+        while let Ok(event) = rx.recv() {
+            let mut result = ApplyResult::default();
+            let mut next = Some(event);
+            while let Some(event) = next {
+                match event {
+                    StreamEvent::Account(update) => {
+                        if let Some(recorder) = &recorder {
+                            recorder.record(update.pubkey, &update.account, update.slot);
+                        }
+                        if latest_slot > 0 {
+                            metrics().account_update_lag.observe(latest_slot.saturating_sub(update.slot) as f64);
+                        }
+                        result.merge(watch_index.apply(&update));
+                    }
+                    StreamEvent::Slot(slot) => latest_slot = latest_slot.max(slot),
+                }
+                next = rx.try_recv().ok();
+            }
+            if let Some(recorder) = &recorder {
+                recorder.flush()?;
+            }
+
+            //新依赖的账户（比如 orca 换了 tick array）先用 rpc 拉一次，之后交给数据源推送
+            let mut pending = result.watch.clone();
+            while !pending.is_empty() {
+                let mut applied = ApplyResult::default();
+                let fetched_batch = fetcher.fetch(&pending);
+                for (key, e) in &fetched_batch.failed {
+                    warn!(account = %key, error = %e, "fetch watched account failed");
+                }
+                for fetched in fetched_batch.accounts.into_values() {
+                    if let Some(account) = fetched.account {
+                        let update = AccountUpdate { pubkey: fetched.pubkey, account, slot: fetched.slot };
+                        applied.merge(watch_index.apply(&update));
+                    }
+                }
+                pending = applied.watch.clone();
+                result.merge(applied);
+            }
+            //同一批里先退订又重新依赖的账户，以 watch_index 最终状态为准
+            source.watch(result.watch.iter().filter(|key| watch_index.contains(key)).cloned().collect());
+            source.unwatch(result.unwatch.iter().filter(|key| !watch_index.contains(key)).cloned().collect());
+
+            if result.touched.is_empty() {
+                continue;
+            }
+            let touched: HashSet<Pubkey> = result.touched.into_iter().collect();
+            let _span = info_span!("batch", latest_slot, touched = touched.len()).entered();
+            error_pools.retain(|pool_id| !touched.contains(pool_id));
+            let opportunities = arbitrager.search_and_report(base_mints, init_balance, &mut error_pools, Some(&touched), &mut sinks)?;
+            metrics().error_pools.with_label_values(&["zero_quote"]).set(error_pools.len() as i64);
+            match &mut execution {
+                Some(execution) => execution.handle(&arbitrager, &opportunities, &mut journal),
+                None => journal.record_all(&opportunities),
+            }
+        }
+
+        match source.join() {
+            Ok(()) => info!("account stream closed"),
+            Err(e) => return Err(format!("account stream failed: {e}").into()),
+        }
+    }
+
+    if let Some(execution) = &mut execution {
+        execution.finish(&mut journal);
+    }
+    journal.close()?;
+    if let Some(recorder) = &recorder {
+        recorder.flush()?;
+        info!(accounts = recorder.count(), "recorded snapshot");
+    }
+    info!("done");
+    Ok(())
+}
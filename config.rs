@@ -0,0 +1,897 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+};
+use clap::Parser;
+use serde::{Serialize, Deserialize};
+use std::{
+    str::FromStr, error::Error, fs,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+use crate::{
+    registry::{PoolType, Registry, PairData, serde_pubkey},
+    flash_loan::SOLEND_PROGRAM_ID,
+    metrics::loopback_addr,
+};
+
+pub const WSOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+//优先级: 默认值 < 配置文件 < 环境变量/命令行
+#[derive(Debug, Parser)]
+#[command(name = "arbitrage", about = "Solana DEX cycle arbitrage scanner")]
+pub struct Cli {
+    #[arg(short, long, env = "ARB_CONFIG")]
+    pub config: Option<PathBuf>,
+    #[arg(long, env = "ARB_RPC_URL", hide_env_values = true)]
+    pub rpc_url: Option<String>,
+    #[arg(long, env = "ARB_WS_URL", hide_env_values = true)]
+    pub ws_url: Option<String>,
+    #[arg(long, env = "ARB_COMMITMENT")]
+    pub commitment: Option<CommitmentLevel>,
+    #[arg(long, env = "ARB_BATCH_SIZE")]
+    pub batch_size: Option<usize>,
+    #[arg(long, env = "ARB_VENUES", value_delimiter = ',')]
+    pub venues: Option<Vec<PoolType>>,
+    #[arg(long, env = "ARB_MAX_HOPS")]
+    pub max_hops: Option<usize>,
+    #[arg(long, env = "ARB_BASE_MINTS", value_delimiter = ',')]
+    pub base_mints: Option<Vec<Pubkey>>,
+    #[arg(long, env = "ARB_INIT_BALANCE")]
+    pub init_balance: Option<u64>,
+    #[arg(long, env = "ARB_MIN_PROFIT")]
+    pub min_profit: Option<u64>,
+    //持续订阅账户更新，而不是搜索一次就退出
+    #[arg(long, env = "ARB_STREAM")]
+    pub stream: bool,
+    #[arg(long, env = "ARB_SOURCE")]
+    pub source: Option<SourceKind>,
+    #[arg(long, env = "ARB_GEYSER_ENDPOINT")]
+    pub geyser_endpoint: Option<String>,
+    #[arg(long, env = "ARB_GEYSER_X_TOKEN", hide_env_values = true)]
+    pub geyser_x_token: Option<String>,
+    //把拉到的账户写进快照文件
+    #[arg(long, env = "ARB_RECORD")]
+    pub record: Option<PathBuf>,
+    //从快照文件回放，不访问 rpc
+    #[arg(long, env = "ARB_REPLAY", conflicts_with = "record")]
+    pub replay: Option<PathBuf>,
+    //找到的机会构建交易并模拟
+    #[arg(long, env = "ARB_SIMULATE")]
+    pub simulate: bool,
+    #[arg(long, env = "ARB_KEYPAIR")]
+    pub keypair: Option<PathBuf>,
+    //模拟通过的交易用哪种方式发送
+    #[arg(long, env = "ARB_EXECUTOR")]
+    pub executor: Option<ExecutorKind>,
+    //prometheus /metrics 的监听地址
+    #[arg(long, env = "ARB_METRICS_LISTEN")]
+    pub metrics_listen: Option<String>,
+    //EnvFilter 语法，比如 info,arbitrage::orca_pool=debug
+    #[arg(long, env = "ARB_LOG")]
+    pub log: Option<String>,
+    #[arg(long, env = "ARB_LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub rpc: RpcConfig,
+    pub registry: Vec<RegistrySource>,
+    pub venues: Vec<PoolType>,
+    pub search: SearchConfig,
+    pub stream: StreamConfig,
+    pub output: OutputConfig,
+    pub journal: JournalConfig,
+    pub metrics: MetricsConfig,
+    pub log: LogConfig,
+    pub snapshot: SnapshotConfig,
+    pub backtest: BacktestConfig,
+    pub execution: ExecutionConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RpcConfig {
+    //带 api key，不要写进代码，用 ARB_RPC_URL
+    pub url: String,
+    pub ws_url: Option<String>,
+    pub commitment: CommitmentLevel,
+    pub batch_size: usize,
+    //额外的节点，和 url 一起按权重轮询
+    pub endpoints: Vec<EndpointConfig>,
+    //一次请求失败后换节点重试的次数
+    pub max_retries: usize,
+    //换节点重试仍然失败时，整个 batch 等一会再拉的次数
+    pub batch_retries: usize,
+    //节点失败后暂停使用的时间，连续失败翻倍
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointConfig {
+    #[serde(default)]
+    pub url: Option<String>,
+    //带 api key 的 url 从这个环境变量读
+    #[serde(default)]
+    pub url_env: Option<String>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    //每秒请求数，不填不限
+    #[serde(default)]
+    pub rate_limit: Option<f64>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistrySource {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub venue: Option<PoolType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub max_hops: usize,
+    #[serde(with = "serde_pubkey::vec")]
+    pub base_mints: Vec<Pubkey>,
+    pub init_balance: u64,
+    pub min_profit: u64,
+    //快照中一条路径允许的最大 slot 差
+    pub max_slot_spread: u64,
+    pub max_refetch_rounds: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamConfig {
+    pub enabled: bool,
+    pub source: SourceKind,
+    pub geyser_endpoint: Option<String>,
+    //和 rpc url 一样不要写进配置文件，用 ARB_GEYSER_X_TOKEN
+    pub geyser_x_token: Option<String>,
+    pub poll_interval_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Websocket,
+    Geyser,
+    Polling,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    pub record: Option<PathBuf>,
+    pub replay: Option<PathBuf>,
+    //回放时只用这个 slot（包含）之前的记录
+    pub replay_until_slot: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BacktestConfig {
+    //发现机会到交易落地之间的 slot 数
+    pub latency_slots: u64,
+    //交易被打包的概率
+    pub inclusion_prob: f64,
+    //每笔交易的固定成本（手续费、tip），按 base mint 的最小单位
+    pub tx_cost: u64,
+    pub seed: u64,
+    //为空时从快照里所有账户都出现过的 slot 开始
+    pub start_slot: Option<u64>,
+    pub end_slot: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExecutionConfig {
+    pub simulate: bool,
+    //钱包 keypair 文件，交易的 payer 和各 token 账户的 owner
+    pub keypair: Option<PathBuf>,
+    pub fees: FeeConfig,
+    pub executor: ExecutorKind,
+    //提交后超过这个时间还查不到结果就放弃跟踪
+    pub confirm_timeout_ms: u64,
+    pub jito: JitoConfig,
+    pub paper: PaperConfig,
+    pub lookup_tables: LookupTableConfig,
+    pub flash_loan: FlashLoanConfig,
+    pub profit_guard: ProfitGuardConfig,
+    pub wallet: WalletConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WalletConfig {
+    //每隔多久刷新余额并做下面的维护
+    pub interval_ms: u64,
+    //给 base mint 出发的路径上的 mint 补建 ATA，每个付一笔租金；没有 ATA 的路径模拟一定失败
+    pub create_accounts: bool,
+    //WSOL 低于 wsol_min 时补到 wsol_target，0 表示不补；SOL 至少留 sol_reserve 付手续费
+    pub wsol_min: u64,
+    pub wsol_target: u64,
+    pub sol_reserve: u64,
+    //把非 base mint 的余额换回 base mint，报价低于 sweep_min_out 的不换
+    pub sweep: bool,
+    pub sweep_min_out: u64,
+    pub sweep_slippage_bps: u64,
+}
+
+//programs/profit-guard 部署后的程序，交易首尾检查起始 token 账户的余额
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfitGuardConfig {
+    pub enabled: bool,
+    #[serde(with = "serde_pubkey")]
+    pub program_id: Pubkey,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlashLoanConfig {
+    pub enabled: bool,
+    #[serde(with = "serde_pubkey")]
+    pub program_id: Pubkey,
+    //每个 base mint 一个 reserve，没有 reserve 的 mint 仍然用钱包里的余额
+    #[serde(with = "serde_pubkey::vec")]
+    pub reserves: Vec<Pubkey>,
+    //借款数量，作为搜索的 init_balance，不填用 search.init_balance
+    pub borrow_amount: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LookupTableConfig {
+    //已有的 lookup table
+    #[serde(with = "serde_pubkey::vec")]
+    pub addresses: Vec<Pubkey>,
+    //启动时把池子的静态账户补进 keypair 自己的表，表满了新建（需要付租金）
+    pub manage: bool,
+    //新建的表记在这里，启动时和 addresses 一起加载；不设置时 manage 只 extend 已有的表
+    pub state_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutorKind {
+    //只模拟不发送
+    None,
+    Rpc,
+    Jito,
+    //不发送，按之后的报价或模拟结果记虚拟成交
+    Paper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaperFill {
+    //fill_delay_ms 之后用池子的最新状态沿路径重新报价，只能用在流模式
+    Requote,
+    //直接用模拟出的余额变化
+    Simulation,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaperConfig {
+    pub fill: PaperFill,
+    pub fill_delay_ms: u64,
+    //虚拟账本的初始 SOL，付手续费用；各 base mint 的初始余额是搜索的 init_balance
+    pub sol_balance: u64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            fill: PaperFill::Requote,
+            fill_delay_ms: 400,
+            sol_balance: 1_000_000_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JitoConfig {
+    pub block_engine_url: String,
+    //为空时启动时用 getTipAccounts 获取
+    #[serde(with = "serde_pubkey::vec")]
+    pub tip_accounts: Vec<Pubkey>,
+    //tip 占模拟净利润的百分比，限制在 [min_tip, max_tip]，lamports
+    pub tip_pct: u64,
+    pub min_tip: u64,
+    pub max_tip: u64,
+    pub timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeConfig {
+    //每个签名的手续费，lamports
+    pub base_fee: u64,
+    //搜索时预估的优先费，lamports，和 base_fee 一起从利润里扣
+    pub priority_fee_estimate: u64,
+    //CU 上限 = 模拟消耗 * (100 + cu_margin_pct) / 100
+    pub cu_margin_pct: u64,
+    //取近期优先费的分位数
+    pub priority_fee_percentile: f64,
+    //优先费最多占预期利润的百分比
+    pub max_priority_fee_pct: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputConfig {
+    pub sinks: Vec<OutputSink>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputSink {
+    Stdout,
+    File { path: PathBuf },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    //运行时可以通过 metrics 端口的 /log 修改
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".to_string(),
+            format: LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    //比如 127.0.0.1:9100，为空时不启动
+    pub listen: Option<String>,
+    //GET/PUT /log 的管理接口，没有鉴权，只能监听 loopback 地址，为空时不启动
+    pub admin_listen: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalFormat {
+    Jsonl,
+    Csv,
+    //需要 parquet feature
+    Parquet,
+}
+
+//每个机会一行，离线分析用
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JournalConfig {
+    //为空时不记录
+    pub dir: Option<PathBuf>,
+    pub formats: Vec<JournalFormat>,
+    pub prefix: String,
+    //每隔多久换新文件
+    pub rotate_secs: u64,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            formats: vec![JournalFormat::Jsonl],
+            prefix: "opportunities".to_string(),
+            rotate_secs: 3600,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            rpc: RpcConfig::default(),
+            registry: vec![],
+            venues: PoolType::compiled(),
+            search: SearchConfig::default(),
+            stream: StreamConfig::default(),
+            output: OutputConfig::default(),
+            journal: JournalConfig::default(),
+            metrics: MetricsConfig::default(),
+            log: LogConfig::default(),
+            snapshot: SnapshotConfig::default(),
+            backtest: BacktestConfig::default(),
+            execution: ExecutionConfig::default(),
+        }
+    }
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            ws_url: None,
+            commitment: CommitmentLevel::Processed,
+            batch_size: 100,
+            endpoints: vec![],
+            max_retries: 3,
+            batch_retries: 2,
+            backoff_ms: 500,
+            max_backoff_ms: 30_000,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_hops: 3,
+            base_mints: vec![Pubkey::from_str(WSOL_MINT).unwrap()],
+            init_balance: 500_000000,
+            min_profit: 500_000,
+            max_slot_spread: 2,
+            max_refetch_rounds: 3,
+        }
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            source: SourceKind::Websocket,
+            geyser_endpoint: None,
+            geyser_x_token: None,
+            poll_interval_ms: 400,
+        }
+    }
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            latency_slots: 1,
+            inclusion_prob: 0.5,
+            tx_cost: 5_000,
+            seed: 0,
+            start_slot: None,
+            end_slot: None,
+        }
+    }
+}
+
+impl Default for ExecutionConfig {
+    fn default() -> Self {
+        Self {
+            simulate: false,
+            keypair: None,
+            fees: FeeConfig::default(),
+            executor: ExecutorKind::None,
+            confirm_timeout_ms: 30_000,
+            jito: JitoConfig::default(),
+            paper: PaperConfig::default(),
+            lookup_tables: LookupTableConfig::default(),
+            flash_loan: FlashLoanConfig::default(),
+            profit_guard: ProfitGuardConfig::default(),
+            wallet: WalletConfig::default(),
+        }
+    }
+}
+
+impl ExecutionConfig {
+    //搜索时从利润里扣的 lamports，jito 的 tip 交易还要再付一笔签名费
+    pub fn tx_fee(&self) -> u64 {
+        match self.executor {
+            ExecutorKind::Jito => self.fees.estimate() + self.fees.base_fee,
+            _ => self.fees.estimate(),
+        }
+    }
+}
+
+impl Default for WalletConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 60_000,
+            create_accounts: false,
+            wsol_min: 0,
+            wsol_target: 0,
+            sol_reserve: 50_000_000,
+            sweep: false,
+            sweep_min_out: 0,
+            sweep_slippage_bps: 100,
+        }
+    }
+}
+
+impl Default for FlashLoanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            program_id: Pubkey::from_str(SOLEND_PROGRAM_ID).unwrap(),
+            reserves: vec![],
+            borrow_amount: None,
+        }
+    }
+}
+
+impl Default for JitoConfig {
+    fn default() -> Self {
+        Self {
+            block_engine_url: "https://mainnet.block-engine.jito.wtf".to_string(),
+            tip_accounts: vec![],
+            tip_pct: 50,
+            min_tip: 1_000,
+            max_tip: 10_000_000,
+            timeout_ms: 5_000,
+        }
+    }
+}
+
+impl Default for FeeConfig {
+    fn default() -> Self {
+        Self {
+            base_fee: 5_000,
+            priority_fee_estimate: 0,
+            cu_margin_pct: 10,
+            priority_fee_percentile: 0.75,
+            max_priority_fee_pct: 30,
+        }
+    }
+}
+
+impl FeeConfig {
+    pub fn estimate(&self) -> u64 {
+        self.base_fee + self.priority_fee_estimate
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            sinks: vec![OutputSink::Stdout],
+        }
+    }
+}
+
+impl OutputConfig {
+    pub fn open_sinks(&self) -> Result<Vec<Box<dyn Write>>, Box<dyn Error>> {
+        self.sinks
+            .iter()
+            .map(|sink| -> Result<Box<dyn Write>, Box<dyn Error>> {
+                match sink {
+                    OutputSink::Stdout => Ok(Box::new(std::io::stdout())),
+                    OutputSink::File { path } => Ok(Box::new(BufWriter::new(File::create(path)?))),
+                }
+            })
+            .collect()
+    }
+}
+
+//(url, weight, rate_limit)
+pub type EndpointSpec = (String, u32, Option<f64>);
+
+impl RpcConfig {
+    //url 不为空时作为权重 1 的第一个节点
+    pub fn endpoints(&self) -> Result<Vec<EndpointSpec>, Box<dyn Error>> {
+        let mut endpoints = vec![];
+        if !self.url.is_empty() {
+            endpoints.push((self.url.clone(), 1, None));
+        }
+        for endpoint in &self.endpoints {
+            let url = match (&endpoint.url, &endpoint.url_env) {
+                (Some(url), _) => url.clone(),
+                (None, Some(var)) => std::env::var(var)
+                    .map_err(|_| format!("rpc endpoint env {} not set", var))?,
+                (None, None) => return Err("rpc endpoint needs url or url_env".into()),
+            };
+            if endpoint.rate_limit.is_some_and(|rate| rate <= 0.0) {
+                return Err(format!("rpc endpoint rate_limit must be positive, got {:?}", endpoint.rate_limit).into());
+            }
+            endpoints.push((url, endpoint.weight, endpoint.rate_limit));
+        }
+        Ok(endpoints)
+    }
+}
+
+impl Config {
+    pub fn load(cli: &Cli) -> Result<Self, Box<dyn Error>> {
+        let mut config = match &cli.config {
+            Some(path) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|e| format!("read config {}: {}", path.display(), e))?;
+                toml::from_str(&text)?
+            }
+            None => Config::default(),
+        };
+
+        if let Some(url) = &cli.rpc_url {
+            config.rpc.url = url.clone();
+        }
+        if let Some(ws_url) = &cli.ws_url {
+            config.rpc.ws_url = Some(ws_url.clone());
+        }
+        if let Some(commitment) = cli.commitment {
+            config.rpc.commitment = commitment;
+        }
+        if let Some(batch_size) = cli.batch_size {
+            config.rpc.batch_size = batch_size;
+        }
+        if let Some(venues) = &cli.venues {
+            config.venues = venues.clone();
+        }
+        if let Some(max_hops) = cli.max_hops {
+            config.search.max_hops = max_hops;
+        }
+        if let Some(base_mints) = &cli.base_mints {
+            config.search.base_mints = base_mints.clone();
+        }
+        if let Some(init_balance) = cli.init_balance {
+            config.search.init_balance = init_balance;
+        }
+        if let Some(min_profit) = cli.min_profit {
+            config.search.min_profit = min_profit;
+        }
+        if cli.stream {
+            config.stream.enabled = true;
+        }
+        if let Some(source) = cli.source {
+            config.stream.source = source;
+        }
+        if let Some(endpoint) = &cli.geyser_endpoint {
+            config.stream.geyser_endpoint = Some(endpoint.clone());
+        }
+        if let Some(x_token) = &cli.geyser_x_token {
+            config.stream.geyser_x_token = Some(x_token.clone());
+        }
+        if let Some(path) = &cli.record {
+            config.snapshot.record = Some(path.clone());
+        }
+        if let Some(path) = &cli.replay {
+            config.snapshot.replay = Some(path.clone());
+        }
+        if cli.simulate {
+            config.execution.simulate = true;
+        }
+        if let Some(path) = &cli.keypair {
+            config.execution.keypair = Some(path.clone());
+        }
+        if let Some(executor) = cli.executor {
+            config.execution.executor = executor;
+        }
+        if let Some(listen) = &cli.metrics_listen {
+            config.metrics.listen = Some(listen.clone());
+        }
+        if let Some(filter) = &cli.log {
+            config.log.filter = filter.clone();
+        }
+        if let Some(format) = cli.log_format {
+            config.log.format = format;
+        }
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.snapshot.replay.is_some() {
+            if self.snapshot.record.is_some() {
+                return Err("snapshot.record and snapshot.replay can't be used together".into());
+            }
+            if self.stream.enabled {
+                return Err("snapshot replay doesn't support stream mode".into());
+            }
+            if self.execution.simulate {
+                return Err("snapshot replay doesn't support simulation".into());
+            }
+        } else if self.rpc.endpoints()?.is_empty() {
+            return Err("rpc url not set, use rpc.url / rpc.endpoints in config, ARB_RPC_URL or --rpc-url".into());
+        }
+        if self.rpc.batch_size == 0 || self.rpc.batch_size > 100 {
+            return Err(format!("rpc.batch_size must be in 1..=100, got {}", self.rpc.batch_size).into());
+        }
+        for venue in &self.venues {
+            if !venue.is_compiled() {
+                return Err(format!("venue {} is not compiled in, rebuild with feature \"{}\"", venue, venue.feature()).into());
+            }
+        }
+        if self.search.max_hops < 2 {
+            return Err(format!("search.max_hops must be at least 2, got {}", self.search.max_hops).into());
+        }
+        if !(0.0..=1.0).contains(&self.execution.fees.priority_fee_percentile) {
+            return Err(format!("execution.fees.priority_fee_percentile must be in 0..=1, got {}", self.execution.fees.priority_fee_percentile).into());
+        }
+        if self.execution.fees.max_priority_fee_pct > 100 {
+            return Err(format!("execution.fees.max_priority_fee_pct must be at most 100, got {}", self.execution.fees.max_priority_fee_pct).into());
+        }
+        if !(0.0..=1.0).contains(&self.backtest.inclusion_prob) {
+            return Err(format!("backtest.inclusion_prob must be in 0..=1, got {}", self.backtest.inclusion_prob).into());
+        }
+        if self.execution.simulate && self.execution.keypair.is_none() {
+            return Err("simulation needs execution.keypair, ARB_KEYPAIR or --keypair".into());
+        }
+        if self.execution.executor != ExecutorKind::None && !self.execution.simulate {
+            return Err("execution.executor only sends simulated transactions, enable execution.simulate".into());
+        }
+        //单次运行池子不会更新，重新报价和原来的报价一模一样
+        if self.execution.executor == ExecutorKind::Paper && self.execution.paper.fill == PaperFill::Requote && !self.stream.enabled {
+            return Err("execution.paper.fill = \"requote\" needs stream mode, pools never update in one-shot mode; use \"simulation\"".into());
+        }
+        if self.execution.flash_loan.enabled && self.execution.flash_loan.reserves.is_empty() {
+            return Err("execution.flash_loan is enabled but has no reserves".into());
+        }
+        if self.execution.profit_guard.enabled && self.execution.profit_guard.program_id == Pubkey::default() {
+            return Err("execution.profit_guard is enabled but has no program_id".into());
+        }
+        if self.journal.formats.contains(&JournalFormat::Parquet) && !cfg!(feature = "parquet") {
+            return Err("journal format parquet is not compiled in, rebuild with feature \"parquet\"".into());
+        }
+        if self.journal.rotate_secs == 0 {
+            return Err("journal.rotate_secs must be positive".into());
+        }
+        let wallet = &self.execution.wallet;
+        if wallet.wsol_target < wallet.wsol_min || wallet.sweep_slippage_bps > 10_000 {
+            return Err(format!("execution.wallet: wsol_target must be at least wsol_min and sweep_slippage_bps at most 10000, got {} / {} / {}",
+                wallet.wsol_target, wallet.wsol_min, wallet.sweep_slippage_bps).into());
+        }
+        let jito = &self.execution.jito;
+        if jito.tip_pct > 100 || jito.min_tip > jito.max_tip {
+            return Err(format!("execution.jito: tip_pct must be at most 100 and min_tip at most max_tip, got {} / {} / {}",
+                jito.tip_pct, jito.min_tip, jito.max_tip).into());
+        }
+        if self.search.base_mints.is_empty() {
+            return Err("search.base_mints is empty".into());
+        }
+        if let Some(listen) = &self.metrics.admin_listen {
+            loopback_addr(listen).map_err(|e| format!("metrics.admin_listen: {}", e))?;
+        }
+        //手续费、优先费上限和 tip 都是 lamports，只有 WSOL 起点的利润能直接和它们比较
+        if self.execution.simulate {
+            if let Some(mint) = self.search.base_mints.iter().find(|mint| **mint != spl_token::native_mint::ID) {
+                return Err(format!("execution only supports WSOL base mints since fees are paid in SOL, got {}", mint).into());
+            }
+        }
+        if self.stream.enabled {
+            match self.stream.source {
+                SourceKind::Websocket if self.rpc.ws_url.is_none() => {
+                    return Err("websocket source needs rpc.ws_url, ARB_WS_URL or --ws-url".into());
+                }
+                SourceKind::Geyser if self.stream.geyser_endpoint.is_none() => {
+                    return Err("geyser source needs stream.geyser_endpoint or --geyser-endpoint".into());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    //启用 flash loan 时按借款数量搜索
+    pub fn init_balance(&self) -> u64 {
+        match &self.execution.flash_loan {
+            flash_loan if flash_loan.enabled => flash_loan.borrow_amount.unwrap_or(self.search.init_balance),
+            _ => self.search.init_balance,
+        }
+    }
+
+    pub fn commitment(&self) -> CommitmentConfig {
+        CommitmentConfig { commitment: self.rpc.commitment }
+    }
+
+    pub fn load_registry(&self) -> Result<Registry, Box<dyn Error>> {
+        Registry::load_all(
+            &self.registry
+                .iter()
+                .map(|source| (source.path.clone(), source.venue))
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    //未启用的 venue 返回空
+    pub fn pairs_for(&self, registry: &Registry, venue: PoolType) -> Vec<PairData> {
+        if self.venues.contains(&venue) {
+            registry.by_venue(venue)
+        } else {
+            vec![]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid() -> Config {
+        let mut config = Config::default();
+        config.rpc.url = "http://127.0.0.1:8899".to_string();
+        config
+    }
+
+    fn rejection(config: &Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    //环境变量是整个进程共享的，只在这一个测试里设置，用到的变量别的测试都不碰
+    #[test]
+    fn cli_over_env_over_toml_over_defaults() {
+        let mut file = tempfile::Builder::new().suffix(".toml").tempfile().unwrap();
+        writeln!(file, "[rpc]\nurl = \"http://toml:8899\"\n\n[search]\nmax_hops = 4\nmin_profit = 10\ninit_balance = 5000").unwrap();
+        let path = file.path().to_str().unwrap();
+
+        std::env::set_var("ARB_MAX_HOPS", "5");
+        std::env::set_var("ARB_MIN_PROFIT", "20");
+        let cli = Cli::try_parse_from(["arbitrage", "--config", path, "--max-hops", "6"]);
+        std::env::remove_var("ARB_MAX_HOPS");
+        std::env::remove_var("ARB_MIN_PROFIT");
+        let config = Config::load(&cli.unwrap()).unwrap();
+
+        assert_eq!(config.search.max_hops, 6);
+        assert_eq!(config.search.min_profit, 20);
+        assert_eq!(config.search.init_balance, 5000);
+        assert_eq!(config.rpc.url, "http://toml:8899");
+        assert_eq!(config.rpc.batch_size, RpcConfig::default().batch_size);
+        assert_eq!(config.search.max_slot_spread, SearchConfig::default().max_slot_spread);
+
+        //命令行也能盖过配置文件
+        let cli = Cli::try_parse_from(["arbitrage", "--config", path, "--rpc-url", "http://cli:8899", "--init-balance", "7"]).unwrap();
+        let config = Config::load(&cli).unwrap();
+        assert_eq!((config.rpc.url.as_str(), config.search.init_balance), ("http://cli:8899", 7));
+        assert_eq!(config.search.min_profit, 10);
+    }
+
+    #[test]
+    fn validate_rejects_bad_values() {
+        assert!(valid().validate().is_ok());
+
+        for inclusion_prob in [-0.1, 1.5, f64::NAN] {
+            let mut config = valid();
+            config.backtest.inclusion_prob = inclusion_prob;
+            assert!(rejection(&config).contains("backtest.inclusion_prob"));
+        }
+        let mut config = valid();
+        config.backtest.inclusion_prob = 1.0;
+        assert!(config.validate().is_ok());
+
+        let mut config = valid();
+        config.search.base_mints.clear();
+        assert!(rejection(&config).contains("search.base_mints"));
+
+        for batch_size in [0, 101] {
+            let mut config = valid();
+            config.rpc.batch_size = batch_size;
+            assert!(rejection(&config).contains("rpc.batch_size"));
+        }
+
+        let mut config = valid();
+        config.rpc.url.clear();
+        assert!(rejection(&config).contains("rpc url not set"));
+
+        //单次运行时 paper executor 不能重新报价
+        let mut config = valid();
+        config.execution.simulate = true;
+        config.execution.keypair = Some(PathBuf::from("id.json"));
+        config.execution.executor = ExecutorKind::Paper;
+        assert!(rejection(&config).contains("execution.paper.fill"));
+        config.stream.enabled = true;
+        config.rpc.ws_url = Some("ws://127.0.0.1:8900".to_string());
+        assert!(config.validate().is_ok());
+    }
+}
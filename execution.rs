@@ -0,0 +1,614 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+    hash::Hash,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, VersionedTransaction},
+    commitment_config::CommitmentConfig,
+    compute_budget::ComputeBudgetInstruction,
+    address_lookup_table::AddressLookupTableAccount,
+    packet::PACKET_DATA_SIZE,
+};
+use solana_client::rpc_config::{RpcSimulateTransactionConfig, RpcSimulateTransactionAccountsConfig};
+use solana_account_decoder::UiAccountEncoding;
+use std::{fmt, error::Error, sync::Arc};
+
+use tracing::warn;
+use crate::{
+    arb::{Arbitrager, Opportunity},
+    config::FeeConfig,
+    flash_loan::FlashLoan,
+    loader::dedup,
+    lookup_table::compile_v0,
+    pool::{token_account_amount, user_token_account},
+    rpc_pool::RpcPool,
+};
+
+//按 opportunity.amounts 逐跳给输入数量，上一跳实际输出不够时下一跳会失败，模拟时能看出来
+//中间跳不限最小输出，最后一跳至少拿回本金
+pub fn build_swap_instructions(
+    arbitrager: &Arbitrager,
+    opportunity: &Opportunity,
+    owner: &Pubkey,
+) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    let hops = opportunity.pool_path.len();
+    let mut instructions = vec![];
+    for (hop, pool_id) in opportunity.pool_path.iter().enumerate() {
+        let pool = arbitrager.pools
+            .get(pool_id)
+            .ok_or_else(|| format!("pool {} not loaded", pool_id))?
+            .borrow();
+        let a_to_b = pool.get_mints()[0] == opportunity.mint_path[hop];
+        let min_amount_out = if hop + 1 == hops { opportunity.init_balance() } else { 0 };
+        instructions.extend(pool.swap_ix(owner, a_to_b, opportunity.amounts[hop], min_amount_out)?);
+    }
+    Ok(instructions)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimulationResult {
+    pub err: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: Option<u64>,
+    //起始 mint token 账户在交易前后的余额
+    pub pre_balance: Option<u64>,
+    pub post_balance: Option<u64>,
+}
+
+impl SimulationResult {
+    pub fn realized_profit(&self) -> Option<i128> {
+        Some(self.post_balance? as i128 - self.pre_balance? as i128)
+    }
+}
+
+//rpc 的 simulateTransaction，也可以换成本地 bank / program-test
+pub trait TransactionSimulator {
+    //token_account 是需要比较前后余额的账户
+    fn simulate(&self, transaction: &VersionedTransaction, token_account: &Pubkey) -> Result<SimulationResult, Box<dyn Error>>;
+}
+
+pub struct RpcSimulator {
+    pub rpc_client: Arc<RpcPool>,
+    pub commitment: CommitmentConfig,
+}
+
+impl TransactionSimulator for RpcSimulator {
+    //模拟结果只有交易后的账户，交易前的余额单独查；模拟用 min_context_slot 钉在这次查询的 slot 之后，
+    //避免换到落后的节点上，在前一笔交易落地之前的状态上模拟
+    fn simulate(&self, transaction: &VersionedTransaction, token_account: &Pubkey) -> Result<SimulationResult, Box<dyn Error>> {
+        let pre = self.rpc_client.call(|client| client.get_account_with_commitment(token_account, self.commitment))?;
+        let pre_balance = pre.value.and_then(|account| token_account_amount(&account.data));
+        //不验签，blockhash 用节点最新的
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(self.commitment),
+            min_context_slot: Some(pre.context.slot),
+            accounts: Some(RpcSimulateTransactionAccountsConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                addresses: vec![token_account.to_string()],
+            }),
+            ..RpcSimulateTransactionConfig::default()
+        };
+        let response = self.rpc_client.call(|client| client.simulate_transaction_with_config(transaction, config.clone()))?;
+        let value = response.value;
+        let post_balance = value.accounts
+            .and_then(|accounts| accounts.into_iter().next().flatten())
+            .and_then(|account| account.decode::<Account>())
+            .and_then(|account| token_account_amount(&account.data));
+        Ok(SimulationResult {
+            err: value.err.map(|e| e.to_string()),
+            logs: value.logs.unwrap_or_default(),
+            units_consumed: value.units_consumed,
+            pre_balance,
+            post_balance,
+        })
+    }
+}
+
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+//getRecentPrioritizationFees 最多接受的账户数
+pub const MAX_FEE_ACCOUNTS: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ComputeBudget {
+    pub unit_limit: u32,
+    //micro-lamports / CU
+    pub unit_price: u64,
+}
+
+impl ComputeBudget {
+    //放在交易最前面的指令数
+    pub const INSTRUCTION_COUNT: usize = 2;
+
+    pub fn instructions(&self) -> Vec<Instruction> {
+        vec![
+            ComputeBudgetInstruction::set_compute_unit_limit(self.unit_limit),
+            ComputeBudgetInstruction::set_compute_unit_price(self.unit_price),
+        ]
+    }
+
+    //lamports
+    pub fn priority_fee(&self) -> u64 {
+        (self.unit_limit as u128 * self.unit_price as u128 / 1_000_000) as u64
+    }
+}
+
+pub trait PriorityFeeSource {
+    //最近的 slot 里写这些账户的交易给出的优先费（micro-lamports / CU）
+    fn recent_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>, Box<dyn Error>>;
+}
+
+impl PriorityFeeSource for RpcPool {
+    fn recent_fees(&self, accounts: &[Pubkey]) -> Result<Vec<u64>, Box<dyn Error>> {
+        let fees = self.call(|client| client.get_recent_prioritization_fees(accounts))?;
+        Ok(fees.into_iter().map(|fee| fee.prioritization_fee).collect())
+    }
+}
+
+fn percentile(mut fees: Vec<u64>, p: f64) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    fees[((fees.len() - 1) as f64 * p).round() as usize]
+}
+
+//交易里可写的非签名账户，也就是各池子的状态、vault、tick / bin array 和用户的 ATA
+pub fn writable_accounts(instructions: &[Instruction]) -> Vec<Pubkey> {
+    let mut keys = dedup(instructions
+        .iter()
+        .flat_map(|ix| ix.accounts.iter())
+        .filter(|meta| meta.is_writable && !meta.is_signer)
+        .map(|meta| meta.pubkey));
+    keys.truncate(MAX_FEE_ACCOUNTS);
+    keys
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Accept { profit: u64 },
+    Failed(String),
+    //模拟成功但扣掉手续费后利润不到 min_profit
+    BelowThreshold { realized: i128 },
+}
+
+pub struct CheckedTransaction {
+    //带 compute budget 指令、按 lookup table 编译好的 v0 交易，可以直接发送
+    pub transaction: VersionedTransaction,
+    pub simulation: SimulationResult,
+    pub compute_budget: ComputeBudget,
+    //签名费 + 优先费，lamports
+    pub fee: u64,
+    pub verdict: Verdict,
+    //报价链预期的利润，和模拟结果对比用
+    pub expected_profit: u64,
+    pub opportunity: Opportunity,
+}
+
+impl CheckedTransaction {
+    pub fn is_accepted(&self) -> bool {
+        matches!(self.verdict, Verdict::Accept { .. })
+    }
+}
+
+impl fmt::Display for CheckedTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.verdict {
+            Verdict::Accept { profit } => write!(f, "simulation ok, net profit {}", profit)?,
+            Verdict::Failed(e) => write!(f, "simulation failed: {}", e)?,
+            Verdict::BelowThreshold { realized } => write!(f, "simulation below threshold, net profit {}", realized)?,
+        }
+        write!(f, ", expected {}, fee {}", self.expected_profit, self.fee)?;
+        if let Some(units) = self.simulation.units_consumed {
+            write!(f, ", cu {}/{}", units, self.compute_budget.unit_limit)?;
+        }
+        write!(f, ", cu price {}", self.compute_budget.unit_price)
+    }
+}
+
+//发送前的检查: 构建交易 -> 模拟 -> 按实际消耗设置 CU 上限和优先费 -> 扣掉手续费判断是否还有利润
+pub struct Preflight {
+    pub simulator: Box<dyn TransactionSimulator>,
+    pub fee_source: Arc<dyn PriorityFeeSource>,
+    pub payer: Arc<Keypair>,
+    pub min_profit: u64,
+    pub fees: FeeConfig,
+    //为空时所有账户都放在交易里，三跳 swap 基本会超过大小限制
+    pub lookup_tables: Vec<AddressLookupTableAccount>,
+    //起始 mint 有 reserve 时用借来的钱做这一圈
+    pub flash_loan: Option<FlashLoan>,
+    //profit guard 的程序 id，设置后交易首尾加上 Start / Check
+    pub profit_guard: Option<Pubkey>,
+}
+
+impl Preflight {
+    pub fn check(
+        &self,
+        arbitrager: &Arbitrager,
+        opportunity: &Opportunity,
+        recent_blockhash: Hash,
+    ) -> Result<CheckedTransaction, Box<dyn Error>> {
+        let owner = self.payer.pubkey();
+        let mut swaps = build_swap_instructions(arbitrager, opportunity, &owner)?;
+        let start_mint = &opportunity.mint_path[0];
+        //手续费和优先费上限按 lamports 算，利润也得是 WSOL 才能比较
+        if *start_mint != spl_token::native_mint::ID {
+            return Err(format!("start mint {} is not WSOL, fees can't be priced", start_mint).into());
+        }
+        let token_account = user_token_account(&owner, start_mint);
+        //借还的净效果是扣掉手续费，体现在起始 token 账户的余额变化里
+        if let Some(flash_loan) = self.flash_loan.as_ref().filter(|flash_loan| flash_loan.reserve(start_mint).is_some()) {
+            let borrow_index = ComputeBudget::INSTRUCTION_COUNT + self.profit_guard.is_some() as usize;
+            swaps = flash_loan.wrap(start_mint, &owner, opportunity.init_balance(), swaps, borrow_index)?;
+        }
+        //Start 在借款之前，Check 在还款之后，余额不到 start + min_profit 整笔交易回滚
+        if let Some(program_id) = &self.profit_guard {
+            swaps.insert(0, profit_guard::instruction::start(program_id, &owner, &token_account));
+            swaps.push(profit_guard::instruction::check(program_id, &owner, &token_account, self.min_profit));
+        }
+
+        //先用最大 CU 上限、零优先费模拟，拿到实际消耗
+        let probe = ComputeBudget { unit_limit: MAX_COMPUTE_UNIT_LIMIT, unit_price: 0 };
+        let simulation = self.simulator.simulate(&self.sign(&probe, &swaps, recent_blockhash)?, &token_account)?;
+        let compute_budget = match simulation.units_consumed {
+            Some(units) if simulation.err.is_none() => self.compute_budget(units, &swaps, opportunity.profit()),
+            _ => probe,
+        };
+        //签名费和优先费从 payer 的 SOL 里扣，不体现在 token 账户余额上
+        let fee = self.fees.base_fee + compute_budget.priority_fee();
+        let verdict = match (&simulation.err, simulation.realized_profit()) {
+            (Some(e), _) => Verdict::Failed(e.clone()),
+            (None, None) => Verdict::Failed(format!("token account {} not readable", token_account)),
+            (None, Some(realized)) => {
+                let net = realized - fee as i128;
+                if net > self.min_profit as i128 {
+                    Verdict::Accept { profit: net as u64 }
+                } else {
+                    Verdict::BelowThreshold { realized: net }
+                }
+            }
+        };
+        Ok(CheckedTransaction {
+            transaction: self.sign(&compute_budget, &swaps, recent_blockhash)?,
+            simulation,
+            compute_budget,
+            fee,
+            verdict,
+            expected_profit: opportunity.profit(),
+            opportunity: opportunity.clone(),
+        })
+    }
+
+    //CU 上限按模拟消耗加 margin；优先费取写同样账户的近期分位数，
+    //并且总优先费（lamports）不超过预期利润的 max_priority_fee_pct，利润按 SOL 计
+    pub fn compute_budget(&self, units_consumed: u64, swaps: &[Instruction], expected_profit: u64) -> ComputeBudget {
+        let unit_limit = (units_consumed * (100 + self.fees.cu_margin_pct) / 100)
+            .min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32;
+        let recent = match self.fee_source.recent_fees(&writable_accounts(swaps)) {
+            Ok(fees) => percentile(fees, self.fees.priority_fee_percentile),
+            Err(e) => {
+                warn!(error = %e, "get recent prioritization fees failed");
+                0
+            }
+        };
+        let max_fee = expected_profit as u128 * self.fees.max_priority_fee_pct as u128 / 100;
+        let max_price = (max_fee * 1_000_000 / unit_limit.max(1) as u128) as u64;
+        ComputeBudget {
+            unit_limit,
+            unit_price: recent.min(max_price),
+        }
+    }
+
+    //guard 账户不存在时创建，返回是否新建
+    pub fn init_profit_guard(&self, rpc_client: &RpcPool) -> Result<bool, Box<dyn Error>> {
+        let Some(program_id) = &self.profit_guard else {
+            return Ok(false);
+        };
+        let owner = self.payer.pubkey();
+        let (guard, _) = profit_guard::guard_address(program_id, &owner);
+        let existing = rpc_client.call(|client| client.get_account_with_commitment(&guard, CommitmentConfig::confirmed()))?;
+        if existing.value.is_some_and(|account| account.owner == *program_id) {
+            return Ok(false);
+        }
+        let recent_blockhash = rpc_client.call(|client| client.get_latest_blockhash())?;
+        let transaction = Transaction::new_signed_with_payer(
+            &[profit_guard::instruction::init(program_id, &owner)],
+            Some(&owner),
+            &[self.payer.as_ref()],
+            recent_blockhash,
+        );
+        rpc_client.call(|client| client.send_and_confirm_transaction(&transaction))?;
+        Ok(true)
+    }
+
+    fn sign(&self, compute_budget: &ComputeBudget, swaps: &[Instruction], recent_blockhash: Hash) -> Result<VersionedTransaction, Box<dyn Error>> {
+        let mut instructions = compute_budget.instructions();
+        instructions.extend_from_slice(swaps);
+        let transaction = compile_v0(&self.payer, &instructions, &self.lookup_tables, recent_blockhash)?;
+        let size = bincode::serialized_size(&transaction)? as usize;
+        if size > PACKET_DATA_SIZE {
+            return Err(format!("transaction is {} bytes, limit {}", size, PACKET_DATA_SIZE).into());
+        }
+        Ok(transaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        flash_loan::FlashLoanReserve,
+        pool::{PoolRef, mock::MockPool},
+        registry::PoolType,
+        rpc_pool::stub::{result, Stub},
+    };
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::{json, Value};
+    use solana_sdk::{compute_budget, instruction::AccountMeta, message::Message};
+    use std::{collections::HashMap, sync::Mutex};
+
+    const WSOL: Pubkey = spl_token::native_mint::ID;
+
+    //每次模拟都返回同样的结果
+    pub struct StubSimulator(pub SimulationResult);
+
+    impl TransactionSimulator for StubSimulator {
+        fn simulate(&self, _: &VersionedTransaction, _: &Pubkey) -> Result<SimulationResult, Box<dyn Error>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    //None 表示查询失败
+    pub struct StubFees(pub Option<Vec<u64>>);
+
+    impl PriorityFeeSource for StubFees {
+        fn recent_fees(&self, _: &[Pubkey]) -> Result<Vec<u64>, Box<dyn Error>> {
+            self.0.clone().ok_or_else(|| "stub fee source down".into())
+        }
+    }
+
+    //WSOL -> a -> b -> WSOL 三跳，第一跳是 b -> a 方向
+    pub fn triangle() -> (Arbitrager, Opportunity) {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pools: Vec<PoolRef> = [[a, WSOL], [a, b], [b, WSOL]]
+            .into_iter()
+            .map(|mints| MockPool::new(Pubkey::new_unique(), mints, [1_000_000, 1_000_000]).into_ref())
+            .collect();
+        let pool_path = pools.iter().map(|pool| pool.borrow().get_pool_id()).collect();
+        let arbitrager = Arbitrager::new(pools, 3, 0, 0, None);
+        let opportunity = Opportunity {
+            path: vec![0, 1, 2, 0],
+            mint_path: vec![WSOL, a, b, WSOL],
+            pool_path,
+            venues: vec![PoolType::RayAmm; 3],
+            amounts: vec![1_000, 2_000, 3_000, 1_100],
+            slot_range: None,
+        };
+        (arbitrager, opportunity)
+    }
+
+    pub fn flash_loan() -> FlashLoan {
+        let reserve = FlashLoanReserve {
+            key: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            liquidity_mint: WSOL,
+            liquidity_supply: Pubkey::new_unique(),
+            available_amount: 1_000_000,
+            flash_loan_fee_wad: 0,
+            fee_receiver: Pubkey::new_unique(),
+        };
+        FlashLoan {
+            program_id: Pubkey::new_unique(),
+            reserves: HashMap::from([(WSOL, reserve)]),
+        }
+    }
+
+    pub fn preflight(simulation: SimulationResult, fees: Option<Vec<u64>>) -> Preflight {
+        Preflight {
+            simulator: Box::new(StubSimulator(simulation)),
+            fee_source: Arc::new(StubFees(fees)),
+            payer: Arc::new(Keypair::new()),
+            min_profit: 10,
+            fees: FeeConfig::default(),
+            lookup_tables: vec![],
+            flash_loan: None,
+            profit_guard: None,
+        }
+    }
+
+    pub fn simulation(pre_balance: u64, post_balance: u64) -> SimulationResult {
+        SimulationResult {
+            units_consumed: Some(100_000),
+            pre_balance: Some(pre_balance),
+            post_balance: Some(post_balance),
+            ..SimulationResult::default()
+        }
+    }
+
+    //(program id, data)，没有 lookup table 时账户都在 static keys 里
+    fn decoded(transaction: &VersionedTransaction) -> Vec<(Pubkey, Vec<u8>)> {
+        let keys = transaction.message.static_account_keys();
+        transaction.message
+            .instructions()
+            .iter()
+            .map(|ix| (keys[ix.program_id_index as usize], ix.data.clone()))
+            .collect()
+    }
+
+    fn swap_data(a_to_b: bool, amount_in: u64, min_amount_out: u64) -> Vec<u8> {
+        let mut data = vec![a_to_b as u8];
+        data.extend_from_slice(&amount_in.to_le_bytes());
+        data.extend_from_slice(&min_amount_out.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn instruction_layout() {
+        let (arbitrager, opportunity) = triangle();
+        let guard_program = Pubkey::new_unique();
+        let mut preflight = preflight(simulation(1_000_000, 1_010_000), Some(vec![10, 20, 30, 40, 50]));
+        preflight.flash_loan = Some(flash_loan());
+        preflight.profit_guard = Some(guard_program);
+        let checked = preflight.check(&arbitrager, &opportunity, Hash::new_unique()).unwrap();
+
+        let owner = preflight.payer.pubkey();
+        let token_account = user_token_account(&owner, &WSOL);
+        let flash_loan = preflight.flash_loan.as_ref().unwrap();
+        let reserve = flash_loan.reserve(&WSOL).unwrap();
+        let [p0, p1, p2] = [0, 1, 2].map(|hop| opportunity.pool_path[hop]);
+        let budget = checked.compute_budget;
+        let expected = vec![
+            (compute_budget::ID, ComputeBudgetInstruction::set_compute_unit_limit(budget.unit_limit).data),
+            (compute_budget::ID, ComputeBudgetInstruction::set_compute_unit_price(budget.unit_price).data),
+            (guard_program, profit_guard::instruction::start(&guard_program, &owner, &token_account).data),
+            (flash_loan.program_id, flash_loan.borrow_ix(reserve, &owner, 1_000).data),
+            //中间跳不限最小输出，最后一跳至少拿回本金
+            (p0, swap_data(false, 1_000, 0)),
+            (p1, swap_data(true, 2_000, 0)),
+            (p2, swap_data(true, 3_000, 1_000)),
+            //借款是第 3 条指令
+            (flash_loan.program_id, flash_loan.repay_ix(reserve, &owner, 1_000, 3).data),
+            (guard_program, profit_guard::instruction::check(&guard_program, &owner, &token_account, 10).data),
+        ];
+        assert_eq!(decoded(&checked.transaction), expected);
+        assert_eq!(checked.transaction.message.static_account_keys()[0], owner);
+    }
+
+    #[test]
+    fn instruction_layout_without_guard_and_flash_loan() {
+        let (arbitrager, opportunity) = triangle();
+        let preflight = preflight(simulation(1_000_000, 1_010_000), Some(vec![]));
+        let checked = preflight.check(&arbitrager, &opportunity, Hash::new_unique()).unwrap();
+        let programs = decoded(&checked.transaction).into_iter().map(|(program_id, _)| program_id).collect::<Vec<_>>();
+        let mut expected = vec![compute_budget::ID, compute_budget::ID];
+        expected.extend(opportunity.pool_path.iter().cloned());
+        assert_eq!(programs, expected);
+    }
+
+    #[test]
+    fn simulation_verdicts() {
+        let (arbitrager, opportunity) = triangle();
+        let check = |simulation: SimulationResult| {
+            preflight(simulation, Some(vec![10, 20, 30, 40, 50])).check(&arbitrager, &opportunity, Hash::new_unique()).unwrap()
+        };
+        //CU 110_000，优先费 40 micro-lamports / CU，合计 4 lamports
+        let checked = check(simulation(1_000_000, 1_010_000));
+        assert_eq!(checked.compute_budget, ComputeBudget { unit_limit: 110_000, unit_price: 40 });
+        assert_eq!(checked.fee, 5_004);
+        assert_eq!(checked.verdict, Verdict::Accept { profit: 4_996 });
+        assert!(checked.is_accepted());
+        assert_eq!(checked.expected_profit, 100);
+
+        //扣完手续费正好是 min_profit 不算
+        assert_eq!(check(simulation(1_000_000, 1_005_014)).verdict, Verdict::BelowThreshold { realized: 10 });
+        assert_eq!(check(simulation(1_000_000, 999_000)).verdict, Verdict::BelowThreshold { realized: -6_004 });
+
+        //失败时保留探测用的 CU 上限，不设优先费
+        let failed = check(SimulationResult { err: Some("custom program error: 0x1".into()), ..simulation(1_000_000, 1_000_000) });
+        assert_eq!(failed.verdict, Verdict::Failed("custom program error: 0x1".into()));
+        assert_eq!(failed.compute_budget, ComputeBudget { unit_limit: MAX_COMPUTE_UNIT_LIMIT, unit_price: 0 });
+        assert_eq!(failed.fee, 5_000);
+
+        let unreadable = check(SimulationResult { pre_balance: None, ..simulation(1_000_000, 1_000_000) });
+        assert!(matches!(unreadable.verdict, Verdict::Failed(e) if e.contains("not readable")));
+    }
+
+    #[test]
+    fn non_wsol_start_is_rejected() {
+        let (arbitrager, mut opportunity) = triangle();
+        opportunity.mint_path.rotate_left(1);
+        opportunity.pool_path.rotate_left(1);
+        let error = preflight(simulation(0, 0), None).check(&arbitrager, &opportunity, Hash::new_unique()).err().unwrap();
+        assert!(error.to_string().contains("not WSOL"));
+    }
+
+    #[test]
+    fn percentile_selection() {
+        assert_eq!(percentile(vec![], 0.75), 0);
+        assert_eq!(percentile(vec![7], 0.75), 7);
+        //先排序，下标 (len - 1) * p 四舍五入
+        assert_eq!(percentile(vec![50, 10, 40, 20, 30], 0.0), 10);
+        assert_eq!(percentile(vec![50, 10, 40, 20, 30], 0.5), 30);
+        assert_eq!(percentile(vec![50, 10, 40, 20, 30], 0.75), 40);
+        assert_eq!(percentile(vec![50, 10, 40, 20, 30], 1.0), 50);
+        assert_eq!(percentile(vec![10, 20, 30, 40], 0.5), 30);
+    }
+
+    #[test]
+    fn compute_budget_caps_priority_fee() {
+        let swaps = [];
+        //利润够大时取分位数
+        let budget = preflight(SimulationResult::default(), Some(vec![10, 20, 30, 40, 50])).compute_budget(100_000, &swaps, 1_000_000);
+        assert_eq!(budget, ComputeBudget { unit_limit: 110_000, unit_price: 40 });
+
+        //优先费不超过利润的 30%：100 * 30% = 30 lamports，30 * 1e6 / 110_000 = 272
+        let budget = preflight(SimulationResult::default(), Some(vec![1_000])).compute_budget(100_000, &swaps, 100);
+        assert_eq!(budget.unit_price, 272);
+        assert!(budget.priority_fee() <= 30);
+        let mut capped = preflight(SimulationResult::default(), Some(vec![1_000]));
+        capped.fees.max_priority_fee_pct = 0;
+        assert_eq!(capped.compute_budget(100_000, &swaps, 100).unit_price, 0);
+
+        //没有样本或者查询失败时不加优先费
+        assert_eq!(preflight(SimulationResult::default(), Some(vec![])).compute_budget(100_000, &swaps, 100).unit_price, 0);
+        assert_eq!(preflight(SimulationResult::default(), None).compute_budget(100_000, &swaps, 100).unit_price, 0);
+
+        //CU 上限加 margin 后不超过 1.4M
+        let budget = preflight(SimulationResult::default(), None).compute_budget(1_300_000, &swaps, 100);
+        assert_eq!(budget.unit_limit, MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn writable_accounts_skip_signers_and_duplicates() {
+        let (signer, pool, vault) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = |accounts| Instruction { program_id: Pubkey::new_unique(), accounts, data: vec![] };
+        let instructions = [
+            ix(vec![AccountMeta::new(signer, true), AccountMeta::new(pool, false), AccountMeta::new_readonly(vault, false)]),
+            ix(vec![AccountMeta::new(pool, false), AccountMeta::new(vault, false)]),
+        ];
+        assert_eq!(writable_accounts(&instructions), vec![pool, vault]);
+    }
+
+    fn token_account_json(amount: u64) -> Value {
+        let mut data = vec![0; 165];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        json!({
+            "lamports": 2_039_280,
+            "data": [BASE64.encode(data), "base64"],
+            "owner": spl_token::ID.to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": 165,
+        })
+    }
+
+    #[test]
+    fn rpc_simulator_pins_context_slot() {
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+        let stub = Stub::start(move |_, request| {
+            recorded.lock().unwrap().push(request.clone());
+            let body = match request["method"].as_str().unwrap() {
+                "getAccountInfo" => json!({"context": {"slot": 500}, "value": token_account_json(1_000)}),
+                _ => json!({
+                    "context": {"slot": 502},
+                    "value": {"err": null, "logs": ["ok"], "accounts": [token_account_json(1_200)], "unitsConsumed": 1_234},
+                }),
+            };
+            (200, result(body))
+        });
+        let simulator = RpcSimulator { rpc_client: Arc::new(stub.pool()), commitment: CommitmentConfig::processed() };
+        let payer = Keypair::new();
+        let transaction = VersionedTransaction::from(Transaction::new_unsigned(Message::new(&[], Some(&payer.pubkey()))));
+        let simulation = simulator.simulate(&transaction, &Pubkey::new_unique()).unwrap();
+        assert_eq!((simulation.pre_balance, simulation.post_balance), (Some(1_000), Some(1_200)));
+        assert_eq!(simulation.realized_profit(), Some(200));
+        assert_eq!(simulation.units_consumed, Some(1_234));
+
+        let requests = requests.lock().unwrap();
+        let simulate = requests.iter().find(|request| request["method"] == "simulateTransaction").unwrap();
+        assert_eq!(simulate["params"][1]["minContextSlot"], 500);
+    }
+}
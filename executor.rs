@@ -0,0 +1,471 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature, Signer},
+    transaction::Transaction,
+    system_instruction,
+};
+use solana_client::rpc_config::RpcSendTransactionConfig;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use rand::seq::SliceRandom;
+use serde_json::{json, Value};
+use std::{
+    fmt, error::Error, str::FromStr, sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tracing::warn;
+use crate::{
+    config::JitoConfig,
+    execution::{CheckedTransaction, Verdict},
+    rpc_pool::{RpcPool, redact_url},
+};
+
+#[derive(Debug, Clone)]
+pub struct Submission {
+    //rpc 发送时是交易签名，jito 是 bundle id
+    pub id: String,
+    pub signature: Signature,
+    //给 validator / block engine 的小费，lamports
+    pub tip: u64,
+    pub submitted_at: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubmissionStatus {
+    Pending,
+    Landed { slot: u64 },
+    Failed(String),
+    //超时还没查到结果
+    Expired,
+}
+
+impl SubmissionStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            SubmissionStatus::Pending => "pending",
+            SubmissionStatus::Landed { .. } => "landed",
+            SubmissionStatus::Failed(_) => "failed",
+            SubmissionStatus::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for SubmissionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubmissionStatus::Pending => write!(f, "pending"),
+            SubmissionStatus::Landed { slot } => write!(f, "landed at slot {}", slot),
+            SubmissionStatus::Failed(e) => write!(f, "failed: {}", e),
+            SubmissionStatus::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+//只提交通过模拟的交易，状态查询不阻塞，由 SubmissionTracker 轮询
+pub trait Executor {
+    fn name(&self) -> &str;
+    fn submit(&self, checked: &CheckedTransaction, payer: &Keypair) -> Result<Submission, Box<dyn Error>>;
+    fn status(&self, submission: &Submission) -> Result<SubmissionStatus, Box<dyn Error>>;
+
+    //不上链的 executor 不看钱包余额，模拟没通过的交易也交给它自己判断
+    fn is_live(&self) -> bool {
+        true
+    }
+
+    //退出时打印的汇总
+    fn summary(&self) -> Option<String> {
+        None
+    }
+}
+
+pub fn accepted_profit(checked: &CheckedTransaction) -> Result<u64, Box<dyn Error>> {
+    match checked.verdict {
+        Verdict::Accept { profit } => Ok(profit),
+        _ => Err("transaction didn't pass simulation".into()),
+    }
+}
+
+//普通 send_transaction，已经模拟过，跳过节点的 preflight
+pub struct RpcExecutor {
+    pub rpc_client: Arc<RpcPool>,
+}
+
+impl Executor for RpcExecutor {
+    fn name(&self) -> &str {
+        "rpc"
+    }
+
+    fn submit(&self, checked: &CheckedTransaction, _payer: &Keypair) -> Result<Submission, Box<dyn Error>> {
+        accepted_profit(checked)?;
+        let config = RpcSendTransactionConfig {
+            skip_preflight: true,
+            max_retries: Some(0),
+            ..RpcSendTransactionConfig::default()
+        };
+        let signature = self.rpc_client
+            .call(|client| client.send_transaction_with_config(&checked.transaction, config))?;
+        Ok(Submission {
+            id: signature.to_string(),
+            signature,
+            tip: 0,
+            submitted_at: Instant::now(),
+        })
+    }
+
+    fn status(&self, submission: &Submission) -> Result<SubmissionStatus, Box<dyn Error>> {
+        let statuses = self.rpc_client.call(|client| client.get_signature_statuses(&[submission.signature]))?;
+        Ok(match statuses.value.into_iter().next().flatten() {
+            None => SubmissionStatus::Pending,
+            Some(status) => match status.err {
+                Some(e) => SubmissionStatus::Failed(e.to_string()),
+                None => SubmissionStatus::Landed { slot: status.slot },
+            },
+        })
+    }
+}
+
+//套利交易后面跟一笔给 tip account 的转账，作为一个 bundle 提交给 block engine，
+//bundle 要么整体上链要么都不上
+pub struct JitoExecutor {
+    http: reqwest::blocking::Client,
+    bundles_url: String,
+    tip_accounts: Vec<Pubkey>,
+    config: JitoConfig,
+    //tip 交易自己的签名费，lamports
+    signature_fee: u64,
+}
+
+impl JitoExecutor {
+    //配置里没有 tip account 时从 block engine 取
+    pub fn new(config: &JitoConfig, signature_fee: u64) -> Result<Self, Box<dyn Error>> {
+        let mut executor = Self {
+            http: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_millis(config.timeout_ms))
+                .build()?,
+            bundles_url: format!("{}/api/v1/bundles", config.block_engine_url.trim_end_matches('/')),
+            tip_accounts: config.tip_accounts.clone(),
+            config: config.clone(),
+            signature_fee,
+        };
+        if executor.tip_accounts.is_empty() {
+            executor.tip_accounts = executor.get_tip_accounts()?;
+        }
+        if executor.tip_accounts.is_empty() {
+            return Err("block engine returned no tip accounts".into());
+        }
+        Ok(executor)
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, Box<dyn Error>> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let response: Value = self.http
+            .post(&self.bundles_url)
+            .json(&body)
+            .send()
+            .map_err(|e| format!("{} {}: {}", method, redact_url(&self.bundles_url), e.without_url()))?
+            .error_for_status()
+            .map_err(|e| format!("{} {}: {}", method, redact_url(&self.bundles_url), e.without_url()))?
+            .json()?;
+        if let Some(error) = response.get("error") {
+            return Err(format!("{} failed: {}", method, error).into());
+        }
+        Ok(response["result"].clone())
+    }
+
+    pub fn get_tip_accounts(&self) -> Result<Vec<Pubkey>, Box<dyn Error>> {
+        let result = self.call("getTipAccounts", json!([]))?;
+        result
+            .as_array()
+            .ok_or("getTipAccounts: unexpected result")?
+            .iter()
+            .map(|key| -> Result<Pubkey, Box<dyn Error>> {
+                Ok(Pubkey::from_str(key.as_str().ok_or("getTipAccounts: unexpected result")?)?)
+            })
+            .collect()
+    }
+
+    //利润（WSOL 起点，lamports）的 tip_pct，限制在 [min_tip, max_tip]
+    pub fn tip(&self, profit: u64) -> u64 {
+        (profit as u128 * self.config.tip_pct as u128 / 100)
+            .clamp(self.config.min_tip as u128, self.config.max_tip as u128) as u64
+    }
+}
+
+impl Executor for JitoExecutor {
+    fn name(&self) -> &str {
+        "jito"
+    }
+
+    fn submit(&self, checked: &CheckedTransaction, payer: &Keypair) -> Result<Submission, Box<dyn Error>> {
+        let profit = accepted_profit(checked)?;
+        //tip 用 SOL 付，其它 mint 的利润没法直接换算
+        let start_mint = &checked.opportunity.mint_path[0];
+        if *start_mint != spl_token::native_mint::ID {
+            return Err(format!("jito tip is paid in SOL, start mint {} is not WSOL", start_mint).into());
+        }
+        //profit 只扣了套利交易的手续费，tip 交易的签名费也得从里面出
+        let tip = self.tip(profit);
+        if tip + self.signature_fee >= profit {
+            return Err(format!("tip {} plus fee {} would eat the whole profit {}", tip, self.signature_fee, profit).into());
+        }
+        let tip_account = self.tip_accounts.choose(&mut rand::thread_rng()).unwrap();
+        let tip_transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer.pubkey(), tip_account, tip)],
+            Some(&payer.pubkey()),
+            &[payer],
+            *checked.transaction.message.recent_blockhash(),
+        );
+        let encoded = vec![
+            BASE64.encode(bincode::serialize(&checked.transaction)?),
+            BASE64.encode(bincode::serialize(&tip_transaction)?),
+        ];
+        let result = self.call("sendBundle", json!([encoded, { "encoding": "base64" }]))?;
+        let bundle_id = result.as_str().ok_or("sendBundle: unexpected result")?.to_string();
+        Ok(Submission {
+            id: bundle_id,
+            signature: checked.transaction.signatures[0],
+            tip,
+            submitted_at: Instant::now(),
+        })
+    }
+
+    //getBundleStatuses 只返回已经上链的 bundle，查不到的算 pending，由 tracker 超时
+    fn status(&self, submission: &Submission) -> Result<SubmissionStatus, Box<dyn Error>> {
+        let result = self.call("getBundleStatuses", json!([[submission.id]]))?;
+        let Some(status) = result["value"].as_array().and_then(|value| value.first()).filter(|status| !status.is_null()) else {
+            return Ok(SubmissionStatus::Pending);
+        };
+        let slot = status["slot"].as_u64().unwrap_or_default();
+        Ok(match status.get("err") {
+            Some(err) if err.get("Ok").is_none() && !err.is_null() => SubmissionStatus::Failed(err.to_string()),
+            _ => SubmissionStatus::Landed { slot },
+        })
+    }
+}
+
+pub struct SubmissionTracker {
+    pending: Vec<Submission>,
+    timeout: Duration,
+}
+
+impl SubmissionTracker {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            pending: vec![],
+            timeout,
+        }
+    }
+
+    pub fn push(&mut self, submission: Submission) {
+        self.pending.push(submission);
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    //返回已经有结果的提交，查询失败的留到下次
+    pub fn poll(&mut self, executor: &dyn Executor) -> Vec<(Submission, SubmissionStatus)> {
+        let mut finished = vec![];
+        let mut pending = vec![];
+        for submission in std::mem::take(&mut self.pending) {
+            let status = match executor.status(&submission) {
+                Ok(status) => status,
+                Err(e) => {
+                    warn!(executor = executor.name(), submission = %submission.id, error = %e, "get submission status failed");
+                    SubmissionStatus::Pending
+                }
+            };
+            match status {
+                SubmissionStatus::Pending if submission.submitted_at.elapsed() < self.timeout => pending.push(submission),
+                SubmissionStatus::Pending => finished.push((submission, SubmissionStatus::Expired)),
+                status => finished.push((submission, status)),
+            }
+        }
+        self.pending = pending;
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{hash::Hash, transaction::VersionedTransaction, system_program};
+    use std::{sync::Mutex, thread};
+    use crate::{arb::Opportunity, execution::{ComputeBudget, SimulationResult}};
+
+    //block engine 替身：按收到的请求返回 JSON-RPC body，记下所有请求
+    struct BlockEngine {
+        url: String,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    impl BlockEngine {
+        fn start(respond: impl Fn(&Value) -> Value + Send + 'static) -> Self {
+            let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+            let requests = Arc::new(Mutex::new(vec![]));
+            let received = requests.clone();
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    assert_eq!(request.url(), "/api/v1/bundles");
+                    let body: Value = serde_json::from_reader(request.as_reader()).unwrap();
+                    let response = respond(&body);
+                    received.lock().unwrap().push(body);
+                    let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                    let _ = request.respond(tiny_http::Response::from_string(response.to_string()).with_header(content_type));
+                }
+            });
+            Self { url, requests }
+        }
+
+        fn result(result: Value) -> Self {
+            Self::start(move |_| json!({ "jsonrpc": "2.0", "id": 1, "result": result.clone() }))
+        }
+
+        fn requests(&self) -> Vec<Value> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn config(&self, tip_accounts: Vec<Pubkey>) -> JitoConfig {
+            JitoConfig {
+                block_engine_url: format!("{}/", self.url),
+                tip_accounts,
+                tip_pct: 50,
+                min_tip: 1_000,
+                max_tip: 10_000,
+                timeout_ms: 1_000,
+            }
+        }
+    }
+
+    fn tip_account() -> Pubkey {
+        Pubkey::new_from_array([7; 32])
+    }
+
+    fn checked(payer: &Keypair, start_mint: Pubkey, verdict: Verdict) -> CheckedTransaction {
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer.pubkey(), &system_program::ID, 1)],
+            Some(&payer.pubkey()),
+            &[payer],
+            Hash::new_unique(),
+        );
+        CheckedTransaction {
+            transaction: VersionedTransaction::from(transaction),
+            simulation: SimulationResult::default(),
+            compute_budget: ComputeBudget::default(),
+            fee: 5_000,
+            verdict,
+            expected_profit: 100_000,
+            opportunity: Opportunity {
+                path: vec![0, 1, 0],
+                mint_path: vec![start_mint, Pubkey::new_unique(), start_mint],
+                pool_path: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+                venues: vec![],
+                amounts: vec![1_000_000, 5, 1_100_000],
+                slot_range: None,
+            },
+        }
+    }
+
+    fn submission(id: &str) -> Submission {
+        Submission {
+            id: id.to_string(),
+            signature: Signature::default(),
+            tip: 0,
+            submitted_at: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn fetches_tip_accounts_when_not_configured() {
+        let engine = BlockEngine::result(json!([tip_account().to_string()]));
+        let executor = JitoExecutor::new(&engine.config(vec![]), 5_000).unwrap();
+        assert_eq!(executor.tip_accounts, vec![tip_account()]);
+        assert_eq!(engine.requests()[0]["method"], "getTipAccounts");
+
+        //配置了就不再请求
+        let engine = BlockEngine::result(json!([]));
+        JitoExecutor::new(&engine.config(vec![tip_account()]), 5_000).unwrap();
+        assert!(engine.requests().is_empty());
+
+        assert!(JitoExecutor::new(&engine.config(vec![]), 5_000).is_err());
+        let engine = BlockEngine::result(json!(["not a pubkey"]));
+        assert!(JitoExecutor::new(&engine.config(vec![]), 5_000).is_err());
+    }
+
+    #[test]
+    fn tip_is_clamped() {
+        let engine = BlockEngine::result(json!(null));
+        let executor = JitoExecutor::new(&engine.config(vec![tip_account()]), 5_000).unwrap();
+        assert_eq!(executor.tip(0), 1_000);
+        assert_eq!(executor.tip(10_000), 5_000);
+        assert_eq!(executor.tip(u64::MAX), 10_000);
+    }
+
+    #[test]
+    fn send_bundle_appends_tip_transaction() {
+        let engine = BlockEngine::result(json!("bundle-1"));
+        let executor = JitoExecutor::new(&engine.config(vec![tip_account()]), 5_000).unwrap();
+        let payer = Keypair::new();
+        let wsol = spl_token::native_mint::ID;
+
+        let accepted = checked(&payer, wsol, Verdict::Accept { profit: 12_000 });
+        let submission = executor.submit(&accepted, &payer).unwrap();
+        assert_eq!(submission.id, "bundle-1");
+        assert_eq!(submission.tip, 6_000);
+        assert_eq!(submission.signature, accepted.transaction.signatures[0]);
+
+        let request = &engine.requests()[0];
+        assert_eq!(request["method"], "sendBundle");
+        assert_eq!(request["params"][1]["encoding"], "base64");
+        let encoded = request["params"][0].as_array().unwrap();
+        assert_eq!(encoded.len(), 2);
+        let decode = |value: &Value| BASE64.decode(value.as_str().unwrap()).unwrap();
+        assert_eq!(decode(&encoded[0]), bincode::serialize(&accepted.transaction).unwrap());
+        let tip_transaction: Transaction = bincode::deserialize(&decode(&encoded[1])).unwrap();
+        assert_eq!(tip_transaction.message.recent_blockhash, *accepted.transaction.message.recent_blockhash());
+        let expected = system_instruction::transfer(&payer.pubkey(), &tip_account(), 6_000);
+        assert_eq!(tip_transaction.message.instructions[0].data, expected.data);
+        assert!(tip_transaction.message.account_keys.contains(&tip_account()));
+
+        //没通过模拟、tip 吃掉全部利润、起点不是 WSOL 的都不发送
+        assert!(executor.submit(&checked(&payer, wsol, Verdict::Failed("err".into())), &payer).is_err());
+        assert!(executor.submit(&checked(&payer, wsol, Verdict::Accept { profit: 1_000 }), &payer).is_err());
+        //tip 5_000 不到利润，但加上 tip 交易的签名费就不剩了
+        assert!(executor.submit(&checked(&payer, wsol, Verdict::Accept { profit: 10_000 }), &payer).is_err());
+        assert!(executor.submit(&checked(&payer, Pubkey::new_unique(), Verdict::Accept { profit: 12_000 }), &payer).is_err());
+        assert_eq!(engine.requests().len(), 1);
+    }
+
+    #[test]
+    fn bundle_statuses() {
+        let engine = BlockEngine::start(|request| {
+            let id = request["params"][0][0].as_str().unwrap();
+            let value = match id {
+                "unknown" => json!([null]),
+                "empty" => json!([]),
+                "landed" => json!([{ "bundle_id": id, "slot": 42, "confirmation_status": "confirmed", "err": { "Ok": null } }]),
+                "reverted" => json!([{ "bundle_id": id, "slot": 43, "err": { "Err": { "InstructionError": [0, { "Custom": 1 }] } } }]),
+                _ => return json!({ "jsonrpc": "2.0", "id": 1, "error": { "code": -32602, "message": "bad bundle id" } }),
+            };
+            json!({ "jsonrpc": "2.0", "id": 1, "result": { "context": { "slot": 50 }, "value": value } })
+        });
+        let executor = JitoExecutor::new(&engine.config(vec![tip_account()]), 5_000).unwrap();
+        assert_eq!(executor.status(&submission("unknown")).unwrap(), SubmissionStatus::Pending);
+        assert_eq!(executor.status(&submission("empty")).unwrap(), SubmissionStatus::Pending);
+        assert_eq!(executor.status(&submission("landed")).unwrap(), SubmissionStatus::Landed { slot: 42 });
+        assert!(matches!(executor.status(&submission("reverted")).unwrap(), SubmissionStatus::Failed(e) if e.contains("InstructionError")));
+        assert!(executor.status(&submission("bad")).is_err());
+        assert_eq!(engine.requests()[0]["method"], "getBundleStatuses");
+    }
+}
@@ -0,0 +1,306 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+    commitment_config::CommitmentConfig,
+};
+use solana_client::{
+    rpc_config::RpcAccountInfoConfig,
+    client_error::Result as ClientResult,
+};
+use solana_account_decoder::UiAccountEncoding;
+use rayon::prelude::*;
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
+use crate::{
+    rpc_pool::{RpcPool, FailureKind, classify},
+    snapshot::{Recorder, SnapshotAccount, SnapshotReader, latest_accounts},
+};
+
+const BATCH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct FetchedAccount {
+    pub pubkey: Pubkey,
+    pub account: Option<Account>,
+    //get_multiple_accounts 返回的 context slot
+    pub slot: u64,
+}
+
+fn fetch_batch(
+    rpc_client: &RpcPool,
+    keys: &[Pubkey],
+    commitment: CommitmentConfig,
+    min_context_slot: Option<u64>,
+) -> ClientResult<Vec<FetchedAccount>> {
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(commitment),
+        min_context_slot,
+        ..RpcAccountInfoConfig::default()
+    };
+    let response = rpc_client.call(|client| client.get_multiple_accounts_with_config(keys, config.clone()))?;
+    let slot = response.context.slot;
+    Ok(keys
+        .iter()
+        .zip(response.value)
+        .map(|(pubkey, account)| FetchedAccount {
+            pubkey: *pubkey,
+            account,
+            slot,
+        })
+        .collect())
+}
+
+//每个 batch 先由 RpcPool 换节点重试，再整体重试 batch_retries 次，仍然失败的记到 failed 里，不影响其他 batch
+#[derive(Debug, Default)]
+pub struct BatchFetch {
+    pub accounts: HashMap<Pubkey, FetchedAccount>,
+    pub failed: HashMap<Pubkey, String>,
+}
+
+impl BatchFetch {
+    pub fn len(&self) -> usize {
+        self.accounts.len() + self.failed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    //账户存在时返回账户和 slot
+    pub fn get(&self, key: &Pubkey) -> Option<(&Account, u64)> {
+        let fetched = self.accounts.get(key)?;
+        Some((fetched.account.as_ref()?, fetched.slot))
+    }
+}
+
+//loader / 重新拉取 / 账户流补拉都通过这个接口，可以换成 rpc、快照回放或者边拉边录
+pub trait AccountFetcher: Send + Sync {
+    fn fetch_at(&self, keys: &[Pubkey], min_context_slot: Option<u64>) -> BatchFetch;
+
+    fn fetch(&self, keys: &[Pubkey]) -> BatchFetch {
+        self.fetch_at(keys, None)
+    }
+}
+
+//各个 venue loader 共用的 rpc 分批拉取
+#[derive(Clone)]
+pub struct BatchFetcher {
+    pub rpc_client: Arc<RpcPool>,
+    pub commitment: CommitmentConfig,
+    pub batch_size: usize,
+    pub batch_retries: usize,
+}
+
+impl AccountFetcher for BatchFetcher {
+    fn fetch_at(&self, keys: &[Pubkey], min_context_slot: Option<u64>) -> BatchFetch {
+        fetch_accounts_with_retry(&self.rpc_client, keys, self.commitment, self.batch_size, min_context_slot, self.batch_retries)
+    }
+}
+
+//从快照回放，不访问网络；快照里没有的账户当作不存在
+pub struct ReplayFetcher {
+    accounts: HashMap<Pubkey, SnapshotAccount>,
+}
+
+impl ReplayFetcher {
+    pub fn new(accounts: HashMap<Pubkey, SnapshotAccount>) -> Self {
+        Self { accounts }
+    }
+
+    //until_slot 之后的记录忽略
+    pub fn open(path: impl AsRef<std::path::Path>, until_slot: Option<u64>) -> Result<Self, Box<dyn std::error::Error>> {
+        let records = SnapshotReader::open(path)?.read_all()?;
+        Ok(Self::new(latest_accounts(records, until_slot)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn max_slot(&self) -> Option<u64> {
+        self.accounts.values().map(|record| record.slot).max()
+    }
+}
+
+impl AccountFetcher for ReplayFetcher {
+    fn fetch_at(&self, keys: &[Pubkey], _min_context_slot: Option<u64>) -> BatchFetch {
+        let slot = self.max_slot().unwrap_or(0);
+        let mut fetch = BatchFetch::default();
+        for key in keys {
+            let fetched = match self.accounts.get(key) {
+                Some(record) => FetchedAccount {
+                    pubkey: *key,
+                    account: Some(record.to_account()),
+                    slot: record.slot,
+                },
+                None => FetchedAccount {
+                    pubkey: *key,
+                    account: None,
+                    slot,
+                },
+            };
+            fetch.accounts.insert(*key, fetched);
+        }
+        fetch
+    }
+}
+
+//把拿到的每个账户写进快照
+pub struct RecordingFetcher {
+    inner: Arc<dyn AccountFetcher>,
+    recorder: Recorder,
+}
+
+impl RecordingFetcher {
+    pub fn new(inner: Arc<dyn AccountFetcher>, recorder: Recorder) -> Self {
+        Self { inner, recorder }
+    }
+
+    pub fn recorder(&self) -> &Recorder {
+        &self.recorder
+    }
+}
+
+impl AccountFetcher for RecordingFetcher {
+    fn fetch_at(&self, keys: &[Pubkey], min_context_slot: Option<u64>) -> BatchFetch {
+        let fetch = self.inner.fetch_at(keys, min_context_slot);
+        for fetched in fetch.accounts.values() {
+            if let Some(account) = &fetched.account {
+                self.recorder.record(fetched.pubkey, account, fetched.slot);
+            }
+        }
+        fetch
+    }
+}
+
+//RpcPool 的重试用完时（比如所有节点都在退避）等一会再拉整个 batch，Fatal 错误不重试
+pub fn fetch_accounts_with_retry(
+    rpc_client: &RpcPool,
+    keys: &[Pubkey],
+    commitment: CommitmentConfig,
+    batch_size: usize,
+    min_context_slot: Option<u64>,
+    batch_retries: usize,
+) -> BatchFetch {
+    let results = keys
+        .par_chunks(batch_size)
+        .map(|chunk| {
+            let mut attempt = 0;
+            loop {
+                match fetch_batch(rpc_client, chunk, commitment, min_context_slot) {
+                    Ok(accounts) => break Ok(accounts),
+                    Err(e) if attempt < batch_retries && classify(&e) != FailureKind::Fatal => {
+                        attempt += 1;
+                        thread::sleep(BATCH_RETRY_DELAY * (1 << attempt));
+                    }
+                    Err(e) => break Err((chunk, e.to_string())),
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut fetch = BatchFetch::default();
+    for result in results {
+        match result {
+            Ok(accounts) => {
+                fetch.accounts.extend(accounts.into_iter().map(|fetched| (fetched.pubkey, fetched)));
+            }
+            Err((chunk, e)) => {
+                fetch.failed.extend(chunk.iter().map(|key| (*key, e.clone())));
+            }
+        }
+    }
+    fetch
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_pool::stub::{result, Stub};
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::{json, Value};
+
+    //getMultipleAccounts 的回复：key 在 existing 里时返回数据是 key 本身的账户，否则 null
+    fn accounts_response(body: &Value, slot: u64, existing: &[Pubkey]) -> String {
+        let value = body["params"][0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|key| {
+                let key = key.as_str().unwrap().parse::<Pubkey>().unwrap();
+                if !existing.contains(&key) {
+                    return Value::Null;
+                }
+                json!({
+                    "lamports": 1,
+                    "data": [BASE64.encode(key.as_ref()), "base64"],
+                    "owner": Pubkey::default().to_string(),
+                    "executable": false,
+                    "rentEpoch": 0,
+                    "space": 32,
+                })
+            })
+            .collect::<Vec<_>>();
+        result(json!({ "context": { "slot": slot }, "value": value }))
+    }
+
+    fn keys(count: usize) -> Vec<Pubkey> {
+        (0..count).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    #[test]
+    fn fetches_in_batches() {
+        let keys = keys(5);
+        let existing = keys[..4].to_vec();
+        let stub = Stub::start(move |hit, body| (200, accounts_response(body, 100 + hit as u64, &existing)));
+        let fetch = fetch_accounts_with_retry(&stub.pool(), &keys, CommitmentConfig::confirmed(), 2, None, 0);
+        assert_eq!(stub.hits(), 3);
+        assert_eq!(fetch.len(), 5);
+        assert!(fetch.failed.is_empty());
+        for key in &keys[..4] {
+            let (account, slot) = fetch.get(key).unwrap();
+            assert_eq!(account.data, key.as_ref());
+            assert!((100..103).contains(&slot));
+        }
+        //不存在的账户也在结果里，只是没有数据
+        assert!(fetch.get(&keys[4]).is_none());
+        assert!(fetch.accounts[&keys[4]].account.is_none());
+    }
+
+    #[test]
+    fn retries_unavailable_batches() {
+        let keys = keys(2);
+        let existing = keys.clone();
+        let stub = Stub::start(move |hit, body| match hit {
+            0 => (503, String::new()),
+            _ => (200, accounts_response(body, 100, &existing)),
+        });
+        let fetch = fetch_accounts_with_retry(&stub.pool(), &keys, CommitmentConfig::confirmed(), 10, Some(100), 1);
+        assert_eq!(stub.hits(), 2);
+        assert!(fetch.failed.is_empty());
+        assert_eq!(fetch.get(&keys[0]).unwrap().1, 100);
+    }
+
+    #[test]
+    fn records_failed_batches() {
+        let keys = keys(3);
+        //重试次数用完
+        let stub = Stub::status(503);
+        let fetch = fetch_accounts_with_retry(&stub.pool(), &keys, CommitmentConfig::confirmed(), 10, None, 1);
+        assert_eq!(stub.hits(), 2);
+        assert!(fetch.accounts.is_empty());
+        assert_eq!(fetch.failed.len(), 3);
+
+        //参数错误这类 Fatal 不重试
+        let stub = Stub::rpc_error(-32602);
+        let fetch = fetch_accounts_with_retry(&stub.pool(), &keys, CommitmentConfig::confirmed(), 10, None, 3);
+        assert_eq!(stub.hits(), 1);
+        assert_eq!(fetch.failed.len(), 3);
+        assert!(fetch.get(&keys[0]).is_none());
+    }
+}
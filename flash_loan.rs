@@ -0,0 +1,276 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    instruction::{AccountMeta, Instruction},
+    sysvar,
+};
+use std::{collections::HashMap, error::Error};
+
+use crate::{
+    fetch::AccountFetcher,
+    pool::user_token_account,
+};
+
+pub const SOLEND_PROGRAM_ID: &str = "So1endDq2YkqhipRh3WViPa8hdiSpxWy6z3Z6tMCpAo";
+const FLASH_BORROW_TAG: u8 = 19;
+const FLASH_REPAY_TAG: u8 = 20;
+const WAD: u128 = 1_000_000_000_000_000_000;
+
+//flash loan 需要的 reserve 字段
+#[derive(Debug, Clone)]
+pub struct FlashLoanReserve {
+    pub key: Pubkey,
+    pub lending_market: Pubkey,
+    pub liquidity_mint: Pubkey,
+    pub liquidity_supply: Pubkey,
+    //reserve 里现在能借出的数量
+    pub available_amount: u64,
+    pub flash_loan_fee_wad: u64,
+    pub fee_receiver: Pubkey,
+}
+
+impl FlashLoanReserve {
+    //按 Solend Reserve 的布局取字段: version(1) last_update(9) lending_market(32)
+    //liquidity: mint(32) decimals(1) supply(32) pyth(32) switchboard(32) available(8) borrowed(16) rate(16) price(16)
+    //collateral: mint(32) supply(8) supply_pubkey(32)
+    //config: 7 个 u8, borrow_fee_wad(8) flash_loan_fee_wad(8) host_fee_pct(1) deposit_limit(8) borrow_limit(8) fee_receiver(32)
+    pub fn parse(key: Pubkey, data: &[u8]) -> Option<Self> {
+        let key_at = |offset: usize| -> Option<Pubkey> {
+            Pubkey::try_from(data.get(offset..offset + 32)?).ok()
+        };
+        let u64_at = |offset: usize| -> Option<u64> {
+            data.get(offset..offset + 8)?.try_into().ok().map(u64::from_le_bytes)
+        };
+        Some(Self {
+            key,
+            lending_market: key_at(10)?,
+            liquidity_mint: key_at(42)?,
+            liquidity_supply: key_at(75)?,
+            available_amount: u64_at(171)?,
+            flash_loan_fee_wad: u64_at(314)?,
+            fee_receiver: key_at(339)?,
+        })
+    }
+
+    //向上取整，和合约里收的一致或者多估一点
+    pub fn fee(&self, amount: u64) -> u64 {
+        (amount as u128 * self.flash_loan_fee_wad as u128).div_ceil(WAD) as u64
+    }
+}
+
+//每个 base mint 对应一个 reserve，借还都用 owner 的 ATA
+#[derive(Debug, Clone)]
+pub struct FlashLoan {
+    pub program_id: Pubkey,
+    pub reserves: HashMap<Pubkey, FlashLoanReserve>,
+}
+
+impl FlashLoan {
+    //拉取 reserve 账户，按 liquidity mint 索引
+    pub fn load(program_id: Pubkey, fetcher: &dyn AccountFetcher, keys: &[Pubkey]) -> Result<Self, Box<dyn Error>> {
+        let fetched = fetcher.fetch(keys);
+        let mut reserves = HashMap::new();
+        for key in keys {
+            let (account, _) = fetched.get(key).ok_or_else(|| format!("reserve {} not found", key))?;
+            if account.owner != program_id {
+                return Err(format!("reserve {} is not owned by {}", key, program_id).into());
+            }
+            let reserve = FlashLoanReserve::parse(*key, &account.data)
+                .ok_or_else(|| format!("reserve {}: invalid account data", key))?;
+            reserves.insert(reserve.liquidity_mint, reserve);
+        }
+        Ok(Self { program_id, reserves })
+    }
+
+    pub fn reserve(&self, mint: &Pubkey) -> Option<&FlashLoanReserve> {
+        self.reserves.get(mint)
+    }
+
+    pub fn lending_market_authority(&self, lending_market: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[lending_market.as_ref()], &self.program_id).0
+    }
+
+    pub fn static_accounts(&self) -> Vec<Pubkey> {
+        let mut keys = vec![self.program_id, sysvar::instructions::ID];
+        for reserve in self.reserves.values() {
+            keys.extend([
+                reserve.key,
+                reserve.lending_market,
+                self.lending_market_authority(&reserve.lending_market),
+                reserve.liquidity_supply,
+                reserve.fee_receiver,
+            ]);
+        }
+        keys
+    }
+
+    pub fn borrow_ix(&self, reserve: &FlashLoanReserve, owner: &Pubkey, amount: u64) -> Instruction {
+        let mut data = vec![FLASH_BORROW_TAG];
+        data.extend_from_slice(&amount.to_le_bytes());
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(reserve.liquidity_supply, false),
+                AccountMeta::new(user_token_account(owner, &reserve.liquidity_mint), false),
+                AccountMeta::new(reserve.key, false),
+                AccountMeta::new_readonly(reserve.lending_market, false),
+                AccountMeta::new_readonly(self.lending_market_authority(&reserve.lending_market), false),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+            data,
+        }
+    }
+
+    //amount 是借出的本金，手续费由合约另外从 owner 的账户扣；host fee 也给 reserve 的 fee receiver
+    pub fn repay_ix(&self, reserve: &FlashLoanReserve, owner: &Pubkey, amount: u64, borrow_instruction_index: u8) -> Instruction {
+        let mut data = vec![FLASH_REPAY_TAG];
+        data.extend_from_slice(&amount.to_le_bytes());
+        data.push(borrow_instruction_index);
+        Instruction {
+            program_id: self.program_id,
+            accounts: vec![
+                AccountMeta::new(user_token_account(owner, &reserve.liquidity_mint), false),
+                AccountMeta::new(reserve.liquidity_supply, false),
+                AccountMeta::new(reserve.fee_receiver, false),
+                AccountMeta::new(reserve.fee_receiver, false),
+                AccountMeta::new(reserve.key, false),
+                AccountMeta::new_readonly(reserve.lending_market, false),
+                AccountMeta::new_readonly(*owner, true),
+                AccountMeta::new_readonly(sysvar::instructions::ID, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+            data,
+        }
+    }
+
+    //swap 指令前后包上借款和还款，borrow_index 是借款指令在整笔交易里的位置
+    pub fn wrap(
+        &self,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        amount: u64,
+        swaps: Vec<Instruction>,
+        borrow_index: usize,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let reserve = self.reserve(mint).ok_or_else(|| format!("no flash loan reserve for {}", mint))?;
+        if amount > reserve.available_amount {
+            return Err(format!("reserve {} has {} available, need {}", reserve.key, reserve.available_amount, amount).into());
+        }
+        let mut instructions = vec![self.borrow_ix(reserve, owner, amount)];
+        instructions.extend(swaps);
+        instructions.push(self.repay_ix(reserve, owner, amount, borrow_index as u8));
+        Ok(instructions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Solend Reserve 账户 619 字节，各字段按 parse 注释里的布局填，其它字段填垃圾
+    fn reserve_data(reserve: &FlashLoanReserve) -> Vec<u8> {
+        let mut data = vec![0xab; 619];
+        data[0] = 1;
+        data[10..42].copy_from_slice(reserve.lending_market.as_ref());
+        data[42..74].copy_from_slice(reserve.liquidity_mint.as_ref());
+        data[74] = 9;
+        data[75..107].copy_from_slice(reserve.liquidity_supply.as_ref());
+        data[171..179].copy_from_slice(&reserve.available_amount.to_le_bytes());
+        data[306..314].copy_from_slice(&1_000_000_000_000u64.to_le_bytes());
+        data[314..322].copy_from_slice(&reserve.flash_loan_fee_wad.to_le_bytes());
+        data[339..371].copy_from_slice(reserve.fee_receiver.as_ref());
+        data
+    }
+
+    fn reserve(fee_wad: u64) -> FlashLoanReserve {
+        FlashLoanReserve {
+            key: Pubkey::new_unique(),
+            lending_market: Pubkey::new_unique(),
+            liquidity_mint: spl_token::native_mint::ID,
+            liquidity_supply: Pubkey::new_unique(),
+            available_amount: 5_000_000,
+            flash_loan_fee_wad: fee_wad,
+            fee_receiver: Pubkey::new_unique(),
+        }
+    }
+
+    fn flash_loan(reserve: FlashLoanReserve) -> FlashLoan {
+        FlashLoan {
+            program_id: Pubkey::new_unique(),
+            reserves: HashMap::from([(reserve.liquidity_mint, reserve)]),
+        }
+    }
+
+    fn tagged(tag: u8) -> Instruction {
+        Instruction::new_with_bytes(Pubkey::new_unique(), &[tag], vec![])
+    }
+
+    #[test]
+    fn parse_reserve_layout() {
+        let expected = reserve(3_000_000_000_000_000);
+        let parsed = FlashLoanReserve::parse(expected.key, &reserve_data(&expected)).unwrap();
+        assert_eq!(parsed.key, expected.key);
+        assert_eq!(parsed.lending_market, expected.lending_market);
+        assert_eq!(parsed.liquidity_mint, expected.liquidity_mint);
+        assert_eq!(parsed.liquidity_supply, expected.liquidity_supply);
+        assert_eq!(parsed.available_amount, expected.available_amount);
+        assert_eq!(parsed.flash_loan_fee_wad, expected.flash_loan_fee_wad);
+        assert_eq!(parsed.fee_receiver, expected.fee_receiver);
+
+        //截断到 fee_receiver 之前就解析不出来
+        assert!(FlashLoanReserve::parse(expected.key, &reserve_data(&expected)[..370]).is_none());
+        assert!(FlashLoanReserve::parse(expected.key, &[]).is_none());
+    }
+
+    #[test]
+    fn fee_rounds_up() {
+        //0.3%
+        let fee = |amount| reserve(3_000_000_000_000_000).fee(amount);
+        assert_eq!(fee(1_000_000), 3_000);
+        assert_eq!(fee(1_000), 3);
+        assert_eq!(fee(1), 1);
+        assert_eq!(fee(334), 2);
+        assert_eq!(fee(0), 0);
+        //u64::MAX 乘 wad 不溢出
+        assert_eq!(fee(u64::MAX), (u64::MAX as u128 * 3 / 1_000 + 1) as u64);
+        assert_eq!(reserve(0).fee(1_000_000), 0);
+    }
+
+    #[test]
+    fn wrap_borrows_first_and_repays_last() {
+        let flash_loan = flash_loan(reserve(0));
+        let reserve = flash_loan.reserve(&spl_token::native_mint::ID).unwrap().clone();
+        let owner = Pubkey::new_unique();
+        let swaps = vec![tagged(1), tagged(2)];
+
+        let wrapped = flash_loan.wrap(&reserve.liquidity_mint, &owner, 1_000_000, swaps.clone(), 3).unwrap();
+        assert_eq!(wrapped.len(), 4);
+        assert_eq!(wrapped[0], flash_loan.borrow_ix(&reserve, &owner, 1_000_000));
+        assert_eq!(wrapped[1..3], swaps[..]);
+        assert_eq!(wrapped[3], flash_loan.repay_ix(&reserve, &owner, 1_000_000, 3));
+
+        let borrow = &wrapped[0];
+        assert_eq!(borrow.program_id, flash_loan.program_id);
+        assert_eq!(borrow.data[0], FLASH_BORROW_TAG);
+        assert_eq!(borrow.data[1..9], 1_000_000u64.to_le_bytes());
+        assert_eq!(borrow.accounts[1].pubkey, user_token_account(&owner, &reserve.liquidity_mint));
+
+        //还款的最后一个字节是借款指令在交易里的位置，合约靠它找到对应的借款
+        let repay = &wrapped[3];
+        assert_eq!(repay.data[0], FLASH_REPAY_TAG);
+        assert_eq!(repay.data[1..9], 1_000_000u64.to_le_bytes());
+        assert_eq!(repay.data[9], 3);
+        assert_eq!(repay.data.len(), 10);
+        assert!(repay.accounts.iter().any(|meta| meta.pubkey == owner && meta.is_signer));
+    }
+
+    #[test]
+    fn wrap_rejects_missing_reserve_and_short_liquidity() {
+        let flash_loan = flash_loan(reserve(0));
+        let owner = Pubkey::new_unique();
+        let wsol = spl_token::native_mint::ID;
+        assert!(flash_loan.wrap(&Pubkey::new_unique(), &owner, 1, vec![], 0).is_err());
+        assert!(flash_loan.wrap(&wsol, &owner, 5_000_001, vec![], 0).is_err());
+        assert!(flash_loan.wrap(&wsol, &owner, 5_000_000, vec![], 0).is_ok());
+    }
+}
@@ -0,0 +1,539 @@
+use serde::{Serialize, Deserialize};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::Path,
+    collections::HashMap,
+    error::Error,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::warn;
+use crate::{
+    arb::Opportunity,
+    config::{JournalConfig, JournalFormat},
+};
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub timestamp_ms: u64,
+    //路径上池子数据的最新 slot
+    pub slot: Option<u64>,
+    pub mint_path: Vec<String>,
+    pub pool_ids: Vec<String>,
+    pub venues: Vec<String>,
+    pub input: u64,
+    //每一跳的输出
+    pub outputs: Vec<u64>,
+    pub profit: u64,
+    //是否提交了交易（包括 paper executor）
+    pub executed: bool,
+    //模拟、提交或上链的结果，没有开启执行时为空
+    pub result: Option<String>,
+}
+
+impl JournalEntry {
+    pub fn new(opportunity: &Opportunity) -> Self {
+        Self {
+            timestamp_ms: now_ms(),
+            slot: opportunity.slot_range.map(|range| range.max),
+            mint_path: opportunity.mint_path.iter().map(|mint| mint.to_string()).collect(),
+            pool_ids: opportunity.pool_path.iter().map(|pool_id| pool_id.to_string()).collect(),
+            venues: opportunity.venues.iter().map(|venue| venue.to_string()).collect(),
+            input: opportunity.init_balance(),
+            outputs: opportunity.amounts[1..].to_vec(),
+            profit: opportunity.profit(),
+            executed: false,
+            result: None,
+        }
+    }
+
+    pub fn with_result(mut self, executed: bool, result: impl ToString) -> Self {
+        self.executed = executed;
+        self.result = Some(result.to_string());
+        self
+    }
+}
+
+//csv 里的列表用 ; 连接
+#[derive(Debug, Serialize)]
+struct CsvRow<'a> {
+    timestamp_ms: u64,
+    slot: Option<u64>,
+    mint_path: String,
+    pool_ids: String,
+    venues: String,
+    input: u64,
+    outputs: String,
+    profit: u64,
+    executed: bool,
+    result: Option<&'a str>,
+}
+
+impl<'a> From<&'a JournalEntry> for CsvRow<'a> {
+    fn from(entry: &'a JournalEntry) -> Self {
+        Self {
+            timestamp_ms: entry.timestamp_ms,
+            slot: entry.slot,
+            mint_path: entry.mint_path.join(";"),
+            pool_ids: entry.pool_ids.join(";"),
+            venues: entry.venues.join(";"),
+            input: entry.input,
+            outputs: entry.outputs.iter().map(|amount| amount.to_string()).collect::<Vec<_>>().join(";"),
+            profit: entry.profit,
+            executed: entry.executed,
+            result: entry.result.as_deref(),
+        }
+    }
+}
+
+trait JournalWriter {
+    //path 是新文件时由 writer 写表头
+    fn open(&mut self, path: &Path) -> Result<(), Box<dyn Error>>;
+    fn write(&mut self, entry: &JournalEntry) -> Result<(), Box<dyn Error>>;
+    fn flush(&mut self) -> Result<(), Box<dyn Error>>;
+    //关闭当前文件，parquet 在这里写 footer
+    fn close(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+#[derive(Default)]
+struct JsonlWriter {
+    file: Option<BufWriter<File>>,
+}
+
+impl JournalWriter for JsonlWriter {
+    fn open(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.file = Some(BufWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    fn write(&mut self, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+        let file = self.file.as_mut().ok_or("journal file not open")?;
+        serde_json::to_writer(&mut *file, entry)?;
+        writeln!(file)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.file = None;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct CsvWriter {
+    writer: Option<csv::Writer<File>>,
+}
+
+impl JournalWriter for CsvWriter {
+    fn open(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.writer = Some(csv::Writer::from_path(path)?);
+        Ok(())
+    }
+
+    fn write(&mut self, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+        let writer = self.writer.as_mut().ok_or("journal file not open")?;
+        writer.serialize(CsvRow::from(entry))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(writer) = &mut self.writer {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.flush()?;
+        self.writer = None;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use super::*;
+    use arrow::{
+        array::{ArrayRef, BooleanArray, ListBuilder, StringArray, StringBuilder, UInt64Array, UInt64Builder},
+        datatypes::{DataType, Field, Schema, SchemaRef},
+        record_batch::RecordBatch,
+    };
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    //攒够一个 row group 再写
+    const ROW_GROUP_SIZE: usize = 1024;
+
+    pub struct ParquetWriter {
+        schema: SchemaRef,
+        writer: Option<ArrowWriter<File>>,
+        rows: Vec<JournalEntry>,
+    }
+
+    impl ParquetWriter {
+        pub fn new() -> Self {
+            let list = |name: &str, item: DataType| {
+                Field::new(name, DataType::List(Arc::new(Field::new("item", item, true))), false)
+            };
+            Self {
+                schema: Arc::new(Schema::new(vec![
+                    Field::new("timestamp_ms", DataType::UInt64, false),
+                    Field::new("slot", DataType::UInt64, true),
+                    list("mint_path", DataType::Utf8),
+                    list("pool_ids", DataType::Utf8),
+                    list("venues", DataType::Utf8),
+                    Field::new("input", DataType::UInt64, false),
+                    list("outputs", DataType::UInt64),
+                    Field::new("profit", DataType::UInt64, false),
+                    Field::new("executed", DataType::Boolean, false),
+                    Field::new("result", DataType::Utf8, true),
+                ])),
+                writer: None,
+                rows: vec![],
+            }
+        }
+
+        fn batch(&self) -> Result<RecordBatch, Box<dyn Error>> {
+            let strings = |values: fn(&JournalEntry) -> &Vec<String>| -> ArrayRef {
+                let mut builder = ListBuilder::new(StringBuilder::new());
+                for row in &self.rows {
+                    for value in values(row) {
+                        builder.values().append_value(value);
+                    }
+                    builder.append(true);
+                }
+                Arc::new(builder.finish())
+            };
+            let mut outputs = ListBuilder::new(UInt64Builder::new());
+            for row in &self.rows {
+                outputs.values().append_slice(&row.outputs);
+                outputs.append(true);
+            }
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(UInt64Array::from_iter_values(self.rows.iter().map(|row| row.timestamp_ms))),
+                Arc::new(self.rows.iter().map(|row| row.slot).collect::<UInt64Array>()),
+                strings(|row| &row.mint_path),
+                strings(|row| &row.pool_ids),
+                strings(|row| &row.venues),
+                Arc::new(UInt64Array::from_iter_values(self.rows.iter().map(|row| row.input))),
+                Arc::new(outputs.finish()),
+                Arc::new(UInt64Array::from_iter_values(self.rows.iter().map(|row| row.profit))),
+                Arc::new(self.rows.iter().map(|row| Some(row.executed)).collect::<BooleanArray>()),
+                Arc::new(self.rows.iter().map(|row| row.result.as_deref()).collect::<StringArray>()),
+            ];
+            Ok(RecordBatch::try_new(self.schema.clone(), columns)?)
+        }
+    }
+
+    impl JournalWriter for ParquetWriter {
+        fn open(&mut self, path: &Path) -> Result<(), Box<dyn Error>> {
+            self.writer = Some(ArrowWriter::try_new(File::create(path)?, self.schema.clone(), None)?);
+            Ok(())
+        }
+
+        fn write(&mut self, entry: &JournalEntry) -> Result<(), Box<dyn Error>> {
+            self.rows.push(entry.clone());
+            if self.rows.len() >= ROW_GROUP_SIZE {
+                self.flush()?;
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+            if self.rows.is_empty() {
+                return Ok(());
+            }
+            let batch = self.batch()?;
+            self.writer.as_mut().ok_or("journal file not open")?.write(&batch)?;
+            self.rows.clear();
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<(), Box<dyn Error>> {
+            self.flush()?;
+            if let Some(writer) = self.writer.take() {
+                writer.close()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn new_writer(format: JournalFormat) -> Result<Box<dyn JournalWriter>, Box<dyn Error>> {
+    Ok(match format {
+        JournalFormat::Jsonl => Box::new(JsonlWriter::default()),
+        JournalFormat::Csv => Box::new(CsvWriter::default()),
+        #[cfg(feature = "parquet")]
+        JournalFormat::Parquet => Box::new(parquet_writer::ParquetWriter::new()),
+        #[cfg(not(feature = "parquet"))]
+        JournalFormat::Parquet => return Err("journal format parquet needs feature \"parquet\"".into()),
+    })
+}
+
+fn extension(format: JournalFormat) -> &'static str {
+    match format {
+        JournalFormat::Jsonl => "jsonl",
+        JournalFormat::Csv => "csv",
+        JournalFormat::Parquet => "parquet",
+    }
+}
+
+//每个格式一个文件，每 rotate_secs 换一批新文件，文件名带开始时间
+//已经提交的机会等 executor 给出结果后再写
+pub struct Journal {
+    config: JournalConfig,
+    writers: Vec<(JournalFormat, Box<dyn JournalWriter>)>,
+    opened_at: Option<u64>,
+    pending: HashMap<String, JournalEntry>,
+}
+
+impl Journal {
+    //没有配置 dir 时不写文件
+    pub fn open(config: &JournalConfig) -> Result<Self, Box<dyn Error>> {
+        let mut writers = vec![];
+        if let Some(dir) = &config.dir {
+            fs::create_dir_all(dir)?;
+            for &format in &config.formats {
+                writers.push((format, new_writer(format)?));
+            }
+        }
+        Ok(Self {
+            config: config.clone(),
+            writers,
+            opened_at: None,
+            pending: HashMap::new(),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.writers.is_empty()
+    }
+
+    //now 是 unix 秒
+    fn rotate(&mut self, now: u64) -> Result<(), Box<dyn Error>> {
+        if self.opened_at.is_some_and(|opened_at| now < opened_at + self.config.rotate_secs) {
+            return Ok(());
+        }
+        let dir = self.config.dir.clone().unwrap_or_default();
+        for (format, writer) in &mut self.writers {
+            writer.close()?;
+            let path = dir.join(format!("{}-{}.{}", self.config.prefix, now, extension(*format)));
+            writer.open(&path)?;
+        }
+        self.opened_at = Some(now);
+        Ok(())
+    }
+
+    pub fn record(&mut self, entry: &JournalEntry) {
+        if !self.is_enabled() {
+            return;
+        }
+        let result = self.rotate(now_secs()).and_then(|_| {
+            for (_, writer) in &mut self.writers {
+                writer.write(entry)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!(error = %e, "write journal failed");
+        }
+    }
+
+    pub fn record_all(&mut self, opportunities: &[Opportunity]) {
+        for opportunity in opportunities {
+            self.record(&JournalEntry::new(opportunity));
+        }
+        self.flush();
+    }
+
+    //submission_id 有结果时调用 resolve
+    pub fn defer(&mut self, submission_id: &str, entry: JournalEntry) {
+        if self.is_enabled() {
+            self.pending.insert(submission_id.to_string(), entry);
+        }
+    }
+
+    pub fn resolve(&mut self, submission_id: &str, result: impl ToString) {
+        if let Some(entry) = self.pending.remove(submission_id) {
+            self.record(&entry.with_result(true, result));
+        }
+    }
+
+    pub fn flush(&mut self) {
+        for (_, writer) in &mut self.writers {
+            if let Err(e) = writer.flush() {
+                warn!(error = %e, "flush journal failed");
+            }
+        }
+    }
+
+    //还没结果的提交按 pending 写出
+    pub fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        for (id, entry) in std::mem::take(&mut self.pending) {
+            self.record(&entry.with_result(true, format!("{}: pending", id)));
+        }
+        for (_, writer) in &mut self.writers {
+            writer.close()?;
+        }
+        self.opened_at = None;
+        Ok(())
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        if let Err(e) = self.close() {
+            warn!(error = %e, "close journal failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+    use std::path::PathBuf;
+    use crate::registry::PoolType;
+
+    fn entry() -> JournalEntry {
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        JournalEntry::new(&Opportunity {
+            path: vec![0, 1, 0],
+            mint_path: vec![a, b, a],
+            pool_path: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            venues: vec![PoolType::RayAmm, PoolType::Orca],
+            amounts: vec![1_000, 2_000, 1_100],
+            slot_range: None,
+        })
+    }
+
+    fn journal(dir: &Path, formats: Vec<JournalFormat>) -> Journal {
+        Journal::open(&JournalConfig {
+            dir: Some(dir.to_path_buf()),
+            formats,
+            prefix: "test".to_string(),
+            rotate_secs: 60,
+        }).unwrap()
+    }
+
+    fn files(dir: &Path) -> Vec<PathBuf> {
+        let mut files = fs::read_dir(dir).unwrap().map(|file| file.unwrap().path()).collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    fn jsonl(path: &Path) -> Vec<JournalEntry> {
+        fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn round_trips_jsonl_and_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = entry().with_result(true, "landed at slot 7");
+        let mut journal = journal(dir.path(), vec![JournalFormat::Jsonl, JournalFormat::Csv]);
+        journal.record(&entry);
+        journal.close().unwrap();
+
+        let files = files(dir.path());
+        assert_eq!(files.len(), 2);
+        let (csv_path, jsonl_path) = (&files[0], &files[1]);
+        assert_eq!(csv_path.extension().unwrap(), "csv");
+
+        let read = jsonl(jsonl_path);
+        assert_eq!(read.len(), 1);
+        assert_eq!(serde_json::to_value(&read[0]).unwrap(), serde_json::to_value(&entry).unwrap());
+        assert_eq!(read[0].outputs, vec![2_000, 1_100]);
+        assert_eq!(read[0].venues, vec!["ray_amm", "orca"]);
+
+        let mut reader = csv::Reader::from_path(csv_path).unwrap();
+        let headers = reader.headers().unwrap().clone();
+        assert_eq!(headers.iter().collect::<Vec<_>>(), vec![
+            "timestamp_ms", "slot", "mint_path", "pool_ids", "venues", "input", "outputs", "profit", "executed", "result",
+        ]);
+        let rows = reader.records().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(&row[0], entry.timestamp_ms.to_string());
+        assert_eq!(&row[1], "");
+        assert_eq!(&row[2], entry.mint_path.join(";"));
+        assert_eq!(&row[3], entry.pool_ids.join(";"));
+        assert_eq!(&row[4], "ray_amm;orca");
+        assert_eq!(&row[5], "1000");
+        assert_eq!(&row[6], "2000;1100");
+        assert_eq!(&row[7], "100");
+        assert_eq!(&row[8], "true");
+        assert_eq!(&row[9], "landed at slot 7");
+    }
+
+    #[test]
+    fn rotates_after_rotate_secs() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = journal(dir.path(), vec![JournalFormat::Jsonl]);
+        journal.rotate(1_000).unwrap();
+        journal.rotate(1_059).unwrap();
+        assert_eq!(files(dir.path()), vec![dir.path().join("test-1000.jsonl")]);
+
+        //正好 rotate_secs 时换新文件
+        journal.rotate(1_060).unwrap();
+        assert_eq!(files(dir.path()), vec![dir.path().join("test-1000.jsonl"), dir.path().join("test-1060.jsonl")]);
+        journal.rotate(1_119).unwrap();
+        assert_eq!(files(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn deferred_entries_wait_for_their_result() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut journal = journal(dir.path(), vec![JournalFormat::Jsonl]);
+        let (first, second, third) = (entry(), entry(), entry());
+        journal.defer("sub-1", first.clone());
+        journal.defer("sub-2", second.clone());
+        journal.defer("sub-3", third.clone());
+        journal.resolve("sub-2", "landed at slot 9");
+        //不认识的 id 和重复 resolve 都不写
+        journal.resolve("sub-4", "landed at slot 10");
+        journal.resolve("sub-2", "landed at slot 11");
+        journal.flush();
+
+        let path = &files(dir.path())[0];
+        let read = jsonl(path);
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].pool_ids, second.pool_ids);
+        assert!(read[0].executed);
+        assert_eq!(read[0].result.as_deref(), Some("landed at slot 9"));
+
+        //关闭时剩下的按 pending 写出
+        journal.close().unwrap();
+        let mut read = jsonl(path);
+        assert_eq!(read.len(), 3);
+        read.sort_by_key(|entry| entry.result.clone());
+        assert_eq!(read[1].result.as_deref(), Some("sub-1: pending"));
+        assert_eq!(read[1].pool_ids, first.pool_ids);
+        assert_eq!(read[2].result.as_deref(), Some("sub-3: pending"));
+        assert_eq!(read[2].pool_ids, third.pool_ids);
+    }
+
+    #[test]
+    fn disabled_without_dir() {
+        let mut journal = Journal::open(&JournalConfig::default()).unwrap();
+        assert!(!journal.is_enabled());
+        journal.defer("sub-1", entry());
+        assert!(journal.pending.is_empty());
+    }
+}
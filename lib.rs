@@ -0,0 +1,27 @@
+pub mod registry;
+pub mod config;
+pub mod pool;
+#[cfg(feature = "orca")]
+pub mod orca_pool;
+#[cfg(feature = "meteora")]
+pub mod meteora_pool;
+#[cfg(feature = "raydium")]
+pub mod ray_amm_pool;
+pub mod arb;
+pub mod stream;
+pub mod source;
+pub mod fetch;
+pub mod loader;
+pub mod rpc_pool;
+pub mod snapshot;
+pub mod backtest;
+pub mod execution;
+pub mod executor;
+pub mod lookup_table;
+pub mod flash_loan;
+pub mod wallet;
+pub mod paper;
+pub mod journal;
+pub mod metrics;
+pub mod logging;
+pub mod bot;
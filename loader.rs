@@ -0,0 +1,528 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+};
+use std::{
+    fmt, rc::Rc, cell::RefCell, thread, sync::Arc,
+    error::Error,
+    collections::{BTreeMap, HashSet},
+};
+
+use crate::{
+    registry::{PairData, PoolType, Registry},
+    config::Config,
+    fetch::{AccountFetcher, BatchFetch, FetchedAccount},
+    pool::{PoolOperations, PoolRef},
+    stream::{AccountUpdate, WatchIndex},
+    metrics::metrics,
+};
+use tracing::{info, warn, info_span};
+#[cfg(feature = "orca")]
+use crate::orca_pool::OrcaLoader;
+#[cfg(feature = "meteora")]
+use crate::meteora_pool::MeteoraLoader;
+#[cfg(feature = "raydium")]
+use crate::ray_amm_pool::RayAmmLoader;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SkipReason {
+    //batch 重试后仍然失败
+    FetchFailed,
+    AccountNotFound,
+    InvalidAccountData,
+    //tick array / bin array / vault 缺失或无法解析
+    MissingDependency,
+    InvalidPoolState,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            SkipReason::FetchFailed => "fetch_failed",
+            SkipReason::AccountNotFound => "account_not_found",
+            SkipReason::InvalidAccountData => "invalid_account_data",
+            SkipReason::MissingDependency => "missing_dependency",
+            SkipReason::InvalidPoolState => "invalid_pool_state",
+        };
+        write!(f, "{reason}")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadSummary {
+    pub venue: PoolType,
+    pub requested: usize,
+    pub loaded: usize,
+    pub skipped: Vec<(Pubkey, SkipReason)>,
+}
+
+impl LoadSummary {
+    pub fn new(venue: PoolType, requested: usize) -> Self {
+        Self {
+            venue,
+            requested,
+            loaded: 0,
+            skipped: vec![],
+        }
+    }
+
+    pub fn skip(&mut self, pool_id: Pubkey, reason: SkipReason) {
+        self.skipped.push((pool_id, reason));
+    }
+
+    pub fn skipped_by_reason(&self) -> BTreeMap<SkipReason, usize> {
+        let mut counts = BTreeMap::new();
+        for (_, reason) in &self.skipped {
+            *counts.entry(reason.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+impl fmt::Display for LoadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: requested {}, loaded {}, skipped {}",
+            self.venue, self.requested, self.loaded, self.skipped.len())?;
+        for (reason, count) in self.skipped_by_reason() {
+            write!(f, ", {reason}: {count}")?;
+        }
+        Ok(())
+    }
+}
+
+//加载分五步: discover -> 拉主账户 -> 推导依赖账户 -> 拉依赖账户 -> build
+//拉取由 load_pools 统一做，新加 venue 只需要实现解析和推导
+pub trait PoolLoader {
+    type Primary;
+    type Pool: PoolOperations + Send + 'static;
+
+    fn venue(&self) -> PoolType;
+
+    fn discover(&self, pairs: &[PairData]) -> Vec<Pubkey> {
+        pairs.iter().map(|pair| pair.pool_id).collect()
+    }
+
+    fn decode_primary(&self, pool_id: &Pubkey, account: &Account) -> Result<Self::Primary, SkipReason>;
+
+    //每个池子自己的依赖账户，build 时按同样的顺序传回去
+    fn dependents(&self, pool_id: &Pubkey, primary: &Self::Primary) -> Result<Vec<Pubkey>, SkipReason>;
+
+    //所有池子共用的依赖账户（比如 clock），只拉一次
+    fn shared_dependents(&self) -> Vec<Pubkey> {
+        vec![]
+    }
+
+    fn build(
+        &self,
+        pool_id: Pubkey,
+        primary: Self::Primary,
+        primary_slot: u64,
+        dependent_keys: Vec<Pubkey>,
+        dependents: &BatchFetch,
+    ) -> Result<Self::Pool, SkipReason>;
+}
+
+pub fn load_pools<L: PoolLoader>(loader: &L, fetcher: &dyn AccountFetcher, pairs: &[PairData]) -> (Vec<L::Pool>, LoadSummary) {
+    let venue = loader.venue();
+    let pool_ids = dedup(loader.discover(pairs));
+    let mut summary = LoadSummary::new(venue, pool_ids.len());
+    let _load = info_span!("load", %venue).entered();
+
+    let fetch_primary = info_span!("fetch_primary", pools = pool_ids.len()).entered();
+    let primaries_fetch = fetcher.fetch(&pool_ids);
+    let mut candidates = vec![];
+    let mut dependent_keys = loader.shared_dependents();
+    for pool_id in pool_ids {
+        let candidate = primary_account(&primaries_fetch, &pool_id).and_then(|fetched| {
+            let primary = loader.decode_primary(&pool_id, fetched.account.as_ref().unwrap())?;
+            let keys = loader.dependents(&pool_id, &primary)?;
+            Ok((primary, fetched.slot, keys))
+        });
+        match candidate {
+            Ok((primary, slot, keys)) => {
+                dependent_keys.extend(keys.iter().cloned());
+                candidates.push((pool_id, primary, slot, keys));
+            }
+            Err(reason) => summary.skip(pool_id, reason),
+        }
+    }
+    info!(candidates = candidates.len(), failed = primaries_fetch.failed.len(), "fetched pool accounts");
+    drop(fetch_primary);
+
+    let dependent_keys = dedup(dependent_keys);
+    let fetch_dependents = info_span!("fetch_dependents", accounts = dependent_keys.len()).entered();
+    let dependents_fetch = fetcher.fetch(&dependent_keys);
+    info!(failed = dependents_fetch.failed.len(), "fetched dependent accounts");
+    drop(fetch_dependents);
+
+    let _build = info_span!("build", candidates = candidates.len()).entered();
+    let mut pools = vec![];
+    for (pool_id, primary, slot, keys) in candidates {
+        match loader.build(pool_id, primary, slot, keys, &dependents_fetch) {
+            Ok(pool) => pools.push(pool),
+            Err(reason) => summary.skip(pool_id, reason),
+        }
+    }
+    summary.loaded = pools.len();
+    (pools, summary)
+}
+
+pub type LoadResult = Result<(Vec<PoolRef>, LoadSummary), String>;
+
+//每个 venue 在自己的线程里加载，join 回主线程后才包成 PoolRef
+pub struct LoaderHandle {
+    pub venue: PoolType,
+    join: Box<dyn FnOnce() -> LoadResult>,
+}
+
+impl LoaderHandle {
+    pub fn spawn<L>(loader: L, fetcher: Arc<dyn AccountFetcher>, pairs: Vec<PairData>) -> Self
+    where
+        L: PoolLoader + Send + 'static,
+    {
+        let venue = loader.venue();
+        let handle = thread::Builder::new()
+            .name(format!("{}-loader", venue))
+            .spawn(move || load_pools(&loader, fetcher.as_ref(), &pairs))
+            .unwrap();
+        let join = Box::new(move || {
+            let (pools, summary) = handle.join().map_err(|_| format!("{} loader panicked", venue))?;
+            let pools = pools
+                .into_iter()
+                .map(|pool| -> PoolRef { Rc::new(RefCell::new(pool)) })
+                .collect();
+            Ok((pools, summary))
+        });
+        Self { venue, join }
+    }
+
+    pub fn join(self) -> LoadResult {
+        (self.join)()
+    }
+}
+
+//只为编译进来、并且配置里启用的 venue 启动 loader；一个 venue 都没编译时 fetcher/pairs 用不上
+#[allow(unused_variables)]
+pub fn spawn_loader(venue: PoolType, fetcher: Arc<dyn AccountFetcher>, pairs: Vec<PairData>) -> Option<LoaderHandle> {
+    match venue {
+        #[cfg(feature = "meteora")]
+        PoolType::Meteora => Some(LoaderHandle::spawn(MeteoraLoader, fetcher, pairs)),
+        #[cfg(feature = "orca")]
+        PoolType::Orca => Some(LoaderHandle::spawn(OrcaLoader, fetcher, pairs)),
+        #[cfg(feature = "raydium")]
+        PoolType::RayAmm => Some(LoaderHandle::spawn(RayAmmLoader, fetcher, pairs)),
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+pub fn spawn_loaders(config: &Config, registry: &Registry, fetcher: &Arc<dyn AccountFetcher>) -> Vec<LoaderHandle> {
+    config.venues
+        .iter()
+        .filter_map(|&venue| spawn_loader(venue, fetcher.clone(), config.pairs_for(registry, venue)))
+        .collect()
+}
+
+//单个池子的错误记在 summary 里；一个 venue 的 loader 线程 panic 只丢掉这个 venue
+pub fn load_all(config: &Config, registry: &Registry, fetcher: &Arc<dyn AccountFetcher>) -> Result<Vec<PoolRef>, Box<dyn Error>> {
+    join_loaders(spawn_loaders(config, registry, fetcher))
+}
+
+//所有 venue 都失败时才返回错误
+pub fn join_loaders(loaders: Vec<LoaderHandle>) -> Result<Vec<PoolRef>, Box<dyn Error>> {
+    let count = loaders.len();
+    let mut failed = vec![];
+    let mut all_pools: Vec<PoolRef> = Vec::new();
+    for loader in loaders {
+        let venue = loader.venue;
+        let (pools, summary) = match loader.join() {
+            Ok(loaded) => loaded,
+            Err(e) => {
+                warn!(%venue, error = %e, "load venue failed");
+                failed.push(e);
+                continue;
+            }
+        };
+        info!(venue = %summary.venue, requested = summary.requested, loaded = summary.loaded, skipped = summary.skipped.len(), "{}", summary);
+        metrics().pools_loaded.with_label_values(&[&summary.venue.to_string()]).set(summary.loaded as i64);
+        for (reason, count) in summary.skipped_by_reason() {
+            metrics().error_pools.with_label_values(&[&reason.to_string()]).add(count as i64);
+        }
+        for (pool_id, reason) in &summary.skipped {
+            warn!(venue = %summary.venue, %pool_id, %reason, "skip pool");
+        }
+        all_pools.extend(pools);
+    }
+    if count > 0 && failed.len() == count {
+        return Err(failed.join("; ").into());
+    }
+    Ok(all_pools)
+}
+
+//各 loader 分批、分阶段拉取，slot 不一致的池子按最新 slot 重新拉一次
+pub fn align_slots(
+    pools: &[PoolRef],
+    watch_index: &mut WatchIndex,
+    fetcher: &dyn AccountFetcher,
+    max_slot_spread: u64,
+    max_rounds: usize,
+) {
+    let _span = info_span!("align_slots", max_slot_spread).entered();
+    for round in 0..max_rounds {
+        let Some(target_slot) = pools
+            .iter()
+            .filter_map(|pool| pool.borrow().slot_range())
+            .map(|range| range.max)
+            .max()
+        else {
+            break;
+        };
+        let stale_keys = dedup(pools
+            .iter()
+            .filter(|pool| {
+                pool.borrow()
+                    .slot_range()
+                    .is_some_and(|range| range.min + max_slot_spread < target_slot)
+            })
+            .flat_map(|pool| pool.borrow().accounts_to_watch()));
+        if stale_keys.is_empty() {
+            break;
+        }
+        info!(round, accounts = stale_keys.len(), target_slot, "refetch accounts behind target slot");
+        let refetched = fetcher.fetch_at(&stale_keys, Some(target_slot));
+        if !refetched.failed.is_empty() {
+            warn!(round, failed = refetched.failed.len(), "refetch failed");
+        }
+        for fetched in refetched.accounts.into_values() {
+            if let Some(account) = fetched.account {
+                watch_index.apply(&AccountUpdate { pubkey: fetched.pubkey, account, slot: fetched.slot });
+            }
+        }
+    }
+}
+
+//主账户取不到时的原因
+pub fn primary_account<'a>(fetch: &'a BatchFetch, key: &Pubkey) -> Result<&'a FetchedAccount, SkipReason> {
+    if fetch.failed.contains_key(key) {
+        return Err(SkipReason::FetchFailed);
+    }
+    match fetch.accounts.get(key) {
+        Some(fetched) if fetched.account.is_some() => Ok(fetched),
+        _ => Err(SkipReason::AccountNotFound),
+    }
+}
+
+pub fn dedup(keys: impl IntoIterator<Item = Pubkey>) -> Vec<Pubkey> {
+    let mut seen = HashSet::new();
+    keys.into_iter().filter(|key| seen.insert(*key)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pool::mock::MockPool;
+    use std::{collections::HashMap, sync::Mutex};
+
+    //按表返回账户，failed 里的 key 当作 batch 失败，记下每次请求的 key 和 min_context_slot
+    #[derive(Default)]
+    struct StubFetcher {
+        accounts: HashMap<Pubkey, (Account, u64)>,
+        failed: HashSet<Pubkey>,
+        calls: Mutex<Vec<(Vec<Pubkey>, Option<u64>)>>,
+    }
+
+    impl StubFetcher {
+        fn new(accounts: &[(Pubkey, [u64; 2], u64)]) -> Self {
+            let accounts = accounts
+                .iter()
+                .map(|(pubkey, amounts, slot)| (*pubkey, (Account { data: MockPool::data(*amounts), ..Account::default() }, *slot)))
+                .collect();
+            Self { accounts, ..Self::default() }
+        }
+
+        fn calls(&self) -> Vec<(Vec<Pubkey>, Option<u64>)> {
+            self.calls.lock().unwrap().clone()
+        }
+    }
+
+    impl AccountFetcher for StubFetcher {
+        fn fetch_at(&self, keys: &[Pubkey], min_context_slot: Option<u64>) -> BatchFetch {
+            self.calls.lock().unwrap().push((keys.to_vec(), min_context_slot));
+            let mut fetch = BatchFetch::default();
+            for key in keys {
+                if self.failed.contains(key) {
+                    fetch.failed.insert(*key, "stub batch failed".to_string());
+                    continue;
+                }
+                let (account, slot) = self.accounts.get(key).cloned().map_or((None, 0), |(account, slot)| (Some(account), slot));
+                fetch.accounts.insert(*key, FetchedAccount { pubkey: *key, account, slot });
+            }
+            fetch
+        }
+    }
+
+    //主账户是 MockPool 的储备量，deps 里的池子还要一个依赖账户；储备为 0 的池子 build 失败
+    #[derive(Default)]
+    struct MockLoader {
+        pool_ids: Vec<Pubkey>,
+        deps: HashMap<Pubkey, Pubkey>,
+        panic: bool,
+    }
+
+    impl PoolLoader for MockLoader {
+        type Primary = [u64; 2];
+        type Pool = MockPool;
+
+        fn venue(&self) -> PoolType {
+            PoolType::RayAmm
+        }
+
+        fn discover(&self, _: &[PairData]) -> Vec<Pubkey> {
+            assert!(!self.panic, "mock loader panicked");
+            self.pool_ids.clone()
+        }
+
+        fn decode_primary(&self, _: &Pubkey, account: &Account) -> Result<[u64; 2], SkipReason> {
+            let data: [u8; 16] = account.data.as_slice().try_into().map_err(|_| SkipReason::InvalidAccountData)?;
+            Ok([0, 8].map(|offset| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())))
+        }
+
+        fn dependents(&self, pool_id: &Pubkey, _: &[u64; 2]) -> Result<Vec<Pubkey>, SkipReason> {
+            Ok(self.deps.get(pool_id).into_iter().copied().collect())
+        }
+
+        fn build(
+            &self,
+            pool_id: Pubkey,
+            primary: [u64; 2],
+            primary_slot: u64,
+            dependent_keys: Vec<Pubkey>,
+            dependents: &BatchFetch,
+        ) -> Result<MockPool, SkipReason> {
+            if dependent_keys.iter().any(|key| dependents.get(key).is_none()) {
+                return Err(SkipReason::MissingDependency);
+            }
+            if primary.contains(&0) {
+                return Err(SkipReason::InvalidPoolState);
+            }
+            let mut pool = MockPool::new(pool_id, [Pubkey::default(), Pubkey::new_unique()], primary);
+            pool.slot = primary_slot;
+            Ok(pool)
+        }
+    }
+
+    fn pool_at(slot: u64) -> PoolRef {
+        let mut pool = MockPool::new(Pubkey::new_unique(), [Pubkey::new_unique(), Pubkey::new_unique()], [100, 100]);
+        pool.slot = slot;
+        pool.into_ref()
+    }
+
+    fn watch_index(pools: &[PoolRef]) -> WatchIndex {
+        let mut watch_index = WatchIndex::new();
+        for pool in pools {
+            watch_index.add_pool(pool);
+        }
+        watch_index
+    }
+
+    fn slot(pool: &PoolRef) -> u64 {
+        pool.borrow().slot_range().unwrap().max
+    }
+
+    #[test]
+    fn align_slots_refetches_stale_pools() {
+        let pools = vec![pool_at(100), pool_at(95), pool_at(90)];
+        let stale = pools[2].borrow().get_pool_id();
+        let fetcher = StubFetcher::new(&[(stale, [100, 300], 100)]);
+        align_slots(&pools, &mut watch_index(&pools), &fetcher, 5, 3);
+
+        //差 5 个 slot 以内的不重新拉，拉过一轮都对齐了就停
+        assert_eq!(fetcher.calls(), vec![(vec![stale], Some(100))]);
+        assert_eq!(pools.iter().map(slot).collect::<Vec<_>>(), vec![100, 95, 100]);
+        assert_eq!(pools[2].borrow().calc_quote(true, 100), 150);
+    }
+
+    #[test]
+    fn align_slots_gives_up_after_max_rounds() {
+        let pools = vec![pool_at(100), pool_at(90)];
+        //节点一直落后，拉回来的还是旧 slot
+        let behind = pools[1].borrow().get_pool_id();
+        let fetcher = StubFetcher::new(&[(behind, [100, 100], 90)]);
+        align_slots(&pools, &mut watch_index(&pools), &fetcher, 5, 3);
+        assert_eq!(fetcher.calls().len(), 3);
+        assert_eq!(slot(&pools[1]), 90);
+
+        //已经对齐、或者没有池子时不请求
+        let fetcher = StubFetcher::new(&[]);
+        align_slots(&pools[..1], &mut watch_index(&pools[..1]), &fetcher, 5, 3);
+        align_slots(&[], &mut WatchIndex::new(), &fetcher, 5, 3);
+        assert!(fetcher.calls().is_empty());
+    }
+
+    #[test]
+    fn load_pools_counts_skips_by_reason() {
+        let ids = (0..7).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let (dep_ok, dep_missing) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut fetcher = StubFetcher::new(&[
+            (ids[0], [100, 200], 10),
+            (ids[1], [100, 200], 11),
+            (ids[3], [0, 200], 10),
+            (ids[4], [100, 200], 10),
+            (ids[5], [100, 200], 10),
+            (dep_ok, [0, 0], 10),
+        ]);
+        //ids[2] 不存在，ids[6] 所在的 batch 失败，ids[1] 的数据长度不对
+        fetcher.accounts.get_mut(&ids[1]).unwrap().0.data.pop();
+        fetcher.failed.insert(ids[6]);
+        let loader = MockLoader {
+            //重复的 id 只算一次
+            pool_ids: [ids.clone(), vec![ids[0]]].concat(),
+            deps: HashMap::from([(ids[4], dep_ok), (ids[5], dep_missing)]),
+            panic: false,
+        };
+        let (pools, summary) = load_pools(&loader, &fetcher, &[]);
+
+        assert_eq!(pools.iter().map(|pool| pool.pool_id).collect::<Vec<_>>(), vec![ids[0], ids[4]]);
+        assert_eq!(pools[0].slot, 10);
+        assert_eq!((summary.venue, summary.requested, summary.loaded), (PoolType::RayAmm, 7, 2));
+        let mut skipped = summary.skipped.clone();
+        skipped.sort_by_key(|(pool_id, _)| ids.iter().position(|id| id == pool_id));
+        assert_eq!(skipped, vec![
+            (ids[1], SkipReason::InvalidAccountData),
+            (ids[2], SkipReason::AccountNotFound),
+            (ids[3], SkipReason::InvalidPoolState),
+            (ids[5], SkipReason::MissingDependency),
+            (ids[6], SkipReason::FetchFailed),
+        ]);
+        assert!(summary.skipped_by_reason().values().all(|count| *count == 1));
+        assert_eq!(summary.skipped_by_reason().len(), 5);
+        assert_eq!(summary.to_string(), "ray_amm: requested 7, loaded 2, skipped 5, fetch_failed: 1, account_not_found: 1, \
+            invalid_account_data: 1, missing_dependency: 1, invalid_pool_state: 1");
+
+        //主账户一次、依赖账户一次，依赖只拉候选池子的
+        let calls = fetcher.calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, ids);
+        assert_eq!(calls[1].0, vec![dep_ok, dep_missing]);
+    }
+
+    #[test]
+    fn failed_venue_does_not_abort_loading() {
+        let pool_id = Pubkey::new_unique();
+        let fetcher: Arc<dyn AccountFetcher> = Arc::new(StubFetcher::new(&[(pool_id, [100, 200], 10)]));
+        let good = MockLoader { pool_ids: vec![pool_id], ..MockLoader::default() };
+        let bad = MockLoader { panic: true, ..MockLoader::default() };
+
+        let pools = join_loaders(vec![
+            LoaderHandle::spawn(bad, fetcher.clone(), vec![]),
+            LoaderHandle::spawn(good, fetcher.clone(), vec![]),
+        ]).unwrap();
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].borrow().get_pool_id(), pool_id);
+
+        //全部失败才报错
+        let bad = MockLoader { panic: true, ..MockLoader::default() };
+        assert!(join_loaders(vec![LoaderHandle::spawn(bad, fetcher.clone(), vec![])]).is_err());
+        assert!(join_loaders(vec![]).unwrap().is_empty());
+    }
+}
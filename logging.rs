@@ -0,0 +1,37 @@
+use tracing_subscriber::{
+    fmt, reload,
+    prelude::*,
+    EnvFilter, Registry,
+};
+use std::{error::Error, io, sync::OnceLock};
+
+use crate::config::{LogConfig, LogFormat};
+
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+//日志写 stderr，stdout 留给机会输出；filter 是 EnvFilter 语法，运行时可以用 set_filter 修改
+pub fn init(config: &LogConfig) -> Result<(), Box<dyn Error>> {
+    let filter = EnvFilter::try_new(&config.filter).map_err(|e| format!("log filter {:?}: {}", config.filter, e))?;
+    let (filter, handle) = reload::Layer::new(filter);
+    let subscriber = tracing_subscriber::registry().with(filter);
+    match config.format {
+        LogFormat::Text => subscriber
+            .with(fmt::layer().with_writer(io::stderr))
+            .try_init()?,
+        LogFormat::Json => subscriber
+            .with(fmt::layer().json().with_current_span(true).with_span_list(true).with_writer(io::stderr))
+            .try_init()?,
+    }
+    FILTER.set(handle).map_err(|_| "logging already initialized")?;
+    Ok(())
+}
+
+pub fn current_filter() -> Option<String> {
+    FILTER.get()?.with_current(|filter| filter.to_string()).ok()
+}
+
+pub fn set_filter(directives: &str) -> Result<(), Box<dyn Error>> {
+    let filter = EnvFilter::try_new(directives).map_err(|e| format!("log filter {:?}: {}", directives, e))?;
+    FILTER.get().ok_or("logging not initialized")?.reload(filter)?;
+    Ok(())
+}
@@ -0,0 +1,293 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    hash::Hash,
+    instruction::Instruction,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, VersionedTransaction},
+    message::{v0, VersionedMessage},
+    commitment_config::CommitmentConfig,
+    address_lookup_table::{
+        AddressLookupTableAccount,
+        state::AddressLookupTable,
+        instruction::{create_lookup_table, extend_lookup_table},
+    },
+};
+use serde::{Serialize, Deserialize};
+use std::{
+    error::Error, fs, sync::Arc,
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use tracing::info;
+use crate::{
+    loader::dedup,
+    rpc_pool::RpcPool,
+    registry::serde_pubkey,
+};
+
+pub const MAX_TABLE_ADDRESSES: usize = 256;
+//一笔 extend 交易放的地址数，受交易大小限制
+pub const EXTEND_CHUNK: usize = 20;
+
+struct Table {
+    account: AddressLookupTableAccount,
+    //只有自己是 authority 的表才能 extend
+    authority: Option<Pubkey>,
+}
+
+//新建的表记在 state 文件里，下次启动接着用，不用重新付租金
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TableState {
+    #[serde(with = "serde_pubkey::vec")]
+    pub created: Vec<Pubkey>,
+}
+
+impl TableState {
+    //文件不存在表示还没建过表
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("read lookup table state {}: {}", path.display(), e))?;
+        Ok(serde_json::from_str(&text)
+            .map_err(|e| format!("lookup table state {}: {}", path.display(), e))?)
+    }
+
+    //先写临时文件再 rename，写到一半退出不会把已有的记录弄坏
+    pub fn write(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .map_err(|e| format!("write lookup table state {}: {}", tmp.display(), e))?;
+        fs::rename(&tmp, path)
+            .map_err(|e| format!("write lookup table state {}: {}", path.display(), e))?;
+        Ok(())
+    }
+}
+
+//各池子的静态账户放进 lookup table，多跳 swap 编译成 v0 交易
+pub struct LookupTableManager {
+    rpc_client: Arc<RpcPool>,
+    tables: Vec<Table>,
+    //没有 state 文件时不新建表
+    state_file: Option<PathBuf>,
+    state: TableState,
+}
+
+impl LookupTableManager {
+    //配置里的表和 state 文件里之前新建的表一起加载
+    pub fn load(rpc_client: Arc<RpcPool>, addresses: &[Pubkey], state_file: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let state = match state_file {
+            Some(path) => TableState::read(path)?,
+            None => TableState::default(),
+        };
+        let mut manager = Self {
+            rpc_client,
+            tables: vec![],
+            state_file: state_file.map(Path::to_path_buf),
+            state,
+        };
+        for key in dedup(addresses.iter().chain(&manager.state.created).cloned()) {
+            let table = manager.fetch_table(&key)?;
+            manager.tables.push(table);
+        }
+        Ok(manager)
+    }
+
+    fn fetch_table(&self, key: &Pubkey) -> Result<Table, Box<dyn Error>> {
+        let account = self.rpc_client.call(|client| client.get_account(key))?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| format!("lookup table {}: {}", key, e))?;
+        Ok(Table {
+            account: AddressLookupTableAccount {
+                key: *key,
+                addresses: table.addresses.to_vec(),
+            },
+            authority: table.meta.authority,
+        })
+    }
+
+    pub fn tables(&self) -> Vec<AddressLookupTableAccount> {
+        self.tables.iter().map(|table| table.account.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.iter().map(|table| table.account.addresses.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn missing(&self, keys: impl IntoIterator<Item = Pubkey>) -> Vec<Pubkey> {
+        let covered = self.tables
+            .iter()
+            .flat_map(|table| table.account.addresses.iter())
+            .collect::<HashSet<_>>();
+        dedup(keys).into_iter().filter(|key| !covered.contains(key)).collect()
+    }
+
+    //把还不在任何表里的地址写进自己的表，表满了新建一张，返回新增的地址数
+    //新加的地址要等下一个 slot 才能在交易里使用
+    pub fn sync(&mut self, authority: &Keypair, keys: impl IntoIterator<Item = Pubkey>) -> Result<usize, Box<dyn Error>> {
+        let mut missing = self.missing(keys);
+        let added = missing.len();
+        let owner = authority.pubkey();
+        while !missing.is_empty() {
+            let idx = match self.tables
+                .iter()
+                .position(|table| table.authority == Some(owner) && table.account.addresses.len() < MAX_TABLE_ADDRESSES)
+            {
+                Some(idx) => idx,
+                None => self.create(authority)?,
+            };
+            let table = &self.tables[idx];
+            let room = MAX_TABLE_ADDRESSES - table.account.addresses.len();
+            let chunk = missing
+                .drain(..room.min(EXTEND_CHUNK).min(missing.len()))
+                .collect::<Vec<_>>();
+            let instruction = extend_lookup_table(table.account.key, owner, Some(owner), chunk.clone());
+            self.send(authority, &[instruction])?;
+            self.tables[idx].account.addresses.extend(chunk);
+        }
+        Ok(added)
+    }
+
+    fn create(&mut self, authority: &Keypair) -> Result<usize, Box<dyn Error>> {
+        let Some(state_file) = self.state_file.clone() else {
+            return Err("lookup tables are full, set execution.lookup_tables.state_file to create new ones".into());
+        };
+        let owner = authority.pubkey();
+        let recent_slot = self.rpc_client.call(|client| client.get_slot_with_commitment(CommitmentConfig::finalized()))?;
+        let (instruction, key) = create_lookup_table(owner, owner, recent_slot);
+        self.send(authority, &[instruction])?;
+        info!(table = %key, "created lookup table");
+        self.state.created.push(key);
+        self.state.write(&state_file)?;
+        self.tables.push(Table {
+            account: AddressLookupTableAccount { key, addresses: vec![] },
+            authority: Some(owner),
+        });
+        Ok(self.tables.len() - 1)
+    }
+
+    fn send(&self, authority: &Keypair, instructions: &[Instruction]) -> Result<(), Box<dyn Error>> {
+        let recent_blockhash = self.rpc_client.call(|client| client.get_latest_blockhash())?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&authority.pubkey()),
+            &[authority],
+            recent_blockhash,
+        );
+        self.rpc_client.call(|client| client.send_and_confirm_transaction(&transaction))?;
+        Ok(())
+    }
+}
+
+//表里没有的账户直接放在交易里
+pub fn compile_v0(
+    payer: &Keypair,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+    recent_blockhash: Hash,
+) -> Result<VersionedTransaction, Box<dyn Error>> {
+    let message = v0::Message::try_compile(&payer.pubkey(), instructions, lookup_tables, recent_blockhash)?;
+    Ok(VersionedTransaction::try_new(VersionedMessage::V0(message), &[payer])?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::{json, Value};
+    use solana_sdk::address_lookup_table::state::LookupTableMeta;
+    use std::{borrow::Cow, collections::HashMap, thread};
+    use crate::config::RpcConfig;
+
+    //getAccountInfo 替身，按请求的 key 返回 lookup table 账户
+    fn serve_tables(tables: HashMap<Pubkey, (Pubkey, Vec<Pubkey>)>) -> Arc<RpcPool> {
+        let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+        thread::spawn(move || {
+            for mut request in server.incoming_requests() {
+                let body: Value = serde_json::from_reader(request.as_reader()).unwrap();
+                //get_account 之前 RpcClient 会先查一次 getVersion
+                if body["method"] == "getVersion" {
+                    let response = json!({ "jsonrpc": "2.0", "id": body["id"], "result": { "solana-core": "1.18.26" } });
+                    let _ = request.respond(tiny_http::Response::from_string(response.to_string()));
+                    continue;
+                }
+                let key: Pubkey = body["params"][0].as_str().unwrap().parse().unwrap();
+                let value = tables.get(&key).map(|(authority, addresses)| {
+                    let data = AddressLookupTable {
+                        meta: LookupTableMeta { authority: Some(*authority), ..LookupTableMeta::default() },
+                        addresses: Cow::Borrowed(addresses),
+                    }.serialize_for_tests().unwrap();
+                    json!({
+                        "data": [BASE64.encode(&data), "base64"],
+                        "executable": false,
+                        "lamports": 1_000_000,
+                        "owner": solana_sdk::address_lookup_table::program::ID.to_string(),
+                        "rentEpoch": 0,
+                        "space": data.len(),
+                    })
+                });
+                let response = json!({ "jsonrpc": "2.0", "id": body["id"], "result": { "context": { "slot": 1 }, "value": value } });
+                let _ = request.respond(tiny_http::Response::from_string(response.to_string()));
+            }
+        });
+        let config = RpcConfig { url, ..RpcConfig::default() };
+        Arc::new(RpcPool::from_config(&config, CommitmentConfig::confirmed()).unwrap())
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lookup_tables.json");
+        assert_eq!(TableState::read(&path).unwrap(), TableState::default());
+
+        let state = TableState { created: vec![Pubkey::new_unique(), Pubkey::new_unique()] };
+        state.write(&path).unwrap();
+        assert_eq!(TableState::read(&path).unwrap(), state);
+        assert!(!path.with_extension("tmp").exists());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(TableState::read(&path).is_err());
+    }
+
+    #[test]
+    fn load_includes_tables_from_state_file() {
+        let authority = Keypair::new();
+        let (configured, created) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let rpc = serve_tables(HashMap::from([
+            (configured, (Pubkey::new_unique(), vec![a])),
+            (created, (authority.pubkey(), vec![b])),
+        ]));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lookup_tables.json");
+        TableState { created: vec![created] }.write(&path).unwrap();
+
+        //配置里也写了的表不重复加载
+        let manager = LookupTableManager::load(rpc.clone(), &[configured, created], Some(&path)).unwrap();
+        let keys = manager.tables().iter().map(|table| table.key).collect::<Vec<_>>();
+        assert_eq!(keys, vec![configured, created]);
+        assert_eq!(manager.len(), 2);
+        assert_eq!(manager.missing([a, b, c]), vec![c]);
+
+        let manager = LookupTableManager::load(rpc, &[configured], None).unwrap();
+        assert_eq!(manager.missing([a, b]), vec![b]);
+    }
+
+    #[test]
+    fn sync_without_state_file_does_not_create_tables() {
+        let authority = Keypair::new();
+        let configured = Pubkey::new_unique();
+        let rpc = serve_tables(HashMap::from([(configured, (Pubkey::new_unique(), vec![]))]));
+        let mut manager = LookupTableManager::load(rpc, &[configured], None).unwrap();
+        assert_eq!(manager.sync(&authority, []).unwrap(), 0);
+        let e = manager.sync(&authority, [Pubkey::new_unique()]).unwrap_err();
+        assert!(e.to_string().contains("state_file"), "{}", e);
+    }
+}
@@ -1,30 +1,35 @@
-#![allow(unused)]
-
 use solana_sdk::{
     pubkey::Pubkey,
-    instruction::Instruction,
+    instruction::{AccountMeta, Instruction},
     account::Account,
-    commitment_config::CommitmentConfig,
-    sysvar::clock::Clock,
-};
-use solana_client::{
-    rpc_client::RpcClient,
-    rpc_config::RpcSendTransactionConfig,
+    sysvar::clock::{self, Clock},
 };
-use anchor_client::{Cluster, Program};
-use anchor_client::anchor_lang::AccountDeserialize;
+use anchor_client::anchor_lang::{InstructionData, ToAccountMetas};
 use std::{collections::HashMap, fmt::Debug, error::Error, str::FromStr};
-use raydium_library::common;
-use crate::pool::PoolOperations;
+use tracing::debug;
+use crate::{
+    pool::{PoolOperations, SlotRange, deserialize_anchor_account, user_token_account},
+    registry::PoolType,
+    fetch::BatchFetch,
+    loader::{PoolLoader, SkipReason, dedup},
+};
 
 use meteora_dlmm_sdk::quote::{
-    SwapExactInQuote, quote_exact_in, get_bin_array_pubkeys_for_swap,
+    quote_exact_in, get_bin_array_pubkeys_for_swap,
 };
-use meteora_dlmm::state::{
-    lb_pair::LbPair, bin::BinArray, bin_array_bitmap_extension::BinArrayBitmapExtension, 
+use meteora_dlmm::{
+    state::{lb_pair::LbPair, bin::BinArray, bin_array_bitmap_extension::BinArrayBitmapExtension},
+    utils::pda::derive_bin_array_bitmap_extension,
 };
 
 pub const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+//swap 时作为 remaining accounts 传入的 bin array 个数
+pub const SWAP_BIN_ARRAY_COUNT: u8 = 3;
+
+pub fn event_authority() -> Pubkey {
+    let dlmm_program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).unwrap();
+    Pubkey::find_program_address(&[b"__event_authority"], &dlmm_program_id).0
+}
 
 #[derive(Debug)]
 pub struct MeteoraPool {
@@ -36,6 +41,7 @@ pub struct MeteoraPool {
     //pub right_bin_array_pubkeys: Vec<Pubkey>,
     pub bin_arrays: HashMap<Pubkey, BinArray>,
     pub clock: Clock,
+    pub account_slots: HashMap<Pubkey, u64>,
 }
 
 impl PoolOperations for MeteoraPool {
@@ -56,7 +62,7 @@ impl PoolOperations for MeteoraPool {
         ) {
             Ok(quote) => quote.amount_out,
             Err(e) => {
-                //eprintln!("meteora {} : {}", self.get_pool_id(), e);
+                debug!(venue = "meteora", pool_id = %self.pool_id, a_to_b, amount_in, error = %e, "quote failed");
                 0
             }
         }
@@ -69,4 +75,303 @@ impl PoolOperations for MeteoraPool {
     fn get_pool_id(&self) -> Pubkey {
         self.pool_id
     }
+
+    fn get_venue(&self) -> PoolType {
+        PoolType::Meteora
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut keys = vec![self.pool_id, self.bitmap_extension_key, clock::ID];
+        keys.extend(self.bin_arrays.keys().cloned());
+        keys
+    }
+
+    //clock 只影响波动费，不算报价变化
+    fn update(&mut self, pubkey: &Pubkey, account: &Account, slot: u64) -> bool {
+        if *pubkey != clock::ID {
+            self.account_slots.insert(*pubkey, slot);
+        }
+        if *pubkey == self.pool_id {
+            match deserialize_anchor_account::<LbPair>(account) {
+                Ok(lb_pair) => {
+                    self.lb_pair = lb_pair;
+                    true
+                }
+                Err(_) => false,
+            }
+        } else if *pubkey == self.bitmap_extension_key {
+            match deserialize_anchor_account::<BinArrayBitmapExtension>(account) {
+                Ok(bitmap_extension) => {
+                    self.bitmap_extension = Some(bitmap_extension);
+                    true
+                }
+                Err(_) => false,
+            }
+        } else if *pubkey == clock::ID {
+            if let Ok(clock) = bincode::deserialize::<Clock>(&account.data) {
+                self.clock = clock;
+            }
+            false
+        } else if self.bin_arrays.contains_key(pubkey) {
+            match deserialize_anchor_account::<BinArray>(account) {
+                Ok(bin_array) => {
+                    self.bin_arrays.insert(*pubkey, bin_array);
+                    true
+                }
+                Err(_) => false,
+            }
+        } else {
+            self.account_slots.remove(pubkey);
+            false
+        }
+    }
+
+    //clock 每个 slot 都变，不计入
+    fn slot_range(&self) -> Option<SlotRange> {
+        SlotRange::of_accounts(&self.accounts_to_watch(), &self.account_slots)
+    }
+
+    //a_to_b 即 x -> y，价格方向上的 bin array 放在 remaining accounts
+    fn swap_ix(
+        &self,
+        owner: &Pubkey,
+        a_to_b: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let dlmm_program_id = Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).unwrap();
+        let bin_arrays = get_bin_array_pubkeys_for_swap(
+            self.pool_id, &self.lb_pair, self.bitmap_extension.as_ref(), a_to_b, SWAP_BIN_ARRAY_COUNT,
+        ).map_err(|e| format!("meteora {}: {}", self.pool_id, e))?;
+        let (mint_in, mint_out) = if a_to_b {
+            (self.lb_pair.token_x_mint, self.lb_pair.token_y_mint)
+        } else {
+            (self.lb_pair.token_y_mint, self.lb_pair.token_x_mint)
+        };
+
+        let accounts = meteora_dlmm::accounts::Swap {
+            lb_pair: self.pool_id,
+            bin_array_bitmap_extension: self.bitmap_extension.is_some().then_some(self.bitmap_extension_key),
+            reserve_x: self.lb_pair.reserve_x,
+            reserve_y: self.lb_pair.reserve_y,
+            user_token_in: user_token_account(owner, &mint_in),
+            user_token_out: user_token_account(owner, &mint_out),
+            token_x_mint: self.lb_pair.token_x_mint,
+            token_y_mint: self.lb_pair.token_y_mint,
+            oracle: self.lb_pair.oracle,
+            host_fee_in: None,
+            user: *owner,
+            token_x_program: spl_token::ID,
+            token_y_program: spl_token::ID,
+            event_authority: event_authority(),
+            program: dlmm_program_id,
+        };
+        let mut account_metas = accounts.to_account_metas(None);
+        account_metas.extend(bin_arrays.into_iter().map(|key| AccountMeta::new(key, false)));
+        let data = meteora_dlmm::instruction::Swap {
+            amount_in,
+            min_amount_out,
+        };
+        Ok(vec![Instruction {
+            program_id: dlmm_program_id,
+            accounts: account_metas,
+            data: data.data(),
+        }])
+    }
+
+    //bitmap extension 大部分池子没有，有的时候才放进去
+    fn static_accounts(&self) -> Vec<Pubkey> {
+        let mut keys = vec![
+            self.pool_id,
+            self.lb_pair.reserve_x,
+            self.lb_pair.reserve_y,
+            self.lb_pair.token_x_mint,
+            self.lb_pair.token_y_mint,
+            self.lb_pair.oracle,
+            event_authority(),
+            Pubkey::from_str(METEORA_DLMM_PROGRAM_ID).unwrap(),
+            spl_token::ID,
+        ];
+        if self.bitmap_extension.is_some() {
+            keys.push(self.bitmap_extension_key);
+        }
+        keys
+    }
+}
+
+pub struct MeteoraLoader;
+
+impl PoolLoader for MeteoraLoader {
+    type Primary = LbPair;
+    type Pool = MeteoraPool;
+
+    fn venue(&self) -> PoolType {
+        PoolType::Meteora
+    }
+
+    fn decode_primary(&self, _pool_id: &Pubkey, account: &Account) -> Result<LbPair, SkipReason> {
+        deserialize_anchor_account::<LbPair>(account).map_err(|_| SkipReason::InvalidAccountData)
+    }
+
+    //发送交易指令时可能还要用到left/right
+    //bitmap extension 大部分池子没有，不存在时 build 里留空
+    fn dependents(&self, pool_id: &Pubkey, lb_pair: &LbPair) -> Result<Vec<Pubkey>, SkipReason> {
+        let left = get_bin_array_pubkeys_for_swap(*pool_id, lb_pair, None, true, 1)
+            .map_err(|_| SkipReason::InvalidPoolState)?;
+        let right = get_bin_array_pubkeys_for_swap(*pool_id, lb_pair, None, false, 1)
+            .map_err(|_| SkipReason::InvalidPoolState)?;
+        let (bitmap_extension_key, _bump) = derive_bin_array_bitmap_extension(*pool_id);
+        Ok(dedup(left.into_iter().chain(right).chain([bitmap_extension_key])))
+    }
+
+    fn shared_dependents(&self) -> Vec<Pubkey> {
+        vec![clock::ID]
+    }
+
+    fn build(
+        &self,
+        pool_id: Pubkey,
+        lb_pair: LbPair,
+        primary_slot: u64,
+        dependent_keys: Vec<Pubkey>,
+        dependents: &BatchFetch,
+    ) -> Result<MeteoraPool, SkipReason> {
+        let (clock_account, _) = dependents.get(&clock::ID).ok_or(SkipReason::MissingDependency)?;
+        let clock: Clock = bincode::deserialize(&clock_account.data).map_err(|_| SkipReason::MissingDependency)?;
+        let (bitmap_extension_key, _bump) = derive_bin_array_bitmap_extension(pool_id);
+        let mut account_slots = HashMap::from([(pool_id, primary_slot)]);
+        let bitmap_extension = dependents.get(&bitmap_extension_key).and_then(|(account, slot)| {
+            let bitmap_extension = deserialize_anchor_account::<BinArrayBitmapExtension>(account).ok()?;
+            account_slots.insert(bitmap_extension_key, slot);
+            Some(bitmap_extension)
+        });
+        let bin_arrays = dependent_keys
+            .into_iter()
+            .filter(|key| *key != bitmap_extension_key)
+            .filter_map(|key| {
+                let (account, slot) = dependents.get(&key)?;
+                let bin_array = deserialize_anchor_account::<BinArray>(account).ok()?;
+                account_slots.insert(key, slot);
+                Some((key, bin_array))
+            })
+            .collect::<HashMap<_, _>>();
+        if bin_arrays.is_empty() {
+            return Err(SkipReason::MissingDependency);
+        }
+        Ok(MeteoraPool {
+            pool_id,
+            lb_pair,
+            bitmap_extension_key,
+            bitmap_extension,
+            bin_arrays,
+            clock,
+            account_slots,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_client::anchor_lang::Discriminator;
+    use bytemuck::{Pod, Zeroable};
+    use crate::fetch::FetchedAccount;
+
+    fn anchor_account<T: Discriminator + Pod>(value: &T) -> Account {
+        let mut data = T::DISCRIMINATOR.to_vec();
+        data.extend_from_slice(bytemuck::bytes_of(value));
+        Account { data, ..Account::default() }
+    }
+
+    fn garbage() -> Account {
+        Account { data: vec![1, 2, 3], ..Account::default() }
+    }
+
+    fn meteora_pool(bin_array_key: Pubkey) -> MeteoraPool {
+        let pool_id = Pubkey::new_unique();
+        MeteoraPool {
+            pool_id,
+            lb_pair: LbPair::zeroed(),
+            bitmap_extension_key: derive_bin_array_bitmap_extension(pool_id).0,
+            bitmap_extension: None,
+            bin_arrays: HashMap::from([(bin_array_key, BinArray::zeroed())]),
+            clock: Clock::default(),
+            account_slots: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn update_keeps_state_on_bad_data() {
+        let bin_array_key = Pubkey::new_unique();
+        let mut pool = meteora_pool(bin_array_key);
+        let pool_id = pool.pool_id;
+
+        let mut lb_pair = LbPair::zeroed();
+        lb_pair.active_id = 5;
+        assert!(pool.update(&pool_id, &anchor_account(&lb_pair), 10));
+        assert_eq!(pool.lb_pair.active_id, 5);
+        assert!(!pool.update(&pool_id, &garbage(), 11));
+        assert_eq!(pool.lb_pair.active_id, 5);
+
+        //bitmap extension 解析失败不覆盖原来的
+        let bitmap_extension_key = pool.bitmap_extension_key;
+        assert!(pool.update(&bitmap_extension_key, &anchor_account(&BinArrayBitmapExtension::zeroed()), 12));
+        assert!(pool.bitmap_extension.is_some());
+        assert!(!pool.update(&bitmap_extension_key, &garbage(), 13));
+        assert!(pool.bitmap_extension.is_some());
+
+        let mut bin_array = BinArray::zeroed();
+        bin_array.index = 7;
+        assert!(pool.update(&bin_array_key, &anchor_account(&bin_array), 14));
+        assert_eq!(pool.bin_arrays[&bin_array_key].index, 7);
+        assert!(!pool.update(&bin_array_key, &garbage(), 15));
+        assert_eq!(pool.bin_arrays[&bin_array_key].index, 7);
+
+        //clock 只更新不算变化，也不记 slot
+        let clock = Clock { slot: 99, unix_timestamp: 1_700_000_000, ..Clock::default() };
+        let account = Account { data: bincode::serialize(&clock).unwrap(), ..Account::default() };
+        assert!(!pool.update(&clock::ID, &account, 16));
+        assert_eq!(pool.clock.slot, 99);
+        assert!(!pool.account_slots.contains_key(&clock::ID));
+
+        let unknown = Pubkey::new_unique();
+        assert!(!pool.update(&unknown, &anchor_account(&bin_array), 17));
+        assert!(!pool.account_slots.contains_key(&unknown));
+    }
+
+    fn batch(accounts: Vec<(Pubkey, Account)>) -> BatchFetch {
+        BatchFetch {
+            accounts: accounts
+                .into_iter()
+                .map(|(pubkey, account)| (pubkey, FetchedAccount { pubkey, account: Some(account), slot: 20 }))
+                .collect(),
+            failed: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn build_reads_bitmap_extension() {
+        let pool_id = Pubkey::new_unique();
+        let bin_array_key = Pubkey::new_unique();
+        let bitmap_extension_key = derive_bin_array_bitmap_extension(pool_id).0;
+        let clock = Account { data: bincode::serialize(&Clock::default()).unwrap(), ..Account::default() };
+        let dependent_keys = vec![bin_array_key, bitmap_extension_key];
+
+        let fetched = batch(vec![
+            (clock::ID, clock.clone()),
+            (bin_array_key, anchor_account(&BinArray::zeroed())),
+            (bitmap_extension_key, anchor_account(&BinArrayBitmapExtension::zeroed())),
+        ]);
+        let pool = MeteoraLoader.build(pool_id, LbPair::zeroed(), 10, dependent_keys.clone(), &fetched).unwrap();
+        assert!(pool.bitmap_extension.is_some());
+        assert_eq!(pool.bin_arrays.keys().collect::<Vec<_>>(), vec![&bin_array_key]);
+        assert_eq!(pool.account_slots.get(&bitmap_extension_key), Some(&20));
+        assert!(pool.static_accounts().contains(&bitmap_extension_key));
+
+        //没有 bitmap extension 的池子
+        let fetched = batch(vec![(clock::ID, clock), (bin_array_key, anchor_account(&BinArray::zeroed()))]);
+        let pool = MeteoraLoader.build(pool_id, LbPair::zeroed(), 10, dependent_keys, &fetched).unwrap();
+        assert!(pool.bitmap_extension.is_none());
+        assert!(!pool.static_accounts().contains(&bitmap_extension_key));
+    }
 }
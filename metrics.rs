@@ -0,0 +1,264 @@
+use prometheus::{
+    Encoder, TextEncoder, Registry,
+    Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+};
+use std::{
+    error::Error,
+    net::SocketAddr,
+    sync::OnceLock,
+    thread::{self, JoinHandle},
+};
+use tracing::{info, warn};
+
+use crate::logging;
+
+//进程内唯一一份，rpc 线程和主线程都直接更新
+pub struct Metrics {
+    registry: Registry,
+    pub pools_loaded: IntGaugeVec,
+    //加载时跳过的池子按 SkipReason，搜索时报价为 0 的是 zero_quote
+    pub error_pools: IntGaugeVec,
+    pub graph_nodes: IntGauge,
+    //有向边，每个池子两条
+    pub graph_edges: IntGauge,
+    pub search_duration: Histogram,
+    pub paths_explored: IntCounter,
+    pub opportunities_found: IntCounter,
+    pub opportunities_submitted: IntCounterVec,
+    //按最终状态: landed / failed / expired
+    pub opportunities_executed: IntCounterVec,
+    //找到了但没有提交的机会，按原因
+    pub opportunities_skipped: IntCounterVec,
+    pub rpc_latency: HistogramVec,
+    pub rpc_requests: IntCounterVec,
+    pub rpc_errors: IntCounterVec,
+    //收到账户更新时最新 slot 和更新所在 slot 的差
+    pub account_update_lag: Histogram,
+    //账户数据源断线重连的次数
+    pub source_reconnects: IntCounterVec,
+    //数据源拉取失败但没有退出的次数
+    pub source_errors: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("arb".to_string()), None)?;
+        let metrics = Self {
+            pools_loaded: IntGaugeVec::new(Opts::new("pools_loaded", "pools loaded per venue"), &["venue"])?,
+            error_pools: IntGaugeVec::new(Opts::new("error_pools", "pools excluded from search by reason"), &["reason"])?,
+            graph_nodes: IntGauge::new("graph_nodes", "token mints in the pool graph")?,
+            graph_edges: IntGauge::new("graph_edges", "directed pool edges in the pool graph")?,
+            search_duration: Histogram::with_opts(
+                HistogramOpts::new("search_duration_seconds", "duration of one search over all base mints")
+                    .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0]),
+            )?,
+            paths_explored: IntCounter::new("search_paths_explored_total", "hops quoted during search")?,
+            opportunities_found: IntCounter::new("opportunities_found_total", "profitable cycles found")?,
+            opportunities_submitted: IntCounterVec::new(
+                Opts::new("opportunities_submitted_total", "transactions handed to an executor"),
+                &["executor"],
+            )?,
+            opportunities_executed: IntCounterVec::new(
+                Opts::new("opportunities_executed_total", "submitted transactions by final status"),
+                &["executor", "status"],
+            )?,
+            opportunities_skipped: IntCounterVec::new(
+                Opts::new("opportunities_skipped_total", "opportunities not handed to an executor by reason"),
+                &["reason"],
+            )?,
+            rpc_latency: HistogramVec::new(
+                HistogramOpts::new("rpc_request_duration_seconds", "rpc request latency per endpoint")
+                    .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+                &["endpoint"],
+            )?,
+            rpc_requests: IntCounterVec::new(Opts::new("rpc_requests_total", "rpc requests per endpoint"), &["endpoint"])?,
+            rpc_errors: IntCounterVec::new(
+                Opts::new("rpc_errors_total", "failed rpc requests per endpoint and kind"),
+                &["endpoint", "kind"],
+            )?,
+            account_update_lag: Histogram::with_opts(
+                HistogramOpts::new("account_update_lag_slots", "slots between the latest slot and a streamed account update")
+                    .buckets(vec![0.0, 1.0, 2.0, 3.0, 5.0, 10.0, 20.0, 50.0, 100.0]),
+            )?,
+            source_reconnects: IntCounterVec::new(
+                Opts::new("source_reconnects_total", "account source reconnects per source"),
+                &["source"],
+            )?,
+            source_errors: IntCounterVec::new(
+                Opts::new("source_errors_total", "account source fetch errors per source"),
+                &["source"],
+            )?,
+            registry,
+        };
+        metrics.registry.register(Box::new(metrics.pools_loaded.clone()))?;
+        metrics.registry.register(Box::new(metrics.error_pools.clone()))?;
+        metrics.registry.register(Box::new(metrics.graph_nodes.clone()))?;
+        metrics.registry.register(Box::new(metrics.graph_edges.clone()))?;
+        metrics.registry.register(Box::new(metrics.search_duration.clone()))?;
+        metrics.registry.register(Box::new(metrics.paths_explored.clone()))?;
+        metrics.registry.register(Box::new(metrics.opportunities_found.clone()))?;
+        metrics.registry.register(Box::new(metrics.opportunities_submitted.clone()))?;
+        metrics.registry.register(Box::new(metrics.opportunities_executed.clone()))?;
+        metrics.registry.register(Box::new(metrics.opportunities_skipped.clone()))?;
+        metrics.registry.register(Box::new(metrics.rpc_latency.clone()))?;
+        metrics.registry.register(Box::new(metrics.rpc_requests.clone()))?;
+        metrics.registry.register(Box::new(metrics.rpc_errors.clone()))?;
+        metrics.registry.register(Box::new(metrics.account_update_lag.clone()))?;
+        metrics.registry.register(Box::new(metrics.source_reconnects.clone()))?;
+        metrics.registry.register(Box::new(metrics.source_errors.clone()))?;
+        Ok(metrics)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, prometheus::Error> {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| Metrics::new().expect("register metrics"))
+}
+
+//GET /metrics，其它路径 404
+pub fn serve(listen: &str) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    let server = tiny_http::Server::http(listen).map_err(|e| format!("metrics listen {}: {}", listen, e))?;
+    let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], TextEncoder::new().format_type().as_bytes())
+        .map_err(|_| "invalid content type header")?;
+    Ok(thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match (request.method(), request.url()) {
+                (tiny_http::Method::Get, "/metrics") => match metrics().encode() {
+                    Ok(body) => tiny_http::Response::from_data(body).with_header(content_type.clone()),
+                    Err(e) => tiny_http::Response::from_string(e.to_string()).with_status_code(500),
+                },
+                _ => tiny_http::Response::from_string("not found").with_status_code(404),
+            };
+            if let Err(e) = request.respond(response) {
+                warn!(error = %e, "metrics respond failed");
+            }
+        }
+    }))
+}
+
+//管理接口没有鉴权，只接受 loopback 地址
+pub fn loopback_addr(listen: &str) -> Result<SocketAddr, Box<dyn Error>> {
+    let addr: SocketAddr = listen.parse().map_err(|e| format!("invalid listen address {}: {}", listen, e))?;
+    if !addr.ip().is_loopback() {
+        return Err(format!("{} is not a loopback address", listen).into());
+    }
+    Ok(addr)
+}
+
+//GET /log 返回当前日志 filter，PUT /log 替换；其它路径 404
+pub fn serve_admin(listen: &str) -> Result<JoinHandle<()>, Box<dyn Error>> {
+    let addr = loopback_addr(listen)?;
+    let server = tiny_http::Server::http(addr).map_err(|e| format!("admin listen {}: {}", listen, e))?;
+    Ok(thread::spawn(move || {
+        for mut request in server.incoming_requests() {
+            let (method, url) = (request.method().clone(), request.url().to_string());
+            let response = match (&method, url.as_str()) {
+                (tiny_http::Method::Get, "/log") => tiny_http::Response::from_string(logging::current_filter().unwrap_or_default()),
+                (tiny_http::Method::Put, "/log") => {
+                    let mut body = String::new();
+                    let result = request.as_reader()
+                        .read_to_string(&mut body)
+                        .map_err(Box::<dyn Error>::from)
+                        .and_then(|_| logging::set_filter(body.trim()));
+                    match result {
+                        Ok(()) => {
+                            info!(filter = body.trim(), "log filter updated");
+                            tiny_http::Response::from_string(body.trim().to_string())
+                        }
+                        Err(e) => tiny_http::Response::from_string(e.to_string()).with_status_code(400),
+                    }
+                }
+                _ => tiny_http::Response::from_string("not found").with_status_code(404),
+            };
+            if let Err(e) = request.respond(response) {
+                warn!(error = %e, "admin respond failed");
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    //先占一个空闲端口再放开，拿到地址后交给 serve
+    fn free_addr() -> String {
+        TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string()
+    }
+
+    fn request(method: reqwest::Method, url: String) -> u16 {
+        reqwest::blocking::Client::new()
+            .request(method, url)
+            .body("debug")
+            .send()
+            .unwrap()
+            .status()
+            .as_u16()
+    }
+
+    #[test]
+    fn metrics_listener_has_no_log_endpoint() {
+        let listen = free_addr();
+        serve(&listen).unwrap();
+        assert_eq!(request(reqwest::Method::GET, format!("http://{}/metrics", listen)), 200);
+        assert_eq!(request(reqwest::Method::GET, format!("http://{}/log", listen)), 404);
+        assert_eq!(request(reqwest::Method::PUT, format!("http://{}/log", listen)), 404);
+    }
+
+    #[test]
+    fn admin_listener_is_loopback_only() {
+        assert!(loopback_addr("127.0.0.1:9101").is_ok());
+        assert!(loopback_addr("[::1]:9101").is_ok());
+        assert!(loopback_addr("0.0.0.0:9101").is_err());
+        assert!(loopback_addr("10.0.0.5:9101").is_err());
+        assert!(loopback_addr("localhost:9101").is_err());
+        assert!(serve_admin("0.0.0.0:0").is_err());
+
+        let listen = free_addr();
+        serve_admin(&listen).unwrap();
+        assert_eq!(request(reqwest::Method::GET, format!("http://{}/log", listen)), 200);
+        assert_eq!(request(reqwest::Method::GET, format!("http://{}/metrics", listen)), 404);
+    }
+
+    //全局的 metrics 各测试共用，这里只用别的代码不会用到的 label
+    #[test]
+    fn rendered_metrics_follow_executions() {
+        let listen = free_addr();
+        serve(&listen).unwrap();
+        metrics().opportunities_submitted.with_label_values(&["metrics-test"]).inc();
+        metrics().opportunities_executed.with_label_values(&["metrics-test", "landed"]).inc();
+        metrics().opportunities_executed.with_label_values(&["metrics-test", "expired"]).inc_by(2);
+        metrics().opportunities_skipped.with_label_values(&["metrics-test"]).inc();
+        metrics().rpc_latency.with_label_values(&["metrics-test"]).observe(0.03);
+        metrics().rpc_latency.with_label_values(&["metrics-test"]).observe(0.2);
+
+        let body = reqwest::blocking::get(format!("http://{}/metrics", listen)).unwrap().text().unwrap();
+        let value = |series: &str| -> f64 {
+            let line = body.lines().find(|line| line.starts_with(&format!("{} ", series)))
+                .unwrap_or_else(|| panic!("{} not in /metrics", series));
+            line.rsplit(' ').next().unwrap().parse().unwrap()
+        };
+        assert_eq!(value(r#"arb_opportunities_submitted_total{executor="metrics-test"}"#), 1.0);
+        assert_eq!(value(r#"arb_opportunities_executed_total{executor="metrics-test",status="landed"}"#), 1.0);
+        assert_eq!(value(r#"arb_opportunities_executed_total{executor="metrics-test",status="expired"}"#), 2.0);
+        assert_eq!(value(r#"arb_opportunities_skipped_total{reason="metrics-test"}"#), 1.0);
+
+        //bucket 是累计的: 0.03 落在 0.05，0.2 落在 0.25
+        let bucket = |le: &str| value(&format!(r#"arb_rpc_request_duration_seconds_bucket{{endpoint="metrics-test",le="{}"}}"#, le));
+        assert_eq!(bucket("0.025"), 0.0);
+        assert_eq!(bucket("0.05"), 1.0);
+        assert_eq!(bucket("0.1"), 1.0);
+        assert_eq!(bucket("0.25"), 2.0);
+        assert_eq!(bucket("+Inf"), 2.0);
+        assert_eq!(value(r#"arb_rpc_request_duration_seconds_count{endpoint="metrics-test"}"#), 2.0);
+        assert!((value(r#"arb_rpc_request_duration_seconds_sum{endpoint="metrics-test"}"#) - 0.23).abs() < 1e-9);
+    }
+}
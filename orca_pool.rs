@@ -1,29 +1,31 @@
-#![allow(unused)]
-
 use solana_sdk::{
     pubkey::Pubkey,
     instruction::Instruction,
     account::Account,
-    commitment_config::CommitmentConfig,
-};
-use solana_client::{
-    rpc_client::RpcClient,
-    rpc_config::RpcSendTransactionConfig,
 };
-use anchor_client::{Cluster, Program};
+use anchor_client::anchor_lang::{InstructionData, ToAccountMetas};
+use std::{str::FromStr, collections::HashMap, error::Error};
+use tracing::debug;
 
 use orca_whirlpools_core::{
     swap_quote_by_input_token, 
-    ExactInSwapQuote, TransferFee, WhirlpoolFacade, TickFacade, TickArrayFacade, TickArrays, 
+    WhirlpoolFacade, TickFacade, TickArrayFacade, TickArrays, 
 };
 use whirlpool_cpi::state::{
-    Tick, TickArray, Whirlpool, 
+    TickArray, Whirlpool, 
 };
 
-use crate::pool::PoolOperations;
+use crate::{
+    pool::{PoolOperations, SlotRange, deserialize_anchor_account, user_token_account},
+    registry::PoolType,
+    fetch::BatchFetch,
+    loader::{PoolLoader, SkipReason},
+};
 
 pub const ORCA_WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
 pub const TICK_ARRAY_COUNT: usize = 1; // 3
+//swap 指令固定要 3 个 tick array
+pub const SWAP_TICK_ARRAY_COUNT: usize = 3;
 
 #[derive(Debug)]
 pub struct OrcaPool {
@@ -42,18 +44,70 @@ pub struct OrcaPool {
     pub tick_array: Option<TickArray>,
     pub tick_array_key_b_a: Option<Pubkey>,
     pub tick_array_b_a: Option<TickArray>,
+    pub account_slots: HashMap<Pubkey, u64>,
 }
 
 impl OrcaPool {
-    pub fn get_tick_array_facade(&self, a_to_b: bool) -> TickArrayFacade {
-        let tick_array = if a_to_b  {
-            self.tick_array.as_ref().unwrap()
+    pub fn update_whirlpool(&mut self, whirlpool: &Whirlpool) {
+        self.tick_current_index = whirlpool.tick_current_index;
+        self.tick_spacing = whirlpool.tick_spacing;
+        self.fee_rate = whirlpool.fee_rate;
+        self.protocol_fee_rate = whirlpool.protocol_fee_rate;
+        self.liquidity = whirlpool.liquidity;
+        self.sqrt_price = whirlpool.sqrt_price;
+        self.reselect_tick_arrays();
+    }
+
+    pub fn required_tick_array_keys(&self) -> Option<(Pubkey, Option<Pubkey>)> {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        let key = *get_tick_array_pubkeys(self.tick_current_index, self.tick_spacing, true, &orca_program_id, &self.pool_id, TICK_ARRAY_COUNT).first()?;
+        let key_b_a = *get_tick_array_pubkeys(self.tick_current_index, self.tick_spacing, false, &orca_program_id, &self.pool_id, TICK_ARRAY_COUNT).first()?;
+        Some((key, (key_b_a != key).then_some(key_b_a)))
+    }
+
+    //价格移动到别的 tick array 后换成新的 key，已有的数据能复用就复用，
+    //没有的先置空，等数据源通过 accounts_to_watch 拿到新账户后 update 进来
+    pub fn reselect_tick_arrays(&mut self) -> bool {
+        let Some((key, key_b_a)) = self.required_tick_array_keys() else {
+            return false;
+        };
+        if key == self.tick_array_key && key_b_a == self.tick_array_key_b_a {
+            return false;
+        }
+        let mut cached = HashMap::new();
+        if let Some(tick_array) = self.tick_array.take() {
+            cached.insert(self.tick_array_key, tick_array);
+        }
+        if let (Some(old_key), Some(tick_array)) = (self.tick_array_key_b_a, self.tick_array_b_a.take()) {
+            cached.insert(old_key, tick_array);
+        }
+        self.tick_array_key = key;
+        self.tick_array = cached.remove(&key);
+        self.tick_array_key_b_a = key_b_a;
+        self.tick_array_b_a = key_b_a.and_then(|key| cached.remove(&key));
+        let watched = self.accounts_to_watch();
+        self.account_slots.retain(|key, _| watched.contains(key));
+        true
+    }
+
+    pub fn oracle_key(&self) -> Pubkey {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        Pubkey::find_program_address(&[b"oracle", self.pool_id.as_ref()], &orca_program_id).0
+    }
+
+    pub fn update_tick_array(&mut self, key: &Pubkey, tick_array: TickArray) {
+        if *key == self.tick_array_key {
+            self.tick_array = Some(tick_array);
+        } else if Some(*key) == self.tick_array_key_b_a {
+            self.tick_array_b_a = Some(tick_array);
+        }
+    }
+
+    pub fn get_tick_array_facade(&self, a_to_b: bool) -> Option<TickArrayFacade> {
+        let tick_array = if a_to_b || self.tick_array_key_b_a.is_none() {
+            self.tick_array.as_ref()?
         } else {
-            if let Some(ref tick_array_b_a) = self.tick_array_b_a {
-                tick_array_b_a
-            } else {
-                self.tick_array.as_ref().unwrap()
-            }
+            self.tick_array_b_a.as_ref()?
         };
         let ticks: Vec<TickFacade> = tick_array.ticks
             .iter()
@@ -68,10 +122,10 @@ impl OrcaPool {
                 }
             })
             .collect();
-        TickArrayFacade {
+        Some(TickArrayFacade {
             start_tick_index: tick_array.start_tick_index,
             ticks: ticks.try_into().unwrap(),
-        }
+        })
     }
 }
 
@@ -81,7 +135,10 @@ impl PoolOperations for OrcaPool {
         a_to_b: bool,
         amount_in: u64,
     ) -> u64 {
-        let tick_array_facade = self.get_tick_array_facade(a_to_b);
+        //新的 tick array 还没拿到
+        let Some(tick_array_facade) = self.get_tick_array_facade(a_to_b) else {
+            return 0;
+        };
         let whrilpool_facade = WhirlpoolFacade {
             tick_spacing: self.tick_spacing,
             tick_current_index: self.tick_current_index,
@@ -106,7 +163,7 @@ impl PoolOperations for OrcaPool {
         ) {
             Ok(quote) => quote.token_est_out,
             Err(e) => {
-                //eprintln!("orca {} : {}", self.get_pool_id(), e);
+                debug!(venue = "orca", pool_id = %self.pool_id, a_to_b, amount_in, error = %e, "quote failed");
                 0
             }
         } 
@@ -119,6 +176,167 @@ impl PoolOperations for OrcaPool {
     fn get_pool_id(&self) -> Pubkey {
         self.pool_id
     }
+
+    fn get_venue(&self) -> PoolType {
+        PoolType::Orca
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        let mut keys = vec![self.pool_id, self.tick_array_key];
+        keys.extend(self.tick_array_key_b_a);
+        keys
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: &Account, slot: u64) -> bool {
+        self.account_slots.insert(*pubkey, slot);
+        if *pubkey == self.pool_id {
+            match deserialize_anchor_account::<Whirlpool>(account) {
+                Ok(whirlpool) => {
+                    self.update_whirlpool(&whirlpool);
+                    true
+                }
+                Err(_) => false,
+            }
+        } else if *pubkey == self.tick_array_key || Some(*pubkey) == self.tick_array_key_b_a {
+            match deserialize_anchor_account::<TickArray>(account) {
+                Ok(tick_array) => {
+                    self.update_tick_array(pubkey, tick_array);
+                    true
+                }
+                Err(_) => false,
+            }
+        } else {
+            self.account_slots.remove(pubkey);
+            false
+        }
+    }
+
+    fn slot_range(&self) -> Option<SlotRange> {
+        SlotRange::of_accounts(&self.accounts_to_watch(), &self.account_slots)
+    }
+
+    //边界附近不足 3 个 tick array 时重复最后一个，合约里重复的账户按不存在处理
+    fn swap_ix(
+        &self,
+        owner: &Pubkey,
+        a_to_b: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        let mut tick_arrays = get_tick_array_pubkeys(
+            self.tick_current_index, self.tick_spacing, a_to_b, &orca_program_id, &self.pool_id, SWAP_TICK_ARRAY_COUNT,
+        );
+        let Some(&last) = tick_arrays.last() else {
+            return Err(format!("orca {}: no tick array for swap", self.pool_id).into());
+        };
+        tick_arrays.resize(SWAP_TICK_ARRAY_COUNT, last);
+
+        let accounts = whirlpool_cpi::accounts::Swap {
+            token_program: spl_token::ID,
+            token_authority: *owner,
+            whirlpool: self.pool_id,
+            token_owner_account_a: user_token_account(owner, &self.token_mint_a),
+            token_vault_a: self.token_vault_a,
+            token_owner_account_b: user_token_account(owner, &self.token_mint_b),
+            token_vault_b: self.token_vault_b,
+            tick_array_0: tick_arrays[0],
+            tick_array_1: tick_arrays[1],
+            tick_array_2: tick_arrays[2],
+            oracle: self.oracle_key(),
+        };
+        let data = whirlpool_cpi::instruction::Swap {
+            amount: amount_in,
+            other_amount_threshold: min_amount_out,
+            sqrt_price_limit: get_default_sqrt_price_limit(a_to_b),
+            amount_specified_is_input: true,
+            a_to_b,
+        };
+        Ok(vec![Instruction {
+            program_id: orca_program_id,
+            accounts: accounts.to_account_metas(None),
+            data: data.data(),
+        }])
+    }
+
+    fn static_accounts(&self) -> Vec<Pubkey> {
+        vec![
+            self.pool_id,
+            self.token_vault_a,
+            self.token_vault_b,
+            self.token_mint_a,
+            self.token_mint_b,
+            self.oracle_key(),
+            Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap(),
+            spl_token::ID,
+        ]
+    }
+}
+
+pub struct OrcaLoader;
+
+impl PoolLoader for OrcaLoader {
+    type Primary = Whirlpool;
+    type Pool = OrcaPool;
+
+    fn venue(&self) -> PoolType {
+        PoolType::Orca
+    }
+
+    fn decode_primary(&self, _pool_id: &Pubkey, account: &Account) -> Result<Whirlpool, SkipReason> {
+        deserialize_anchor_account::<Whirlpool>(account).map_err(|_| SkipReason::InvalidAccountData)
+    }
+
+    //a_to_b / b_a 目前都只取一个，相同时只有一个 key
+    fn dependents(&self, pool_id: &Pubkey, pool: &Whirlpool) -> Result<Vec<Pubkey>, SkipReason> {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        let key = get_tick_array_pubkeys(pool.tick_current_index, pool.tick_spacing, true, &orca_program_id, pool_id, TICK_ARRAY_COUNT);
+        let key_b_a = get_tick_array_pubkeys(pool.tick_current_index, pool.tick_spacing, false, &orca_program_id, pool_id, TICK_ARRAY_COUNT);
+        match (key.first(), key_b_a.first()) {
+            (Some(&key), Some(&key_b_a)) if key != key_b_a => Ok(vec![key, key_b_a]),
+            (Some(&key), Some(_)) => Ok(vec![key]),
+            _ => Err(SkipReason::InvalidPoolState),
+        }
+    }
+
+    fn build(
+        &self,
+        pool_id: Pubkey,
+        pool: Whirlpool,
+        primary_slot: u64,
+        dependent_keys: Vec<Pubkey>,
+        dependents: &BatchFetch,
+    ) -> Result<OrcaPool, SkipReason> {
+        let tick_array_key = dependent_keys[0];
+        let tick_array_key_b_a = dependent_keys.get(1).cloned();
+        let mut account_slots = HashMap::from([(pool_id, primary_slot)]);
+        let mut lookup = |key: &Pubkey| {
+            let (account, slot) = dependents.get(key)?;
+            let tick_array = deserialize_anchor_account::<TickArray>(account).ok()?;
+            account_slots.insert(*key, slot);
+            Some(tick_array)
+        };
+        let tick_array = lookup(&tick_array_key).ok_or(SkipReason::MissingDependency)?;
+        let tick_array_b_a = tick_array_key_b_a.as_ref().and_then(&mut lookup);
+        Ok(OrcaPool {
+            pool_id,
+            tick_current_index: pool.tick_current_index,
+            tick_spacing: pool.tick_spacing,
+            fee_rate: pool.fee_rate,
+            protocol_fee_rate: pool.protocol_fee_rate,
+            liquidity: pool.liquidity,
+            sqrt_price: pool.sqrt_price,
+            token_mint_a: pool.token_mint_a,
+            token_mint_b: pool.token_mint_b,
+            token_vault_a: pool.token_vault_a,
+            token_vault_b: pool.token_vault_b,
+            tick_array_key,
+            tick_array: Some(tick_array),
+            tick_array_key_b_a,
+            tick_array_b_a,
+            account_slots,
+        })
+    }
 }
 
 
@@ -163,6 +381,7 @@ pub fn get_tick_array_pubkeys(
     a_to_b: bool,
     orca_program_id: &Pubkey,
     pool_id: &Pubkey,
+    count: usize,
 ) -> Vec<Pubkey> {
     let tick_spacing = tick_spacing as i32;
     let ticks_in_array = TICK_ARRAY_SIZE * tick_spacing;
@@ -191,7 +410,7 @@ pub fn get_tick_array_pubkeys(
     
     start_tick_indexes
         .iter()
-        .take(TICK_ARRAY_COUNT) 
+        .take(count)
         .map(|start_tick_index| {
             Pubkey::find_program_address(
                 &[
@@ -203,4 +422,172 @@ pub fn get_tick_array_pubkeys(
             ).0
         })
         .collect::<Vec<Pubkey>>()
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_client::anchor_lang::Discriminator;
+    use std::{rc::Rc, cell::RefCell};
+    use crate::{pool::PoolRef, stream::{AccountUpdate, WatchIndex}};
+
+    const WHIRLPOOL_LEN: usize = 653;
+    const TICK_ARRAY_LEN: usize = 9988;
+
+    //只填 update_whirlpool 用到的字段，偏移按 anchor 布局（含 8 字节 discriminator）
+    fn whirlpool_account(tick_current_index: i32, tick_spacing: u16) -> Account {
+        let mut data = vec![0; WHIRLPOOL_LEN];
+        data[..8].copy_from_slice(&Whirlpool::DISCRIMINATOR);
+        data[41..43].copy_from_slice(&tick_spacing.to_le_bytes());
+        data[45..47].copy_from_slice(&3000u16.to_le_bytes());
+        data[49..65].copy_from_slice(&1_000_000u128.to_le_bytes());
+        data[65..81].copy_from_slice(&(1u128 << 64).to_le_bytes());
+        data[81..85].copy_from_slice(&tick_current_index.to_le_bytes());
+        Account { data, ..Account::default() }
+    }
+
+    fn tick_array_account(start_tick_index: i32) -> Account {
+        let mut data = vec![0; TICK_ARRAY_LEN];
+        data[..8].copy_from_slice(&TickArray::DISCRIMINATOR);
+        data[8..12].copy_from_slice(&start_tick_index.to_le_bytes());
+        Account { data, ..Account::default() }
+    }
+
+    fn tick_array_key(pool_id: &Pubkey, start_tick_index: i32) -> Pubkey {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        Pubkey::find_program_address(&[b"tick_array", pool_id.as_ref(), start_tick_index.to_string().as_bytes()], &orca_program_id).0
+    }
+
+    fn keys(pool_id: &Pubkey, start_tick_indexes: &[i32]) -> Vec<Pubkey> {
+        start_tick_indexes.iter().map(|&start| tick_array_key(pool_id, start)).collect()
+    }
+
+    fn orca_pool(tick_current_index: i32, tick_spacing: u16) -> OrcaPool {
+        let mut pool = OrcaPool {
+            pool_id: Pubkey::new_unique(),
+            tick_current_index,
+            tick_spacing,
+            fee_rate: 3000,
+            protocol_fee_rate: 0,
+            liquidity: 1_000_000,
+            sqrt_price: 1 << 64,
+            token_mint_a: Pubkey::new_unique(),
+            token_mint_b: Pubkey::new_unique(),
+            token_vault_a: Pubkey::new_unique(),
+            token_vault_b: Pubkey::new_unique(),
+            tick_array_key: Pubkey::default(),
+            tick_array: None,
+            tick_array_key_b_a: None,
+            tick_array_b_a: None,
+            account_slots: HashMap::new(),
+        };
+        pool.reselect_tick_arrays();
+        pool
+    }
+
+    fn tick_array_pubkeys(pool_id: &Pubkey, tick_current_index: i32, tick_spacing: u16, a_to_b: bool) -> Vec<Pubkey> {
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        get_tick_array_pubkeys(tick_current_index, tick_spacing, a_to_b, &orca_program_id, pool_id, SWAP_TICK_ARRAY_COUNT)
+    }
+
+    #[test]
+    fn tick_array_pubkeys_by_direction() {
+        let pool_id = Pubkey::new_unique();
+        let orca_program_id = Pubkey::from_str(ORCA_WHIRLPOOL_PROGRAM_ID).unwrap();
+        //tick_spacing 64，一个 tick array 覆盖 5632 个 tick
+        assert_eq!(tick_array_pubkeys(&pool_id, 100, 64, true), keys(&pool_id, &[0, -5632, -11264]));
+        assert_eq!(tick_array_pubkeys(&pool_id, 100, 64, false), keys(&pool_id, &[0, 5632, 11264]));
+        assert_eq!(get_tick_array_pubkeys(100, 64, true, &orca_program_id, &pool_id, 1), keys(&pool_id, &[0]));
+
+        //离上边界不到一个 tick_spacing，b -> a 从下一个 array 开始
+        assert_eq!(tick_array_pubkeys(&pool_id, 5568, 64, true), keys(&pool_id, &[0, -5632, -11264]));
+        assert_eq!(tick_array_pubkeys(&pool_id, 5568, 64, false), keys(&pool_id, &[5632, 11264, 16896]));
+        //正好在 array 起点
+        assert_eq!(tick_array_pubkeys(&pool_id, 5632, 64, true), keys(&pool_id, &[5632, 0, -5632]));
+        assert_eq!(tick_array_pubkeys(&pool_id, 5632, 64, false), keys(&pool_id, &[5632, 11264, 16896]));
+
+        //负 tick 向下取整，tick_spacing 8 时一个 array 704 个 tick
+        assert_eq!(tick_array_pubkeys(&pool_id, -1, 8, true), keys(&pool_id, &[-704, -1408, -2112]));
+        assert_eq!(tick_array_pubkeys(&pool_id, -1, 8, false), keys(&pool_id, &[0, 704, 1408]));
+        assert_eq!(tick_array_pubkeys(&pool_id, -704, 8, true), keys(&pool_id, &[-704, -1408, -2112]));
+        assert_eq!(tick_array_pubkeys(&pool_id, -800, 8, false), keys(&pool_id, &[-1408, -704, 0]));
+        //-705 在 -1408 的 array 里，但离上边界不到一个 tick_spacing
+        assert_eq!(tick_array_pubkeys(&pool_id, -705, 8, false), keys(&pool_id, &[-704, 0, 704]));
+
+        //最低的 array 下面没有了，不足 3 个
+        assert_eq!(tick_array_pubkeys(&pool_id, MIN_TICK_INDEX, 64, true), keys(&pool_id, &[-444928]));
+    }
+
+    #[test]
+    fn reselect_on_tick_array_boundary() {
+        let mut pool = orca_pool(100, 64);
+        let pool_id = pool.pool_id;
+        let [lower, zero, upper] = [-5632, 0, 5632].map(|start| tick_array_key(&pool_id, start));
+        assert_eq!(pool.required_tick_array_keys(), Some((zero, None)));
+        assert_eq!(pool.accounts_to_watch(), vec![pool_id, zero]);
+        assert!(pool.update(&zero, &tick_array_account(0), 10));
+
+        //同一个 array 里移动不用换
+        pool.tick_current_index = 200;
+        assert!(!pool.reselect_tick_arrays());
+
+        //靠近上边界：a -> b 不变，b -> a 换到上一个 array，已有的数据保留
+        assert!(pool.update(&pool_id, &whirlpool_account(5568, 64), 11));
+        assert_eq!(pool.accounts_to_watch(), vec![pool_id, zero, upper]);
+        assert!(pool.tick_array.is_some() && pool.tick_array_b_a.is_none());
+        assert!(pool.update(&upper, &tick_array_account(5632), 12));
+        assert!(pool.tick_array_b_a.is_some());
+
+        //往上越过边界：两个方向都是 upper，之前 b -> a 的数据挪到 tick_array
+        assert!(pool.update(&pool_id, &whirlpool_account(5700, 64), 13));
+        assert_eq!(pool.accounts_to_watch(), vec![pool_id, upper]);
+        assert_eq!(pool.tick_array.as_ref().map(|tick_array| tick_array.start_tick_index), Some(5632));
+        assert!(pool.account_slots.contains_key(&upper) && !pool.account_slots.contains_key(&zero));
+
+        //往下越过两个 array：都要重新拿，拿到之前报价为 0
+        assert!(pool.update(&pool_id, &whirlpool_account(-10, 64), 14));
+        assert_eq!(pool.accounts_to_watch(), vec![pool_id, lower, zero]);
+        assert!(pool.tick_array.is_none() && pool.tick_array_b_a.is_none());
+        assert_eq!(pool.calc_quote(true, 1_000), 0);
+        //不再关注的 array 更新不进来
+        assert!(!pool.update(&upper, &tick_array_account(5632), 15));
+        assert!(!pool.account_slots.contains_key(&upper));
+    }
+
+    #[test]
+    fn update_keeps_state_on_bad_data() {
+        let mut pool = orca_pool(100, 64);
+        let (pool_id, key) = (pool.pool_id, pool.tick_array_key);
+        assert!(pool.update(&key, &tick_array_account(0), 10));
+        let garbage = Account { data: vec![1, 2, 3], ..Account::default() };
+        assert!(!pool.update(&pool_id, &garbage, 11));
+        assert!(!pool.update(&key, &garbage, 12));
+        assert_eq!(pool.tick_current_index, 100);
+        assert!(pool.tick_array.is_some());
+        assert!(pool.update(&pool_id, &whirlpool_account(150, 64), 13));
+        assert_eq!(pool.tick_current_index, 150);
+    }
+
+    #[test]
+    fn watch_index_follows_tick_arrays() {
+        let pool = Rc::new(RefCell::new(orca_pool(-1, 8)));
+        let pool_id = pool.borrow().pool_id;
+        let [lower, zero, further] = [-704, 0, -1408].map(|start| tick_array_key(&pool_id, start));
+        let pool_ref: PoolRef = pool.clone();
+        let mut index = WatchIndex::new();
+        index.add_pool(&pool_ref);
+        let mut keys = index.keys();
+        keys.sort();
+        let mut expected = vec![pool_id, lower, zero];
+        expected.sort();
+        assert_eq!(keys, expected);
+
+        //-1 -> -705：a -> b 换到 further，b -> a 从 zero 换到 lower
+        let update = AccountUpdate { pubkey: pool_id, account: whirlpool_account(-705, 8), slot: 20 };
+        let result = index.apply(&update);
+        assert_eq!(result.touched, vec![pool_id]);
+        assert_eq!(result.watch, vec![further]);
+        assert_eq!(result.unwatch, vec![zero]);
+        assert_eq!(pool.borrow().required_tick_array_keys(), Some((further, Some(lower))));
+        assert!(index.contains(&further) && !index.contains(&zero));
+    }
+}
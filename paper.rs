@@ -0,0 +1,340 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Keypair,
+};
+use std::{
+    fmt, error::Error,
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    arb::{Arbitrager, Opportunity, quote_path},
+    config::{PaperConfig, PaperFill},
+    execution::CheckedTransaction,
+    executor::{Executor, Submission, SubmissionStatus, accepted_profit},
+    flash_loan::FlashLoan,
+    pool::PoolRef,
+};
+
+//虚拟余额，手续费记在 native mint（SOL）下
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    initial: HashMap<Pubkey, i128>,
+    pub balances: HashMap<Pubkey, i128>,
+    pub fills: usize,
+    pub failures: usize,
+}
+
+impl Ledger {
+    pub fn new(initial: HashMap<Pubkey, u64>) -> Self {
+        let initial = initial.into_iter().map(|(mint, amount)| (mint, amount as i128)).collect::<HashMap<_, _>>();
+        Self {
+            balances: initial.clone(),
+            initial,
+            fills: 0,
+            failures: 0,
+        }
+    }
+
+    pub fn balance(&self, mint: &Pubkey) -> i128 {
+        self.balances.get(mint).copied().unwrap_or(0)
+    }
+
+    pub fn apply(&mut self, mint: &Pubkey, delta: i128) {
+        *self.balances.entry(*mint).or_default() += delta;
+    }
+
+    pub fn pnl(&self, mint: &Pubkey) -> i128 {
+        self.balance(mint) - self.initial.get(mint).copied().unwrap_or(0)
+    }
+}
+
+impl fmt::Display for Ledger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "paper ledger: {} fills, {} failures", self.fills, self.failures)?;
+        let mut mints = self.balances.keys().collect::<Vec<_>>();
+        mints.sort();
+        for mint in mints {
+            write!(f, "\n    {}: balance {}, pnl {}", mint, self.balance(mint), self.pnl(mint))?;
+        }
+        Ok(())
+    }
+}
+
+struct PendingFill {
+    opportunity: Opportunity,
+    fee: u64,
+    //模拟出的起始 token 余额变化
+    simulated: Option<i128>,
+    ready_at: Instant,
+}
+
+//和真实 executor 同一个接口，但什么都不发送：提交时记下机会，查询状态时按配置成交并记账
+pub struct PaperExecutor {
+    config: PaperConfig,
+    //和 Arbitrager 共享同一批池子，流模式下会被更新，重新报价拿到的是之后 slot 的状态
+    pools: HashMap<Pubkey, PoolRef>,
+    flash_loan: Option<FlashLoan>,
+    pending: RefCell<HashMap<String, PendingFill>>,
+    ledger: RefCell<Ledger>,
+    next_id: Cell<u64>,
+}
+
+impl PaperExecutor {
+    pub fn new(config: PaperConfig, arbitrager: &Arbitrager, initial: HashMap<Pubkey, u64>) -> Self {
+        Self {
+            config,
+            pools: arbitrager.pools.clone(),
+            flash_loan: arbitrager.flash_loan.clone(),
+            pending: RefCell::new(HashMap::new()),
+            ledger: RefCell::new(Ledger::new(initial)),
+            next_id: Cell::new(0),
+        }
+    }
+
+    pub fn ledger(&self) -> Ledger {
+        self.ledger.borrow().clone()
+    }
+
+    fn borrowed(&self, mint: &Pubkey) -> bool {
+        self.flash_loan.as_ref().is_some_and(|flash_loan| flash_loan.reserve(mint).is_some())
+    }
+
+    fn flash_loan_fee(&self, mint: &Pubkey, amount: u64) -> u64 {
+        self.flash_loan
+            .as_ref()
+            .and_then(|flash_loan| flash_loan.reserve(mint))
+            .map_or(0, |reserve| reserve.fee(amount))
+    }
+
+    //返回起始 token 的余额变化；最后一跳拿不回本金时交易会失败
+    fn fill(&self, pending: &PendingFill) -> Result<i128, String> {
+        let opportunity = &pending.opportunity;
+        match self.config.fill {
+            PaperFill::Simulation => pending.simulated.ok_or_else(|| "no simulated balance change".to_string()),
+            PaperFill::Requote => {
+                let init = opportunity.init_balance();
+                let amounts = quote_path(&self.pools, opportunity, init).ok_or("requote failed")?;
+                let final_balance = amounts[amounts.len() - 1];
+                if final_balance < init {
+                    return Err(format!("requoted {} below input {}", final_balance, init));
+                }
+                Ok(final_balance as i128 - init as i128 - self.flash_loan_fee(&opportunity.mint_path[0], init) as i128)
+            }
+        }
+    }
+
+    fn latest_slot(&self, opportunity: &Opportunity) -> u64 {
+        opportunity.pool_path
+            .iter()
+            .filter_map(|pool_id| self.pools.get(pool_id)?.borrow().slot_range())
+            .map(|range| range.max)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl Executor for PaperExecutor {
+    fn name(&self) -> &str {
+        "paper"
+    }
+
+    fn submit(&self, checked: &CheckedTransaction, _payer: &Keypair) -> Result<Submission, Box<dyn Error>> {
+        if self.config.fill == PaperFill::Simulation {
+            accepted_profit(checked)?;
+        }
+        let opportunity = &checked.opportunity;
+        let start_mint = &opportunity.mint_path[0];
+        let available = self.ledger.borrow().balance(start_mint);
+        if !self.borrowed(start_mint) && available < opportunity.init_balance() as i128 {
+            return Err(format!("virtual balance {} of {} is below input {}", available, start_mint, opportunity.init_balance()).into());
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let id = format!("paper-{}", id);
+        self.pending.borrow_mut().insert(id.clone(), PendingFill {
+            opportunity: opportunity.clone(),
+            fee: checked.fee,
+            simulated: checked.simulation.realized_profit(),
+            ready_at: Instant::now() + Duration::from_millis(self.config.fill_delay_ms),
+        });
+        Ok(Submission {
+            id,
+            signature: checked.transaction.signatures[0],
+            tip: 0,
+            submitted_at: Instant::now(),
+        })
+    }
+
+    //失败的交易也要付手续费
+    fn status(&self, submission: &Submission) -> Result<SubmissionStatus, Box<dyn Error>> {
+        let mut pending = self.pending.borrow_mut();
+        let fill = pending.get(&submission.id).ok_or_else(|| format!("unknown paper submission {}", submission.id))?;
+        if Instant::now() < fill.ready_at {
+            return Ok(SubmissionStatus::Pending);
+        }
+        let fill = pending.remove(&submission.id).unwrap();
+        let mut ledger = self.ledger.borrow_mut();
+        ledger.apply(&spl_token::native_mint::ID, -(fill.fee as i128));
+        Ok(match self.fill(&fill) {
+            Ok(delta) => {
+                ledger.apply(&fill.opportunity.mint_path[0], delta);
+                ledger.fills += 1;
+                SubmissionStatus::Landed { slot: self.latest_slot(&fill.opportunity) }
+            }
+            Err(e) => {
+                ledger.failures += 1;
+                SubmissionStatus::Failed(e)
+            }
+        })
+    }
+
+    fn is_live(&self) -> bool {
+        false
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(self.ledger.borrow().to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{
+        account::Account,
+        hash::Hash,
+        signature::Signer,
+        system_instruction, system_program,
+        transaction::{Transaction, VersionedTransaction},
+    };
+    use crate::{
+        execution::{ComputeBudget, SimulationResult, Verdict},
+        pool::mock::MockPool,
+        registry::PoolType,
+    };
+
+    const WSOL: Pubkey = spl_token::native_mint::ID;
+    const FEE: u64 = 5_000;
+
+    //WSOL -> a -> WSOL，1_000 进去报价 1_097 回来
+    fn round_trip() -> (Arbitrager, Opportunity) {
+        let a = Pubkey::new_unique();
+        let pools = vec![
+            MockPool::new(Pubkey::new_unique(), [WSOL, a], [1_000_000, 2_000_000]).into_ref(),
+            MockPool::new(Pubkey::new_unique(), [a, WSOL], [2_000_000, 1_100_000]).into_ref(),
+        ];
+        let pool_path = pools.iter().map(|pool| pool.borrow().get_pool_id()).collect();
+        let arbitrager = Arbitrager::new(pools, 2, 0, 0, None);
+        let opportunity = Opportunity {
+            path: vec![0, 1, 0],
+            mint_path: vec![WSOL, a, WSOL],
+            pool_path,
+            venues: vec![PoolType::RayAmm; 2],
+            amounts: vec![1_000, 1_998, 1_097],
+            slot_range: None,
+        };
+        (arbitrager, opportunity)
+    }
+
+    fn checked(opportunity: &Opportunity, verdict: Verdict, simulated: Option<(u64, u64)>) -> CheckedTransaction {
+        let payer = Keypair::new();
+        let transaction = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(&payer.pubkey(), &system_program::ID, 1)],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::new_unique(),
+        );
+        CheckedTransaction {
+            transaction: VersionedTransaction::from(transaction),
+            simulation: SimulationResult {
+                pre_balance: simulated.map(|(pre, _)| pre),
+                post_balance: simulated.map(|(_, post)| post),
+                ..SimulationResult::default()
+            },
+            compute_budget: ComputeBudget::default(),
+            fee: FEE,
+            verdict,
+            expected_profit: opportunity.profit(),
+            opportunity: opportunity.clone(),
+        }
+    }
+
+    fn executor(arbitrager: &Arbitrager, fill: PaperFill, fill_delay_ms: u64) -> PaperExecutor {
+        let config = PaperConfig { fill, fill_delay_ms, ..PaperConfig::default() };
+        PaperExecutor::new(config, arbitrager, HashMap::from([(WSOL, 1_000_000)]))
+    }
+
+    #[test]
+    fn ledger_tracks_balance_and_pnl() {
+        let other = Pubkey::new_unique();
+        let mut ledger = Ledger::new(HashMap::from([(WSOL, 100)]));
+        ledger.apply(&WSOL, -30);
+        ledger.apply(&other, 7);
+        assert_eq!(ledger.balance(&WSOL), 70);
+        assert_eq!(ledger.pnl(&WSOL), -30);
+        assert_eq!(ledger.balance(&other), 7);
+        assert_eq!(ledger.pnl(&other), 7);
+        assert_eq!(ledger.balance(&Pubkey::new_unique()), 0);
+        assert!(ledger.to_string().contains(&format!("{}: balance 70, pnl -30", WSOL)));
+    }
+
+    #[test]
+    fn requote_fill_books_profit_and_fee() {
+        let (arbitrager, opportunity) = round_trip();
+        let executor = executor(&arbitrager, PaperFill::Requote, 0);
+        //requote 不看模拟结果
+        let submission = executor.submit(&checked(&opportunity, Verdict::Failed("err".into()), None), &Keypair::new()).unwrap();
+        assert_eq!(submission.id, "paper-0");
+        assert_eq!(executor.status(&submission).unwrap(), SubmissionStatus::Landed { slot: 0 });
+
+        let ledger = executor.ledger();
+        assert_eq!((ledger.fills, ledger.failures), (1, 0));
+        assert_eq!(ledger.pnl(&WSOL), 97 - FEE as i128);
+        //已经结算过的不能再查
+        assert!(executor.status(&submission).is_err());
+    }
+
+    #[test]
+    fn reverted_fill_only_pays_fee() {
+        let (arbitrager, opportunity) = round_trip();
+        let executor = executor(&arbitrager, PaperFill::Requote, 0);
+        let submission = executor.submit(&checked(&opportunity, Verdict::Accept { profit: 97 }, None), &Keypair::new()).unwrap();
+        //提交之后第二个池子的价格变了，回来的不够本金
+        let pool = &arbitrager.pools[&opportunity.pool_path[1]];
+        let account = Account { data: MockPool::data([2_000_000, 900_000]), ..Default::default() };
+        assert!(pool.borrow_mut().update(&opportunity.pool_path[1], &account, 5));
+        assert!(matches!(executor.status(&submission).unwrap(), SubmissionStatus::Failed(_)));
+
+        let ledger = executor.ledger();
+        assert_eq!((ledger.fills, ledger.failures), (0, 1));
+        assert_eq!(ledger.pnl(&WSOL), -(FEE as i128));
+    }
+
+    #[test]
+    fn simulation_fill_uses_simulated_change() {
+        let (arbitrager, opportunity) = round_trip();
+        let executor = executor(&arbitrager, PaperFill::Simulation, 0);
+        let payer = Keypair::new();
+        assert!(executor.submit(&checked(&opportunity, Verdict::Failed("err".into()), Some((0, 0))), &payer).is_err());
+
+        let submission = executor.submit(&checked(&opportunity, Verdict::Accept { profit: 45 }, Some((1_000, 1_050))), &payer).unwrap();
+        assert_eq!(executor.status(&submission).unwrap(), SubmissionStatus::Landed { slot: 0 });
+        assert_eq!(executor.ledger().pnl(&WSOL), 50 - FEE as i128);
+    }
+
+    #[test]
+    fn fill_waits_for_delay_and_balance() {
+        let (arbitrager, mut opportunity) = round_trip();
+        let executor = executor(&arbitrager, PaperFill::Requote, 60_000);
+        let submission = executor.submit(&checked(&opportunity, Verdict::Accept { profit: 97 }, None), &Keypair::new()).unwrap();
+        assert_eq!(executor.status(&submission).unwrap(), SubmissionStatus::Pending);
+        assert_eq!(executor.ledger().pnl(&WSOL), 0);
+
+        //虚拟余额不够本金，又没有 flash loan
+        opportunity.amounts[0] = 1_000_001;
+        assert!(executor.submit(&checked(&opportunity, Verdict::Accept { profit: 97 }, None), &Keypair::new()).is_err());
+    }
+}
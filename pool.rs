@@ -1,16 +1,17 @@
-#![allow(unused)]
-
 use solana_sdk::{
     pubkey::Pubkey,
     account::Account,
     instruction::Instruction,
 };
-use anchor_client::{Cluster, Program};
+use anchor_client::anchor_lang::{AccountDeserialize, Result as AnchorResult};
+use spl_associated_token_account::get_associated_token_address;
 use std::{
-    str::FromStr, fmt::Debug, 
+    fmt::Debug, 
     rc::Rc, cell::RefCell,
     collections::HashMap,
+    error::Error,
 };
+use crate::registry::PoolType;
 
 pub trait PoolOperations: Debug {
     fn calc_quote(
@@ -21,22 +22,86 @@ pub trait PoolOperations: Debug {
 
     fn get_mints(&self) -> Vec<Pubkey>;
     fn get_pool_id(&self) -> Pubkey; //test
-    /*fn swap_ix(
+    fn get_venue(&self) -> PoolType;
+
+    //报价依赖的所有账户，任何数据源拿到这些账户的新数据后调用 update
+    fn accounts_to_watch(&self) -> Vec<Pubkey>;
+    //返回 false 表示账户无法解析，或者这个账户的变化不影响报价
+    //slot 是拿到这个账户数据时的 context slot
+    fn update(&mut self, pubkey: &Pubkey, account: &Account, slot: u64) -> bool;
+    //报价依赖的账户各自的 slot 范围，没有 slot 信息时返回 None
+    fn slot_range(&self) -> Option<SlotRange>;
+    //按当前状态生成一跳 swap 的指令，输入输出用 owner 的 ATA
+    fn swap_ix(
         &self,
-        program: &Program<C>,
         owner: &Pubkey,
-        mint_in: &Pubkey,
-        mint_out: &Pubkey,
-    ) -> Vec<Instruction>;*/
+        a_to_b: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>>;
+    //swap 用到的、不随价格变化的账户（池子、vault、market、oracle、程序），放进 lookup table
+    fn static_accounts(&self) -> Vec<Pubkey>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotRange {
+    pub min: u64,
+    pub max: u64,
+}
+
+impl SlotRange {
+    pub fn new(slot: u64) -> Self {
+        Self { min: slot, max: slot }
+    }
+
+    pub fn merge(self, other: SlotRange) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn spread(&self) -> u64 {
+        self.max - self.min
+    }
+
+    pub fn of_accounts(keys: &[Pubkey], account_slots: &HashMap<Pubkey, u64>) -> Option<Self> {
+        keys.iter()
+            .filter_map(|key| account_slots.get(key))
+            .map(|&slot| SlotRange::new(slot))
+            .reduce(SlotRange::merge)
+    }
+}
+
+//anchor 账户带 8 字节 discriminator，orca / meteora 共用，不再依赖 raydium_library
+pub fn deserialize_anchor_account<T: AccountDeserialize>(account: &Account) -> AnchorResult<T> {
+    let mut data: &[u8] = &account.data;
+    T::try_deserialize(&mut data)
+}
+
+pub fn user_token_account(owner: &Pubkey, mint: &Pubkey) -> Pubkey {
+    get_associated_token_address(owner, mint)
+}
+
+//token 账户（spl token / token-2022）前 64 字节是 mint 和 owner，后面是 amount
+pub fn token_account_amount(data: &[u8]) -> Option<u64> {
+    data.get(64..72)?.try_into().ok().map(u64::from_le_bytes)
 }
 
+pub type PoolRef = Rc<RefCell<dyn PoolOperations>>;
 
 #[derive(Debug, Clone)]
-pub struct PoolEdge(pub HashMap<usize, Vec<Rc<Box<dyn PoolOperations>>>>);
+pub struct PoolEdge(pub HashMap<usize, Vec<PoolRef>>);
 
 #[derive(Debug)]
 pub struct PoolGraph(pub HashMap<usize, PoolEdge>);
 
+impl Default for PoolGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PoolGraph {
     pub fn new() -> Self {
         Self(HashMap::new())
@@ -46,14 +111,99 @@ impl PoolGraph {
         &mut self, 
         idx0: usize, 
         idx1: usize, 
-        pool: Rc<Box<dyn PoolOperations>>,
+        pool: PoolRef,
     ) {
         let edges = self.0
             .entry(idx0)
             .or_insert_with(|| PoolEdge(HashMap::new()));
         let pools = edges.0
             .entry(idx1)
-            .or_insert_with(|| vec![]);
+            .or_default();
         pools.push(pool);
     }
-}
\ No newline at end of file
+}
+//各模块测试共用的恒定乘积池，没有手续费
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use solana_sdk::instruction::AccountMeta;
+
+    //主账户数据是两个 le u64 的储备量
+    #[derive(Debug)]
+    pub struct MockPool {
+        pub pool_id: Pubkey,
+        pub mints: [Pubkey; 2],
+        pub amounts: [u64; 2],
+        pub slot: u64,
+    }
+
+    impl MockPool {
+        pub fn new(pool_id: Pubkey, mints: [Pubkey; 2], amounts: [u64; 2]) -> Self {
+            Self { pool_id, mints, amounts, slot: 0 }
+        }
+
+        pub fn into_ref(self) -> PoolRef {
+            Rc::new(RefCell::new(self))
+        }
+
+        pub fn data(amounts: [u64; 2]) -> Vec<u8> {
+            amounts.iter().flat_map(|amount| amount.to_le_bytes()).collect()
+        }
+    }
+
+    impl PoolOperations for MockPool {
+        fn calc_quote(&self, a_to_b: bool, amount_in: u64) -> u64 {
+            let (reserve_in, reserve_out) = if a_to_b {
+                (self.amounts[0], self.amounts[1])
+            } else {
+                (self.amounts[1], self.amounts[0])
+            };
+            (reserve_out as u128 * amount_in as u128 / (reserve_in as u128 + amount_in as u128)) as u64
+        }
+
+        fn get_mints(&self) -> Vec<Pubkey> {
+            self.mints.to_vec()
+        }
+
+        fn get_pool_id(&self) -> Pubkey {
+            self.pool_id
+        }
+
+        fn get_venue(&self) -> PoolType {
+            PoolType::RayAmm
+        }
+
+        fn accounts_to_watch(&self) -> Vec<Pubkey> {
+            vec![self.pool_id]
+        }
+
+        fn update(&mut self, pubkey: &Pubkey, account: &Account, slot: u64) -> bool {
+            if *pubkey != self.pool_id || account.data.len() != 16 {
+                return false;
+            }
+            self.amounts = [0, 8].map(|offset| u64::from_le_bytes(account.data[offset..offset + 8].try_into().unwrap()));
+            self.slot = slot;
+            true
+        }
+
+        fn slot_range(&self) -> Option<SlotRange> {
+            Some(SlotRange::new(self.slot))
+        }
+
+        //program_id 是池子 id，data 是 a_to_b | amount_in | min_amount_out，方便检查指令顺序和参数
+        fn swap_ix(&self, owner: &Pubkey, a_to_b: bool, amount_in: u64, min_amount_out: u64) -> Result<Vec<Instruction>, Box<dyn Error>> {
+            let mut data = vec![a_to_b as u8];
+            data.extend_from_slice(&amount_in.to_le_bytes());
+            data.extend_from_slice(&min_amount_out.to_le_bytes());
+            Ok(vec![Instruction {
+                program_id: self.pool_id,
+                accounts: vec![AccountMeta::new_readonly(*owner, true)],
+                data,
+            }])
+        }
+
+        fn static_accounts(&self) -> Vec<Pubkey> {
+            vec![self.pool_id]
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program,
+};
+
+use crate::guard_address;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardInstruction {
+    //创建 owner 的 guard 账户，只需要一次
+    Init,
+    //记下 token 账户当前余额
+    Start,
+    //要求余额 >= Start 时 + min_profit
+    Check { min_profit: u64 },
+}
+
+impl GuardInstruction {
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        let (&tag, rest) = data.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+        Ok(match tag {
+            0 => Self::Init,
+            1 => Self::Start,
+            2 => {
+                let min_profit = rest
+                    .get(..8)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(u64::from_le_bytes)
+                    .ok_or(ProgramError::InvalidInstructionData)?;
+                Self::Check { min_profit }
+            }
+            _ => return Err(ProgramError::InvalidInstructionData),
+        })
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        match self {
+            Self::Init => vec![0],
+            Self::Start => vec![1],
+            Self::Check { min_profit } => {
+                let mut data = vec![2];
+                data.extend_from_slice(&min_profit.to_le_bytes());
+                data
+            }
+        }
+    }
+}
+
+pub fn init(program_id: &Pubkey, owner: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new(*owner, true),
+            AccountMeta::new(guard_address(program_id, owner).0, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: GuardInstruction::Init.pack(),
+    }
+}
+
+fn guarded(program_id: &Pubkey, owner: &Pubkey, token_account: &Pubkey, instruction: GuardInstruction) -> Instruction {
+    Instruction {
+        program_id: *program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(*owner, true),
+            AccountMeta::new(guard_address(program_id, owner).0, false),
+            AccountMeta::new_readonly(*token_account, false),
+        ],
+        data: instruction.pack(),
+    }
+}
+
+pub fn start(program_id: &Pubkey, owner: &Pubkey, token_account: &Pubkey) -> Instruction {
+    guarded(program_id, owner, token_account, GuardInstruction::Start)
+}
+
+pub fn check(program_id: &Pubkey, owner: &Pubkey, token_account: &Pubkey, min_profit: u64) -> Instruction {
+    guarded(program_id, owner, token_account, GuardInstruction::Check { min_profit })
+}
@@ -0,0 +1,182 @@
+//交易开头记下 token 账户余额，结尾检查 end >= start + min_profit，不满足整笔交易回滚
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    entrypoint::ProgramResult,
+    msg,
+    program::{invoke, invoke_signed},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    pubkey,
+    rent::Rent,
+    system_instruction, system_program,
+    sysvar::Sysvar,
+};
+
+pub mod instruction;
+
+use instruction::GuardInstruction;
+
+#[cfg(not(feature = "no-entrypoint"))]
+solana_program::entrypoint!(process_instruction);
+
+pub const GUARD_SEED: &[u8] = b"guard";
+//owner(32) token_account(32) start_amount(8)
+pub const GUARD_LEN: usize = 72;
+
+const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuardError {
+    //结束余额不到 start + min_profit
+    InsufficientProfit = 0,
+    //没有先执行 Start，或者 Start 记的不是这个 token 账户
+    NotStarted = 1,
+    InvalidGuard = 2,
+    InvalidTokenAccount = 3,
+}
+
+impl From<GuardError> for ProgramError {
+    fn from(e: GuardError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+pub fn guard_address(program_id: &Pubkey, owner: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[GUARD_SEED, owner.as_ref()], program_id)
+}
+
+struct GuardState {
+    owner: Pubkey,
+    token_account: Pubkey,
+    start_amount: u64,
+}
+
+impl GuardState {
+    fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.len() < GUARD_LEN {
+            return Err(GuardError::InvalidGuard.into());
+        }
+        Ok(Self {
+            owner: Pubkey::try_from(&data[0..32]).unwrap(),
+            token_account: Pubkey::try_from(&data[32..64]).unwrap(),
+            start_amount: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+        })
+    }
+
+    fn pack(&self, data: &mut [u8]) {
+        data[0..32].copy_from_slice(self.owner.as_ref());
+        data[32..64].copy_from_slice(self.token_account.as_ref());
+        data[64..72].copy_from_slice(&self.start_amount.to_le_bytes());
+    }
+}
+
+pub fn process_instruction(program_id: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+    match GuardInstruction::unpack(data)? {
+        GuardInstruction::Init => process_init(program_id, accounts),
+        GuardInstruction::Start => process_start(program_id, accounts),
+        GuardInstruction::Check { min_profit } => process_check(program_id, accounts, min_profit),
+    }
+}
+
+//每个 owner 一个 guard 账户，已经存在时什么都不做
+//不用 create_account：任何人都能先往 PDA 转 lamports，create_account 遇到有余额的地址会失败
+fn process_init(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let iter = &mut accounts.iter();
+    let owner = next_account_info(iter)?;
+    let guard = next_account_info(iter)?;
+    let system = next_account_info(iter)?;
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    let (key, bump) = guard_address(program_id, owner.key);
+    if *guard.key != key || *system.key != system_program::ID {
+        return Err(GuardError::InvalidGuard.into());
+    }
+    if guard.owner == program_id {
+        return Ok(());
+    }
+    //只补租金的差额，已经够了就不转
+    let shortfall = Rent::get()?.minimum_balance(GUARD_LEN).saturating_sub(guard.lamports());
+    if shortfall > 0 {
+        invoke(
+            &system_instruction::transfer(owner.key, guard.key, shortfall),
+            &[owner.clone(), guard.clone(), system.clone()],
+        )?;
+    }
+    let seeds: &[&[u8]] = &[GUARD_SEED, owner.key.as_ref(), &[bump]];
+    invoke_signed(
+        &system_instruction::allocate(guard.key, GUARD_LEN as u64),
+        &[guard.clone(), system.clone()],
+        &[seeds],
+    )?;
+    invoke_signed(
+        &system_instruction::assign(guard.key, program_id),
+        &[guard.clone(), system.clone()],
+        &[seeds],
+    )?;
+    GuardState {
+        owner: *owner.key,
+        token_account: Pubkey::default(),
+        start_amount: 0,
+    }
+    .pack(&mut guard.try_borrow_mut_data()?);
+    Ok(())
+}
+
+//Start / Check 的账户: owner(signer), guard(writable), token_account
+fn load<'a, 'b>(
+    program_id: &Pubkey,
+    accounts: &'b [AccountInfo<'a>],
+) -> Result<(&'b AccountInfo<'a>, GuardState, u64), ProgramError> {
+    let iter = &mut accounts.iter();
+    let owner = next_account_info(iter)?;
+    let guard = next_account_info(iter)?;
+    let token_account = next_account_info(iter)?;
+    if !owner.is_signer {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+    if guard.owner != program_id {
+        return Err(GuardError::InvalidGuard.into());
+    }
+    let state = GuardState::unpack(&guard.try_borrow_data()?)?;
+    if state.owner != *owner.key {
+        return Err(GuardError::InvalidGuard.into());
+    }
+    Ok((guard, state, token_amount(token_account)?))
+}
+
+//spl token 和 token-2022 的 amount 都在 64..72
+fn token_amount(token_account: &AccountInfo) -> Result<u64, ProgramError> {
+    if *token_account.owner != TOKEN_PROGRAM_ID && *token_account.owner != TOKEN_2022_PROGRAM_ID {
+        return Err(GuardError::InvalidTokenAccount.into());
+    }
+    let data = token_account.try_borrow_data()?;
+    let amount = data.get(64..72).ok_or(GuardError::InvalidTokenAccount)?;
+    Ok(u64::from_le_bytes(amount.try_into().unwrap()))
+}
+
+fn process_start(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let (guard, mut state, amount) = load(program_id, accounts)?;
+    state.token_account = *accounts[2].key;
+    state.start_amount = amount;
+    state.pack(&mut guard.try_borrow_mut_data()?);
+    Ok(())
+}
+
+//检查通过后清掉记录，没有 Start 的 Check 一定失败
+fn process_check(program_id: &Pubkey, accounts: &[AccountInfo], min_profit: u64) -> ProgramResult {
+    let (guard, mut state, amount) = load(program_id, accounts)?;
+    if state.token_account == Pubkey::default() || state.token_account != *accounts[2].key {
+        return Err(GuardError::NotStarted.into());
+    }
+    let required = state.start_amount.saturating_add(min_profit);
+    if amount < required {
+        msg!("profit guard: start {}, end {}, required {}", state.start_amount, amount, required);
+        return Err(GuardError::InsufficientProfit.into());
+    }
+    state.token_account = Pubkey::default();
+    state.start_amount = 0;
+    state.pack(&mut guard.try_borrow_mut_data()?);
+    Ok(())
+}
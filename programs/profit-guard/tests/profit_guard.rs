@@ -0,0 +1,199 @@
+use profit_guard::{GuardError, GUARD_LEN, guard_address, instruction};
+use solana_program_test::{processor, BanksClientError, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    instruction::{AccountMeta, Instruction, InstructionError},
+    pubkey,
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+    system_instruction,
+    transaction::{Transaction, TransactionError},
+};
+
+const TOKEN_PROGRAM_ID: Pubkey = pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+
+//程序只看 owner 和 64..72 的 amount，其它字段留空
+fn token_account(amount: u64, owner: Pubkey) -> Account {
+    let mut data = vec![0; 165];
+    data[64..72].copy_from_slice(&amount.to_le_bytes());
+    Account {
+        lamports: 2_039_280,
+        data,
+        owner,
+        executable: false,
+        rent_epoch: 0,
+    }
+}
+
+struct Guard {
+    context: ProgramTestContext,
+    program_id: Pubkey,
+    token: Pubkey,
+}
+
+impl Guard {
+    async fn start(amount: u64) -> Self {
+        let program_id = Pubkey::new_unique();
+        let token = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new("profit_guard", program_id, processor!(profit_guard::process_instruction));
+        program_test.add_account(token, token_account(amount, TOKEN_PROGRAM_ID));
+        let context = program_test.start_with_context().await;
+        Self { context, program_id, token }
+    }
+
+    fn owner(&self) -> Keypair {
+        self.context.payer.insecure_clone()
+    }
+
+    //每笔交易用新的 blockhash，同样的指令可以重复发
+    async fn send(&mut self, instructions: &[Instruction], signers: &[&Keypair]) -> Result<(), BanksClientError> {
+        let blockhash = self.context.get_new_latest_blockhash().await.unwrap();
+        let mut all_signers = vec![&self.context.payer];
+        all_signers.extend_from_slice(signers);
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &all_signers,
+            blockhash,
+        );
+        self.context.banks_client.process_transaction(transaction).await
+    }
+
+    async fn init(&mut self) -> Result<(), BanksClientError> {
+        let owner = self.owner();
+        self.send(&[instruction::init(&self.program_id, &owner.pubkey())], &[]).await
+    }
+
+    async fn guard_start(&mut self, token: Pubkey) -> Result<(), BanksClientError> {
+        let owner = self.owner();
+        self.send(&[instruction::start(&self.program_id, &owner.pubkey(), &token)], &[]).await
+    }
+
+    async fn guard_check(&mut self, token: Pubkey, min_profit: u64) -> Result<(), BanksClientError> {
+        let owner = self.owner();
+        self.send(&[instruction::check(&self.program_id, &owner.pubkey(), &token, min_profit)], &[]).await
+    }
+
+    //两笔交易之间改 token 账户余额，模拟 swap 的结果
+    fn set_amount(&mut self, amount: u64) {
+        self.context.set_account(&self.token, &token_account(amount, TOKEN_PROGRAM_ID).into());
+    }
+
+    async fn guard_account(&mut self) -> Option<Account> {
+        let (guard, _) = guard_address(&self.program_id, &self.owner().pubkey());
+        self.context.banks_client.get_account(guard).await.unwrap()
+    }
+}
+
+fn custom_error(result: Result<(), BanksClientError>) -> GuardError {
+    let error = result.unwrap_err().unwrap();
+    match error {
+        TransactionError::InstructionError(_, InstructionError::Custom(code)) => match code {
+            0 => GuardError::InsufficientProfit,
+            1 => GuardError::NotStarted,
+            2 => GuardError::InvalidGuard,
+            3 => GuardError::InvalidTokenAccount,
+            _ => panic!("unexpected error code {}", code),
+        },
+        _ => panic!("unexpected error {:?}", error),
+    }
+}
+
+#[tokio::test]
+async fn init_start_check() {
+    let mut guard = Guard::start(1_000).await;
+    guard.init().await.unwrap();
+    let account = guard.guard_account().await.unwrap();
+    assert_eq!(account.owner, guard.program_id);
+    assert_eq!(account.data.len(), GUARD_LEN);
+    //再 init 一次什么都不做
+    guard.init().await.unwrap();
+
+    let token = guard.token;
+    guard.guard_start(token).await.unwrap();
+    guard.set_amount(1_500);
+    guard.guard_check(token, 500).await.unwrap();
+
+    //Check 通过后记录清空，没有 Start 的 Check 失败
+    assert_eq!(custom_error(guard.guard_check(token, 0).await), GuardError::NotStarted);
+}
+
+#[tokio::test]
+async fn check_below_start_amount_fails() {
+    let mut guard = Guard::start(1_000).await;
+    guard.init().await.unwrap();
+    let token = guard.token;
+    guard.guard_start(token).await.unwrap();
+    guard.set_amount(1_400);
+    assert_eq!(custom_error(guard.guard_check(token, 500).await), GuardError::InsufficientProfit);
+    guard.set_amount(900);
+    assert_eq!(custom_error(guard.guard_check(token, 0).await), GuardError::InsufficientProfit);
+}
+
+#[tokio::test]
+async fn wrong_owner_is_rejected() {
+    let mut guard = Guard::start(1_000).await;
+    guard.init().await.unwrap();
+    let other = Keypair::new();
+    let (owner_guard, _) = guard_address(&guard.program_id, &guard.owner().pubkey());
+    //别人签名、拿 owner 的 guard 账户
+    let start = Instruction {
+        program_id: guard.program_id,
+        accounts: vec![
+            AccountMeta::new_readonly(other.pubkey(), true),
+            AccountMeta::new(owner_guard, false),
+            AccountMeta::new_readonly(guard.token, false),
+        ],
+        data: instruction::GuardInstruction::Start.pack(),
+    };
+    assert_eq!(custom_error(guard.send(&[start], &[&other]).await), GuardError::InvalidGuard);
+
+    //自己的 guard 还没 init
+    let start = instruction::start(&guard.program_id, &other.pubkey(), &guard.token);
+    assert_eq!(custom_error(guard.send(&[start], &[&other]).await), GuardError::InvalidGuard);
+}
+
+#[tokio::test]
+async fn wrong_token_account_is_rejected() {
+    let mut guard = Guard::start(1_000).await;
+    guard.init().await.unwrap();
+    let other_token = Pubkey::new_unique();
+    guard.context.set_account(&other_token, &token_account(5_000, TOKEN_PROGRAM_ID).into());
+    let not_token = Pubkey::new_unique();
+    guard.context.set_account(&not_token, &token_account(5_000, Pubkey::new_unique()).into());
+
+    let token = guard.token;
+    guard.guard_start(token).await.unwrap();
+    //Check 的 token 账户和 Start 记的不一样
+    assert_eq!(custom_error(guard.guard_check(other_token, 0).await), GuardError::NotStarted);
+    //不属于 token 程序的账户
+    assert_eq!(custom_error(guard.guard_start(not_token).await), GuardError::InvalidTokenAccount);
+    assert_eq!(custom_error(guard.guard_check(not_token, 0).await), GuardError::InvalidTokenAccount);
+}
+
+#[tokio::test]
+async fn init_with_prefunded_guard() {
+    let mut guard = Guard::start(1_000).await;
+    let owner = guard.owner();
+    let (address, _) = guard_address(&guard.program_id, &owner.pubkey());
+    //先往 PDA 转一笔：够空账户免租（890_880），不够 GUARD_LEN 的租金
+    guard.send(&[system_instruction::transfer(&owner.pubkey(), &address, 1_000_000)], &[]).await.unwrap();
+    guard.init().await.unwrap();
+    let account = guard.guard_account().await.unwrap();
+    assert_eq!(account.owner, guard.program_id);
+    assert_eq!(account.data.len(), GUARD_LEN);
+
+    //转的已经超过租金，不再补
+    let mut guard = Guard::start(1_000).await;
+    let owner = guard.owner();
+    let (address, _) = guard_address(&guard.program_id, &owner.pubkey());
+    guard.send(&[system_instruction::transfer(&owner.pubkey(), &address, 10_000_000)], &[]).await.unwrap();
+    guard.init().await.unwrap();
+    let account = guard.guard_account().await.unwrap();
+    assert_eq!(account.owner, guard.program_id);
+    assert_eq!(account.lamports, 10_000_000);
+
+    let token = guard.token;
+    guard.guard_start(token).await.unwrap();
+    guard.guard_check(token, 0).await.unwrap();
+}
@@ -1,26 +1,59 @@
-#![allow(unused)]
-
 use solana_sdk::{
     pubkey::Pubkey,
     instruction::Instruction,
     account::Account,
 };
-use solana_client::{
-    rpc_client::RpcClient,
-    rpc_config::RpcSendTransactionConfig,
-};
-use anchor_client::{Cluster, Program};
 use std::{collections::HashMap, fmt::Debug, error::Error, str::FromStr};
+use tracing::debug;
 
-use crate::pool::PoolOperations;
+use crate::{
+    pool::{PoolOperations, SlotRange, user_token_account},
+    registry::PoolType,
+    fetch::BatchFetch,
+    loader::{PoolLoader, SkipReason},
+};
 
-use raydium_library::amm;
+use raydium_library::{amm, common};
 use raydium_amm::{
     math::{SwapDirection, Calculator}, 
-    state::AmmInfo,
+    state::{AmmInfo, Loadable},
 };
 
 pub const RAY_AMM_PROGRAM_ID: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8"; 
+const AMM_AUTHORITY_SEED: &[u8] = b"amm authority";
+
+//swap 指令需要的 openbook market 账户，报价用不到
+#[derive(Debug, Clone)]
+pub struct MarketKeys {
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+}
+
+impl MarketKeys {
+    //MarketState 前面有 5 字节 "serum" 填充，字段偏移按 serum dex v3 的布局
+    pub fn parse(market: &Pubkey, market_program: &Pubkey, data: &[u8]) -> Option<Self> {
+        let key_at = |offset: usize| -> Option<Pubkey> {
+            Pubkey::try_from(data.get(offset..offset + 32)?).ok()
+        };
+        if key_at(13)? != *market {
+            return None;
+        }
+        let nonce: [u8; 8] = data.get(45..53)?.try_into().ok()?;
+        let vault_signer = Pubkey::create_program_address(&[market.as_ref(), &nonce], market_program).ok()?;
+        Some(Self {
+            coin_vault: key_at(117)?,
+            pc_vault: key_at(165)?,
+            event_queue: key_at(253)?,
+            bids: key_at(285)?,
+            asks: key_at(317)?,
+            vault_signer,
+        })
+    }
+}
 
 #[derive(Debug)]
 pub struct RayAmmPool {
@@ -28,10 +61,75 @@ pub struct RayAmmPool {
     pub amm_state: AmmInfo,
     pub coin_vault_amount: u64,
     pub pc_vault_amount: u64,
-    //其中包括amm_authority需要计算，其他的都在amm_state中获取
-    //let amm_keys = amm::load_amm_keys(&rpc_client, &ray_amm_program_id, &amm_pool)?; 
-    //用于发送指令时，获取报价时不需要。获取较快。一次全部获取
-    //let market_keys = amm::get_keys_for_market(rpc_client, &amm_keys.market_program, &amm_keys.market)?;
+    //vault token account 中的原始余额，扣除 pnl 后得到上面的 amount
+    pub coin_vault_balance: u64,
+    pub pc_vault_balance: u64,
+    pub account_slots: HashMap<Pubkey, u64>,
+    //amm_authority 由 nonce 计算，其他的都在 amm_state 中获取
+    //market 账户加载时拉一次，解析失败的池子只能报价不能 swap
+    pub market_keys: Option<MarketKeys>,
+}
+
+impl RayAmmPool {
+    pub fn new(
+        pool_id: Pubkey,
+        amm_state: AmmInfo,
+        coin_vault_balance: u64,
+        pc_vault_balance: u64,
+        account_slots: HashMap<Pubkey, u64>,
+    ) -> Option<Self> {
+        let mut pool = RayAmmPool {
+            pool_id,
+            amm_state,
+            coin_vault_amount: 0,
+            pc_vault_amount: 0,
+            coin_vault_balance,
+            pc_vault_balance,
+            account_slots,
+            market_keys: None,
+        };
+        pool.refresh_vault_amounts().then_some(pool)
+    }
+
+    //失败时保留上一次算出的 amount，等下一次更新
+    pub fn refresh_vault_amounts(&mut self) -> bool {
+        match Calculator::calc_total_without_take_pnl_no_orderbook(
+            self.pc_vault_balance,
+            self.coin_vault_balance,
+            &self.amm_state,
+        ) {
+            Ok((pc_vault_amount, coin_vault_amount)) => {
+                self.pc_vault_amount = pc_vault_amount;
+                self.coin_vault_amount = coin_vault_amount;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    pub fn update_amm_state(&mut self, amm_state: AmmInfo) -> bool {
+        self.amm_state = amm_state;
+        self.refresh_vault_amounts()
+    }
+
+    pub fn amm_authority(&self) -> Result<Pubkey, Box<dyn Error>> {
+        let ray_amm_program_id = Pubkey::from_str(RAY_AMM_PROGRAM_ID).unwrap();
+        Ok(Pubkey::create_program_address(
+            &[AMM_AUTHORITY_SEED, &[self.amm_state.nonce as u8]],
+            &ray_amm_program_id,
+        )?)
+    }
+
+    pub fn update_vault(&mut self, key: &Pubkey, balance: u64) -> bool {
+        if *key == self.amm_state.coin_vault {
+            self.coin_vault_balance = balance;
+        } else if *key == self.amm_state.pc_vault {
+            self.pc_vault_balance = balance;
+        } else {
+            return false;
+        }
+        self.refresh_vault_amounts()
+    }
 }
 
 impl PoolOperations for RayAmmPool {
@@ -45,6 +143,9 @@ impl PoolOperations for RayAmmPool {
         } else {
             SwapDirection::PC2Coin
         };
+        if self.pc_vault_amount == 0 || self.coin_vault_amount == 0 {
+            return 0;
+        }
         let base_in = true;
         let slippage_bps = 0;
         
@@ -60,7 +161,7 @@ impl PoolOperations for RayAmmPool {
         ) {
             Ok(quote) => quote,
             Err(e) => {
-                eprintln!("ray amm {} : {}", self.get_pool_id(), e);
+                debug!(venue = "ray_amm", pool_id = %self.pool_id, a_to_b, amount_in, error = %e, "quote failed");
                 0
             }
         }
@@ -73,4 +174,220 @@ impl PoolOperations for RayAmmPool {
     fn get_pool_id(&self) -> Pubkey {
         self.pool_id
     }
+
+    fn get_venue(&self) -> PoolType {
+        PoolType::RayAmm
+    }
+
+    fn accounts_to_watch(&self) -> Vec<Pubkey> {
+        vec![self.pool_id, self.amm_state.coin_vault, self.amm_state.pc_vault]
+    }
+
+    fn update(&mut self, pubkey: &Pubkey, account: &Account, slot: u64) -> bool {
+        self.account_slots.insert(*pubkey, slot);
+        if *pubkey == self.pool_id {
+            match AmmInfo::load_from_bytes(&account.data) {
+                Ok(amm_state) => self.update_amm_state(amm_state.clone()),
+                Err(_) => false,
+            }
+        } else if *pubkey == self.amm_state.coin_vault || *pubkey == self.amm_state.pc_vault {
+            match common::unpack_token(&account.data) {
+                Ok(vault) => self.update_vault(pubkey, vault.base.amount),
+                Err(_) => false,
+            }
+        } else {
+            self.account_slots.remove(pubkey);
+            false
+        }
+    }
+
+    fn slot_range(&self) -> Option<SlotRange> {
+        SlotRange::of_accounts(&self.accounts_to_watch(), &self.account_slots)
+    }
+
+    fn swap_ix(
+        &self,
+        owner: &Pubkey,
+        a_to_b: bool,
+        amount_in: u64,
+        min_amount_out: u64,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let market_keys = self.market_keys
+            .as_ref()
+            .ok_or_else(|| format!("ray amm {}: market keys not loaded", self.pool_id))?;
+        let ray_amm_program_id = Pubkey::from_str(RAY_AMM_PROGRAM_ID).unwrap();
+        let amm_authority = self.amm_authority()?;
+        let (mint_in, mint_out) = if a_to_b {
+            (self.amm_state.coin_vault_mint, self.amm_state.pc_vault_mint)
+        } else {
+            (self.amm_state.pc_vault_mint, self.amm_state.coin_vault_mint)
+        };
+        let instruction = raydium_amm::instruction::swap_base_in(
+            &ray_amm_program_id,
+            &self.pool_id,
+            &amm_authority,
+            &self.amm_state.open_orders,
+            &self.amm_state.coin_vault,
+            &self.amm_state.pc_vault,
+            &self.amm_state.market_program,
+            &self.amm_state.market,
+            &market_keys.bids,
+            &market_keys.asks,
+            &market_keys.event_queue,
+            &market_keys.coin_vault,
+            &market_keys.pc_vault,
+            &market_keys.vault_signer,
+            &user_token_account(owner, &mint_in),
+            &user_token_account(owner, &mint_out),
+            owner,
+            amount_in,
+            min_amount_out,
+        )?;
+        Ok(vec![instruction])
+    }
+
+    fn static_accounts(&self) -> Vec<Pubkey> {
+        let mut keys = vec![
+            self.pool_id,
+            self.amm_state.open_orders,
+            self.amm_state.coin_vault,
+            self.amm_state.pc_vault,
+            self.amm_state.market_program,
+            self.amm_state.market,
+            Pubkey::from_str(RAY_AMM_PROGRAM_ID).unwrap(),
+            spl_token::ID,
+        ];
+        keys.extend(self.amm_authority().ok());
+        if let Some(market_keys) = &self.market_keys {
+            keys.extend([
+                market_keys.bids,
+                market_keys.asks,
+                market_keys.event_queue,
+                market_keys.coin_vault,
+                market_keys.pc_vault,
+                market_keys.vault_signer,
+            ]);
+        }
+        keys
+    }
+}
+
+pub struct RayAmmLoader;
+
+impl PoolLoader for RayAmmLoader {
+    type Primary = AmmInfo;
+    type Pool = RayAmmPool;
+
+    fn venue(&self) -> PoolType {
+        PoolType::RayAmm
+    }
+
+    fn decode_primary(&self, _pool_id: &Pubkey, account: &Account) -> Result<AmmInfo, SkipReason> {
+        if account.data.len() != std::mem::size_of::<AmmInfo>() {
+            return Err(SkipReason::InvalidAccountData);
+        }
+        AmmInfo::load_from_bytes(&account.data)
+            .map(|amm_info| amm_info.clone())
+            .map_err(|_| SkipReason::InvalidAccountData)
+    }
+
+    fn dependents(&self, _pool_id: &Pubkey, amm_info: &AmmInfo) -> Result<Vec<Pubkey>, SkipReason> {
+        Ok(vec![amm_info.coin_vault, amm_info.pc_vault, amm_info.market])
+    }
+
+    fn build(
+        &self,
+        pool_id: Pubkey,
+        amm_state: AmmInfo,
+        primary_slot: u64,
+        _dependent_keys: Vec<Pubkey>,
+        dependents: &BatchFetch,
+    ) -> Result<RayAmmPool, SkipReason> {
+        let vault = |key: &Pubkey| -> Result<(u64, u64), SkipReason> {
+            let (account, slot) = dependents.get(key).ok_or(SkipReason::MissingDependency)?;
+            let vault = common::unpack_token(&account.data).map_err(|_| SkipReason::MissingDependency)?;
+            Ok((vault.base.amount, slot))
+        };
+        let (coin_amount, coin_slot) = vault(&amm_state.coin_vault)?;
+        let (pc_amount, pc_slot) = vault(&amm_state.pc_vault)?;
+        let account_slots = HashMap::from([
+            (pool_id, primary_slot),
+            (amm_state.coin_vault, coin_slot),
+            (amm_state.pc_vault, pc_slot),
+        ]);
+        let market_keys = dependents
+            .get(&amm_state.market)
+            .and_then(|(account, _)| MarketKeys::parse(&amm_state.market, &amm_state.market_program, &account.data));
+        let mut pool = RayAmmPool::new(pool_id, amm_state, coin_amount, pc_amount, account_slots)
+            .ok_or(SkipReason::InvalidPoolState)?;
+        pool.market_keys = market_keys;
+        Ok(pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytemuck::Zeroable;
+    use solana_sdk::program_pack::Pack;
+    use spl_token::state::{Account as TokenAccount, AccountState};
+
+    fn vault_account(amount: u64) -> Account {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount { amount, state: AccountState::Initialized, ..TokenAccount::default() }.pack_into_slice(&mut data);
+        Account { data, owner: spl_token::ID, ..Account::default() }
+    }
+
+    fn amm_account(amm_state: &AmmInfo) -> Account {
+        Account { data: bytemuck::bytes_of(amm_state).to_vec(), ..Account::default() }
+    }
+
+    fn ray_pool() -> RayAmmPool {
+        let mut amm_state = AmmInfo::zeroed();
+        amm_state.coin_vault = Pubkey::new_unique();
+        amm_state.pc_vault = Pubkey::new_unique();
+        amm_state.fees.swap_fee_numerator = 25;
+        amm_state.fees.swap_fee_denominator = 10_000;
+        RayAmmPool::new(Pubkey::new_unique(), amm_state, 1_000_000, 2_000_000, HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn update_vaults_and_state() {
+        let mut pool = ray_pool();
+        let (pool_id, coin_vault, pc_vault) = (pool.pool_id, pool.amm_state.coin_vault, pool.amm_state.pc_vault);
+        assert_eq!((pool.coin_vault_amount, pool.pc_vault_amount), (1_000_000, 2_000_000));
+
+        assert!(pool.update(&coin_vault, &vault_account(1_500_000), 10));
+        assert!(pool.update(&pc_vault, &vault_account(2_500_000), 11));
+        assert_eq!((pool.coin_vault_amount, pool.pc_vault_amount), (1_500_000, 2_500_000));
+        assert!(pool.calc_quote(true, 1_000) > 0);
+
+        //pnl 从余额里扣
+        let mut amm_state = pool.amm_state;
+        amm_state.state_data.need_take_pnl_coin = 500_000;
+        assert!(pool.update(&pool_id, &amm_account(&amm_state), 12));
+        assert_eq!((pool.coin_vault_amount, pool.pc_vault_amount), (1_000_000, 2_500_000));
+        assert_eq!(pool.slot_range(), Some(SlotRange { min: 10, max: 12 }));
+    }
+
+    #[test]
+    fn update_keeps_reserves_on_bad_data() {
+        let mut pool = ray_pool();
+        let (pool_id, coin_vault) = (pool.pool_id, pool.amm_state.coin_vault);
+        let garbage = Account { data: vec![1, 2, 3], ..Account::default() };
+        assert!(!pool.update(&coin_vault, &garbage, 10));
+        assert!(!pool.update(&pool_id, &garbage, 11));
+        assert_eq!((pool.coin_vault_amount, pool.pc_vault_amount), (1_000_000, 2_000_000));
+
+        //pnl 超过余额算不出来，保留上一次的储备量
+        let mut amm_state = pool.amm_state;
+        amm_state.state_data.need_take_pnl_coin = 5_000_000;
+        assert!(!pool.update(&pool_id, &amm_account(&amm_state), 12));
+        assert_eq!((pool.coin_vault_amount, pool.pc_vault_amount), (1_000_000, 2_000_000));
+        assert!(pool.calc_quote(false, 1_000) > 0);
+
+        let unknown = Pubkey::new_unique();
+        assert!(!pool.update(&unknown, &vault_account(1), 13));
+        assert!(!pool.account_slots.contains_key(&unknown));
+    }
 }
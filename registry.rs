@@ -0,0 +1,396 @@
+use solana_sdk::{pubkey, pubkey::Pubkey};
+use serde::{Serialize, Deserialize};
+use std::{
+    str::FromStr, error::Error, fmt, fs,
+    path::Path,
+    collections::HashMap,
+};
+
+pub const REGISTRY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolType {
+    #[serde(alias = "Orca", alias = "whirlpool")]
+    Orca,
+    #[serde(alias = "Meteora", alias = "meteora_dlmm")]
+    Meteora,
+    #[serde(alias = "RayAmm", alias = "raydium", alias = "raydium_amm")]
+    RayAmm,
+}
+
+impl PoolType {
+    pub const ALL: [PoolType; 3] = [PoolType::Meteora, PoolType::Orca, PoolType::RayAmm];
+
+    //对应的 cargo feature 是否编译进来
+    pub fn is_compiled(&self) -> bool {
+        match self {
+            PoolType::Orca => cfg!(feature = "orca"),
+            PoolType::Meteora => cfg!(feature = "meteora"),
+            PoolType::RayAmm => cfg!(feature = "raydium"),
+        }
+    }
+
+    pub fn compiled() -> Vec<PoolType> {
+        Self::ALL.into_iter().filter(|venue| venue.is_compiled()).collect()
+    }
+
+    //池子账户的 owner，不依赖对应 feature 是否编译进来
+    pub fn program_id(&self) -> Pubkey {
+        match self {
+            PoolType::Orca => pubkey!("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc"),
+            PoolType::Meteora => pubkey!("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo"),
+            PoolType::RayAmm => pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8"),
+        }
+    }
+
+    pub fn feature(&self) -> &'static str {
+        match self {
+            PoolType::Orca => "orca",
+            PoolType::Meteora => "meteora",
+            PoolType::RayAmm => "raydium",
+        }
+    }
+}
+
+impl fmt::Display for PoolType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            PoolType::Orca => "orca",
+            PoolType::Meteora => "meteora",
+            PoolType::RayAmm => "ray_amm",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for PoolType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "orca" | "whirlpool" => Ok(PoolType::Orca),
+            "meteora" | "meteora_dlmm" => Ok(PoolType::Meteora),
+            "ray_amm" | "raydium" | "raydium_amm" => Ok(PoolType::RayAmm),
+            _ => Err(format!("unknown venue: {s}")),
+        }
+    }
+}
+
+//一个池子的描述，mint/decimals/symbol 可能在旧文件中不存在，以链上数据为准
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairData {
+    pub venue: PoolType,
+    #[serde(with = "serde_pubkey")]
+    pub pool_id: Pubkey,
+    #[serde(default, with = "serde_pubkey::option", skip_serializing_if = "Option::is_none")]
+    pub mint_a: Option<Pubkey>,
+    #[serde(default, with = "serde_pubkey::option", skip_serializing_if = "Option::is_none")]
+    pub mint_b: Option<Pubkey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals_a: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decimals_b: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol_a: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbol_b: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryFile {
+    pub version: u32,
+    #[serde(default)]
+    pub pools: Vec<PairData>,
+}
+
+// version 0: orca-reader 导出的 json 数组，没有 venue 字段
+#[derive(Debug, Clone, Deserialize)]
+struct LegacyPairData {
+    #[serde(with = "serde_pubkey")]
+    pool_id: Pubkey,
+    #[serde(default, alias = "pool_type")]
+    venue: Option<PoolType>,
+    #[serde(default, alias = "token_mint_a", with = "serde_pubkey::option")]
+    mint_a: Option<Pubkey>,
+    #[serde(default, alias = "token_mint_b", with = "serde_pubkey::option")]
+    mint_b: Option<Pubkey>,
+}
+
+enum RegistryDocument {
+    Versioned(RegistryFile),
+    Legacy(Vec<LegacyPairData>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    pub pools: Vec<PairData>,
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self { pools: vec![] }
+    }
+
+    // default_venue 只用于旧格式文件
+    pub fn load(
+        path: impl AsRef<Path>,
+        default_venue: Option<PoolType>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("read registry {}: {}", path.display(), e))?;
+        let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+        let document = if is_toml {
+            RegistryDocument::Versioned(toml::from_str(&text).map_err(|e| format!("registry {}: {}", path.display(), e))?)
+        } else {
+            //先按新格式解析，旧格式也不行时报新格式的错误，否则只能看到哪种格式都不匹配
+            match serde_json::from_str(&text) {
+                Ok(file) => RegistryDocument::Versioned(file),
+                Err(e) => RegistryDocument::Legacy(
+                    serde_json::from_str(&text).map_err(|_| format!("registry {}: {}", path.display(), e))?,
+                ),
+            }
+        };
+
+        let pools = match document {
+            RegistryDocument::Versioned(file) => {
+                if file.version > REGISTRY_VERSION {
+                    return Err(format!(
+                        "registry {} has version {}, newest supported is {}",
+                        path.display(), file.version, REGISTRY_VERSION,
+                    ).into());
+                }
+                file.pools
+            }
+            RegistryDocument::Legacy(pairs) => pairs
+                .into_iter()
+                .map(|pair| {
+                    let venue = pair.venue.or(default_venue).ok_or_else(|| {
+                        format!("registry {}: no venue for pool {}", path.display(), pair.pool_id)
+                    })?;
+                    Ok(PairData {
+                        venue,
+                        pool_id: pair.pool_id,
+                        mint_a: pair.mint_a,
+                        mint_b: pair.mint_b,
+                        decimals_a: None,
+                        decimals_b: None,
+                        symbol_a: None,
+                        symbol_b: None,
+                        tags: vec![],
+                        enabled: true,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        };
+        Ok(Self { pools })
+    }
+
+    pub fn load_all<P: AsRef<Path>>(
+        sources: &[(P, Option<PoolType>)],
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut registry = Self::new();
+        for (path, default_venue) in sources {
+            registry.extend(Self::load(path, *default_venue)?);
+        }
+        Ok(registry)
+    }
+
+    // 同一个 pool_id 以后加入的为准，保留第一次出现的位置
+    pub fn extend(&mut self, other: Registry) {
+        let mut index: HashMap<Pubkey, usize> = self.pools
+            .iter()
+            .enumerate()
+            .map(|(idx, pair)| (pair.pool_id, idx))
+            .collect();
+        for pair in other.pools {
+            match index.get(&pair.pool_id) {
+                Some(&idx) => self.pools[idx] = pair,
+                None => {
+                    index.insert(pair.pool_id, self.pools.len());
+                    self.pools.push(pair);
+                }
+            }
+        }
+    }
+
+    pub fn by_venue(&self, venue: PoolType) -> Vec<PairData> {
+        self.pools
+            .iter()
+            .filter(|pair| pair.enabled && pair.venue == venue)
+            .cloned()
+            .collect()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = RegistryFile {
+            version: REGISTRY_VERSION,
+            pools: self.pools.clone(),
+        };
+        let text = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::to_string_pretty(&file)?
+        } else {
+            serde_json::to_string_pretty(&file)?
+        };
+        fs::write(path, text)?;
+        Ok(())
+    }
+}
+
+// Pubkey 在新格式中写成 base58 字符串，旧文件里也可能是 [u8; 32]
+pub mod serde_pubkey {
+    use solana_sdk::pubkey::Pubkey;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use std::str::FromStr;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum RawPubkey {
+        Base58(String),
+        Bytes([u8; 32]),
+    }
+
+    impl RawPubkey {
+        fn into_pubkey<E: Error>(self) -> Result<Pubkey, E> {
+            match self {
+                RawPubkey::Base58(s) => Pubkey::from_str(&s).map_err(E::custom),
+                RawPubkey::Bytes(bytes) => Ok(Pubkey::new_from_array(bytes)),
+            }
+        }
+    }
+
+    pub fn serialize<S: Serializer>(pubkey: &Pubkey, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&pubkey.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+        RawPubkey::deserialize(deserializer)?.into_pubkey()
+    }
+
+    pub mod vec {
+        use super::*;
+        use serde::ser::SerializeSeq;
+
+        pub fn serialize<S: Serializer>(pubkeys: &[Pubkey], serializer: S) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(pubkeys.len()))?;
+            for pubkey in pubkeys {
+                seq.serialize_element(&pubkey.to_string())?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Pubkey>, D::Error> {
+            Vec::<RawPubkey>::deserialize(deserializer)?
+                .into_iter()
+                .map(RawPubkey::into_pubkey)
+                .collect()
+        }
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(pubkey: &Option<Pubkey>, serializer: S) -> Result<S::Ok, S::Error> {
+            match pubkey {
+                Some(pubkey) => serializer.serialize_str(&pubkey.to_string()),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Pubkey>, D::Error> {
+            Option::<RawPubkey>::deserialize(deserializer)?
+                .map(RawPubkey::into_pubkey)
+                .transpose()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn pool_id(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn pair(venue: PoolType, byte: u8) -> PairData {
+        PairData {
+            venue,
+            pool_id: pool_id(byte),
+            mint_a: None,
+            mint_b: None,
+            decimals_a: None,
+            decimals_b: None,
+            symbol_a: None,
+            symbol_b: None,
+            tags: vec![],
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn extend_replaces_duplicates_in_place() {
+        let mut registry = Registry { pools: vec![pair(PoolType::Orca, 1), pair(PoolType::Orca, 2)] };
+        let mut disabled = pair(PoolType::Meteora, 1);
+        disabled.enabled = false;
+        registry.extend(Registry { pools: vec![disabled, pair(PoolType::RayAmm, 3), pair(PoolType::RayAmm, 3)] });
+
+        let ids = registry.pools.iter().map(|pair| pair.pool_id).collect::<Vec<_>>();
+        assert_eq!(ids, vec![pool_id(1), pool_id(2), pool_id(3)]);
+        assert_eq!(registry.pools[0].venue, PoolType::Meteora);
+        assert!(!registry.pools[0].enabled);
+    }
+
+    //orca-reader 导出的数组：pool_type / token_mint_a 别名，pubkey 可以是字节数组
+    #[test]
+    fn load_legacy_orca_reader_array() {
+        let mint = Pubkey::new_unique();
+        let text = format!(
+            r#"[
+                {{"pool_id": "{}", "token_mint_a": "{}", "token_mint_b": {:?}}},
+                {{"pool_id": {:?}, "pool_type": "Meteora"}}
+            ]"#,
+            pool_id(1), mint, pool_id(9).to_bytes(), pool_id(2).to_bytes(),
+        );
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+
+        let registry = Registry::load(file.path(), Some(PoolType::Orca)).unwrap();
+        assert_eq!(registry.pools.len(), 2);
+        assert_eq!(registry.pools[0].venue, PoolType::Orca);
+        assert_eq!(registry.pools[0].pool_id, pool_id(1));
+        assert_eq!(registry.pools[0].mint_a, Some(mint));
+        assert_eq!(registry.pools[0].mint_b, Some(pool_id(9)));
+        assert_eq!(registry.pools[1].venue, PoolType::Meteora);
+        assert_eq!(registry.pools[1].pool_id, pool_id(2));
+        assert!(registry.pools[1].enabled);
+
+        //没有 venue 又没有默认值时报错
+        assert!(Registry::load(file.path(), None).is_err());
+    }
+
+    #[test]
+    fn versioned_errors_are_reported() {
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        write!(file, r#"{{"version": 1, "pools": [{{"venue": "orca", "pool_id": "not a pubkey"}}]}}"#).unwrap();
+        let error = Registry::load(file.path(), None).unwrap_err().to_string();
+        assert!(error.starts_with(&format!("registry {}: ", file.path().display())), "{}", error);
+        assert!(!error.contains("did not match any variant"), "{}", error);
+        assert!(error.contains("line 1"), "{}", error);
+
+        let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+        write!(file, r#"{{"version": 1, "pools": [{{"venue": "orca", "pool_id": "{}"}}]}}"#, pool_id(1)).unwrap();
+        assert_eq!(Registry::load(file.path(), None).unwrap().pools[0].pool_id, pool_id(1));
+    }
+}
@@ -0,0 +1,552 @@
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_request::RpcError,
+    client_error::{ClientError, ClientErrorKind, Result as ClientResult},
+};
+use std::{
+    error::Error, fmt,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{config::RpcConfig, metrics::metrics};
+
+//节点落后 / min_context_slot 还没到，换一个节点重试
+const RPC_NODE_UNHEALTHY: i64 = -32005;
+const RPC_MIN_CONTEXT_SLOT_NOT_REACHED: i64 = -32016;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    //429
+    RateLimited,
+    //连接失败、超时、5xx、节点落后
+    Unavailable,
+    //参数错误之类，换节点也没用
+    Fatal,
+}
+
+impl FailureKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FailureKind::RateLimited => "rate_limited",
+            FailureKind::Unavailable => "unavailable",
+            FailureKind::Fatal => "fatal",
+        }
+    }
+}
+
+pub fn classify(error: &ClientError) -> FailureKind {
+    match error.kind() {
+        ClientErrorKind::Reqwest(e) => match e.status().map(|status| status.as_u16()) {
+            Some(429) => FailureKind::RateLimited,
+            Some(status) if status < 500 => FailureKind::Fatal,
+            _ => FailureKind::Unavailable,
+        },
+        ClientErrorKind::Io(_) => FailureKind::Unavailable,
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            code: RPC_NODE_UNHEALTHY | RPC_MIN_CONTEXT_SLOT_NOT_REACHED, ..
+        }) => FailureKind::Unavailable,
+        ClientErrorKind::RpcError(RpcError::RpcRequestError(_)) => FailureKind::Unavailable,
+        _ => FailureKind::Fatal,
+    }
+}
+
+struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        let capacity = rate.max(1.0);
+        Self {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    //拿不到 token 时返回需要等待的时间
+    fn try_acquire(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last).as_secs_f64() * self.rate).min(self.capacity);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct Health {
+    consecutive_failures: u32,
+    down_until: Option<Instant>,
+    requests: u64,
+    errors: u64,
+    rate_limited: u64,
+}
+
+pub struct Endpoint {
+    //url 里一般带 api key，日志里只打印 label
+    pub label: String,
+    pub weight: u32,
+    client: RpcClient,
+    limiter: Option<Mutex<TokenBucket>>,
+    health: Mutex<Health>,
+}
+
+impl Endpoint {
+    pub fn new(url: &str, weight: u32, rate_limit: Option<f64>, commitment: CommitmentConfig) -> Self {
+        Self {
+            label: redact_url(url),
+            weight: weight.max(1),
+            client: RpcClient::new_with_commitment(url.to_string(), commitment),
+            limiter: rate_limit.map(|rate| Mutex::new(TokenBucket::new(rate))),
+            health: Mutex::new(Health::default()),
+        }
+    }
+
+    pub fn client(&self) -> &RpcClient {
+        &self.client
+    }
+
+    fn acquire(&self) {
+        let Some(limiter) = &self.limiter else {
+            return;
+        };
+        loop {
+            let wait = limiter.lock().unwrap().try_acquire();
+            match wait {
+                Ok(()) => return,
+                Err(wait) => thread::sleep(wait),
+            }
+        }
+    }
+
+    fn is_available(&self, now: Instant) -> bool {
+        self.health.lock().unwrap().down_until.is_none_or(|until| until <= now)
+    }
+
+    fn down_until(&self) -> Option<Instant> {
+        self.health.lock().unwrap().down_until
+    }
+
+    fn record_success(&self) {
+        let mut health = self.health.lock().unwrap();
+        health.requests += 1;
+        health.consecutive_failures = 0;
+        health.down_until = None;
+    }
+
+    fn record_failure(&self, kind: FailureKind, backoff: &Backoff) {
+        let mut health = self.health.lock().unwrap();
+        health.requests += 1;
+        health.errors += 1;
+        if kind == FailureKind::RateLimited {
+            health.rate_limited += 1;
+        }
+        //Fatal 是请求本身的问题，不算节点不健康
+        if kind != FailureKind::Fatal {
+            health.consecutive_failures += 1;
+            health.down_until = Some(Instant::now() + backoff.delay(health.consecutive_failures));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub base: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    //第 n 次失败等 base * 2^(n-1)，不超过 max
+    pub fn delay(&self, failures: u32) -> Duration {
+        let shift = failures.saturating_sub(1).min(16);
+        self.base.saturating_mul(1 << shift).min(self.max)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    pub label: String,
+    pub weight: u32,
+    pub available: bool,
+    pub consecutive_failures: u32,
+    pub requests: u64,
+    pub errors: u64,
+    pub rate_limited: u64,
+}
+
+impl fmt::Display for EndpointStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (weight {}): {}, requests {}, errors {}, rate_limited {}",
+            self.label, self.weight,
+            if self.available { "up" } else { "down" },
+            self.requests, self.errors, self.rate_limited)
+    }
+}
+
+//多个节点按权重轮询，失败的节点按退避时间暂时摘掉，请求失败自动换节点重试
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    //smooth weighted round robin 的 current weight
+    current: Mutex<Vec<i64>>,
+    backoff: Backoff,
+    max_retries: usize,
+}
+
+impl RpcPool {
+    pub fn new(endpoints: Vec<Endpoint>, backoff: Backoff, max_retries: usize) -> Self {
+        assert!(!endpoints.is_empty(), "rpc pool needs at least one endpoint");
+        let current = Mutex::new(vec![0; endpoints.len()]);
+        Self {
+            endpoints,
+            current,
+            backoff,
+            max_retries,
+        }
+    }
+
+    pub fn from_config(config: &RpcConfig, commitment: CommitmentConfig) -> Result<Self, Box<dyn Error>> {
+        let endpoints = config
+            .endpoints()?
+            .into_iter()
+            .map(|(url, weight, rate_limit)| Endpoint::new(&url, weight, rate_limit, commitment))
+            .collect::<Vec<_>>();
+        if endpoints.is_empty() {
+            return Err("no rpc endpoint configured".into());
+        }
+        let backoff = Backoff {
+            base: Duration::from_millis(config.backoff_ms),
+            max: Duration::from_millis(config.max_backoff_ms),
+        };
+        Ok(Self::new(endpoints, backoff, config.max_retries))
+    }
+
+    pub fn endpoints(&self) -> &[Endpoint] {
+        &self.endpoints
+    }
+
+    //只在可用节点里选；全部不可用时等最早恢复的那个
+    fn select(&self) -> usize {
+        let now = Instant::now();
+        let available = self.endpoints
+            .iter()
+            .map(|endpoint| endpoint.is_available(now))
+            .collect::<Vec<_>>();
+        if !available.contains(&true) {
+            let (idx, until) = self.endpoints
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, endpoint)| Some((idx, endpoint.down_until()?)))
+                .min_by_key(|(_, until)| *until)
+                .unwrap();
+            thread::sleep(until.saturating_duration_since(Instant::now()));
+            return idx;
+        }
+
+        let mut current = self.current.lock().unwrap();
+        let mut total = 0;
+        let mut best: Option<usize> = None;
+        for (idx, endpoint) in self.endpoints.iter().enumerate() {
+            if !available[idx] {
+                continue;
+            }
+            current[idx] += endpoint.weight as i64;
+            total += endpoint.weight as i64;
+            if best.is_none_or(|best| current[idx] > current[best]) {
+                best = Some(idx);
+            }
+        }
+        let best = best.unwrap();
+        current[best] -= total;
+        best
+    }
+
+    pub fn call<T>(&self, f: impl Fn(&RpcClient) -> ClientResult<T>) -> ClientResult<T> {
+        let mut attempt = 0;
+        loop {
+            let endpoint = &self.endpoints[self.select()];
+            endpoint.acquire();
+            let started = Instant::now();
+            let result = f(&endpoint.client);
+            metrics().rpc_latency.with_label_values(&[&endpoint.label]).observe(started.elapsed().as_secs_f64());
+            metrics().rpc_requests.with_label_values(&[&endpoint.label]).inc();
+            match result {
+                Ok(value) => {
+                    endpoint.record_success();
+                    return Ok(value);
+                }
+                Err(e) => {
+                    let kind = classify(&e);
+                    metrics().rpc_errors.with_label_values(&[&endpoint.label, kind.label()]).inc();
+                    endpoint.record_failure(kind, &self.backoff);
+                    if kind == FailureKind::Fatal || attempt >= self.max_retries {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> Vec<EndpointStats> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .map(|endpoint| {
+                let health = endpoint.health.lock().unwrap().clone();
+                EndpointStats {
+                    label: endpoint.label.clone(),
+                    weight: endpoint.weight,
+                    available: health.down_until.is_none_or(|until| until <= now),
+                    consecutive_failures: health.consecutive_failures,
+                    requests: health.requests,
+                    errors: health.errors,
+                    rate_limited: health.rate_limited,
+                }
+            })
+            .collect()
+    }
+}
+
+//只保留 scheme://host，去掉 path 和 query 里的 api key
+pub fn redact_url(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let host = rest.split(['/', '?']).next().unwrap_or(rest);
+    let host = host.rsplit('@').next().unwrap_or(host);
+    if scheme.is_empty() {
+        host.to_string()
+    } else {
+        format!("{scheme}://{host}")
+    }
+}
+
+#[cfg(test)]
+pub mod stub {
+    use super::*;
+    use serde_json::Value;
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    //本地 JSON-RPC 替身：按第几次请求和请求内容决定返回的 status 和 body，记下收到的请求数；
+    //RpcClient 有些方法会先查 getVersion，替身直接回答，不算在请求数里
+    pub struct Stub {
+        pub url: String,
+        hits: Arc<AtomicUsize>,
+    }
+
+    impl Stub {
+        pub fn start(respond: impl Fn(usize, &Value) -> (u16, String) + Send + 'static) -> Self {
+            let server = tiny_http::Server::http("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", server.server_addr().to_ip().unwrap());
+            let hits = Arc::new(AtomicUsize::new(0));
+            let counter = hits.clone();
+            thread::spawn(move || {
+                for mut request in server.incoming_requests() {
+                    let body: Value = serde_json::from_reader(request.as_reader()).unwrap_or_default();
+                    let (status, body) = if body["method"] == "getVersion" {
+                        (200, result(serde_json::json!({"solana-core": "1.18.26"})))
+                    } else {
+                        respond(counter.fetch_add(1, Ordering::SeqCst), &body)
+                    };
+                    //429 时 RpcClient 自己会按 Retry-After 重试几次，设成 0 不等待
+                    let retry_after = tiny_http::Header::from_bytes(&b"Retry-After"[..], &b"0"[..]).unwrap();
+                    let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+                    let response = tiny_http::Response::from_string(body)
+                        .with_status_code(status)
+                        .with_header(retry_after)
+                        .with_header(content_type);
+                    let _ = request.respond(response);
+                }
+            });
+            Self { url, hits }
+        }
+
+        pub fn status(status: u16) -> Self {
+            Self::start(move |_, _| (status, String::new()))
+        }
+
+        pub fn slot(slot: u64) -> Self {
+            Self::start(move |_, _| (200, result(slot.into())))
+        }
+
+        pub fn rpc_error(code: i64) -> Self {
+            Self::start(move |_, _| (200, format!(r#"{{"jsonrpc":"2.0","id":1,"error":{{"code":{},"message":"stub"}}}}"#, code)))
+        }
+
+        pub fn hits(&self) -> usize {
+            self.hits.load(Ordering::SeqCst)
+        }
+
+        pub fn endpoint(&self, weight: u32) -> Endpoint {
+            Endpoint::new(&self.url, weight, None, CommitmentConfig::processed())
+        }
+
+        //只有这一个节点，失败不重试
+        pub fn pool(&self) -> RpcPool {
+            let backoff = Backoff {
+                base: Duration::from_millis(1),
+                max: Duration::from_millis(1),
+            };
+            RpcPool::new(vec![self.endpoint(1)], backoff, 0)
+        }
+    }
+
+    pub fn result(value: Value) -> String {
+        serde_json::json!({"jsonrpc": "2.0", "id": 1, "result": value}).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::stub::Stub;
+    use solana_client::rpc_request::RpcRequest;
+    use std::net::TcpListener;
+
+    fn backoff(base_ms: u64) -> Backoff {
+        Backoff {
+            base: Duration::from_millis(base_ms),
+            max: Duration::from_millis(base_ms * 4),
+        }
+    }
+
+    //RpcClient::get_slot 会先查一次 getVersion，直接发请求让替身只收到 getSlot
+    fn get_slot(client: &RpcClient) -> ClientResult<u64> {
+        client.send(RpcRequest::GetSlot, serde_json::Value::Null)
+    }
+
+    fn classify_stub(stub: &Stub) -> FailureKind {
+        let error = get_slot(&RpcClient::new(stub.url.clone())).unwrap_err();
+        classify(&error)
+    }
+
+    #[test]
+    fn classify_http_and_rpc_errors() {
+        assert_eq!(classify_stub(&Stub::status(429)), FailureKind::RateLimited);
+        assert_eq!(classify_stub(&Stub::status(500)), FailureKind::Unavailable);
+        assert_eq!(classify_stub(&Stub::status(503)), FailureKind::Unavailable);
+        assert_eq!(classify_stub(&Stub::status(403)), FailureKind::Fatal);
+        assert_eq!(classify_stub(&Stub::rpc_error(RPC_NODE_UNHEALTHY)), FailureKind::Unavailable);
+        assert_eq!(classify_stub(&Stub::rpc_error(RPC_MIN_CONTEXT_SLOT_NOT_REACHED)), FailureKind::Unavailable);
+        assert_eq!(classify_stub(&Stub::rpc_error(-32602)), FailureKind::Fatal);
+
+        //端口上没有服务
+        let closed = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let error = get_slot(&RpcClient::new(format!("http://{}", closed))).unwrap_err();
+        assert_eq!(classify(&error), FailureKind::Unavailable);
+    }
+
+    #[test]
+    fn failover_to_second_endpoint() {
+        let (down, up) = (Stub::status(503), Stub::slot(42));
+        //权重高的先被选中
+        let pool = RpcPool::new(vec![down.endpoint(2), up.endpoint(1)], backoff(1_000), 3);
+        assert_eq!(pool.call(get_slot).unwrap(), 42);
+        assert_eq!((down.hits(), up.hits()), (1, 1));
+
+        //退避期间不再选失败的节点
+        for _ in 0..3 {
+            assert_eq!(pool.call(get_slot).unwrap(), 42);
+        }
+        assert_eq!((down.hits(), up.hits()), (1, 4));
+        let stats = pool.stats();
+        assert!(!stats[0].available);
+        assert_eq!((stats[0].consecutive_failures, stats[0].errors), (1, 1));
+        assert!(stats[1].available);
+    }
+
+    #[test]
+    fn fatal_errors_are_not_retried() {
+        let (bad_request, up) = (Stub::rpc_error(-32602), Stub::slot(42));
+        let pool = RpcPool::new(vec![bad_request.endpoint(2), up.endpoint(1)], backoff(1_000), 3);
+        assert!(pool.call(get_slot).is_err());
+        assert_eq!((bad_request.hits(), up.hits()), (1, 0));
+        //请求本身的问题不算节点不健康
+        assert!(pool.stats()[0].available);
+    }
+
+    #[test]
+    fn backoff_down_until_window() {
+        let backoff = backoff(50);
+        assert_eq!(backoff.delay(1), Duration::from_millis(50));
+        assert_eq!(backoff.delay(2), Duration::from_millis(100));
+        assert_eq!(backoff.delay(3), Duration::from_millis(200));
+        assert_eq!(backoff.delay(30), Duration::from_millis(200));
+
+        let endpoint = Stub::status(503).endpoint(1);
+        let before = Instant::now();
+        endpoint.record_failure(FailureKind::Unavailable, &backoff);
+        let until = endpoint.down_until().unwrap();
+        assert!(until >= before + Duration::from_millis(50));
+        assert!(!endpoint.is_available(until - Duration::from_millis(1)));
+        assert!(endpoint.is_available(until));
+
+        //连续失败翻倍，成功后清零
+        endpoint.record_failure(FailureKind::RateLimited, &backoff);
+        assert!(endpoint.down_until().unwrap() >= before + Duration::from_millis(100));
+        endpoint.record_success();
+        assert_eq!(endpoint.down_until(), None);
+        assert!(endpoint.is_available(Instant::now()));
+    }
+
+    #[test]
+    fn waits_for_earliest_endpoint_when_all_down() {
+        let down = Stub::status(503);
+        let pool = RpcPool::new(vec![down.endpoint(1)], backoff(50), 1);
+        let started = Instant::now();
+        assert!(pool.call(get_slot).is_err());
+        //第二次请求要等第一次失败后的退避时间
+        assert_eq!(down.hits(), 2);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn smooth_weighted_selection() {
+        let url = "http://127.0.0.1:1";
+        let endpoint = |weight| Endpoint::new(url, weight, None, CommitmentConfig::processed());
+        let pool = RpcPool::new(vec![endpoint(5), endpoint(1), endpoint(1)], backoff(1_000), 0);
+        let picks = (0..14).map(|_| pool.select()).collect::<Vec<_>>();
+        assert_eq!(picks, vec![0, 0, 1, 0, 2, 0, 0, 0, 0, 1, 0, 2, 0, 0]);
+
+        //不可用的节点不参与轮询
+        pool.endpoints[0].record_failure(FailureKind::Unavailable, &pool.backoff);
+        let picks = (0..4).map(|_| pool.select()).collect::<Vec<_>>();
+        assert!(picks.iter().all(|&idx| idx != 0));
+        assert_eq!(picks.iter().filter(|&&idx| idx == 1).count(), 2);
+    }
+
+    #[test]
+    fn token_bucket_throttles() {
+        let mut bucket = TokenBucket::new(20.0);
+        for _ in 0..20 {
+            assert!(bucket.try_acquire().is_ok());
+        }
+        let wait = bucket.try_acquire().unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(50));
+
+        //容量用完后按 rate 放行
+        let up = Stub::slot(42);
+        let pool = RpcPool::new(
+            vec![Endpoint::new(&up.url, 1, Some(20.0), CommitmentConfig::processed())],
+            backoff(1_000),
+            0,
+        );
+        let started = Instant::now();
+        for _ in 0..25 {
+            pool.call(get_slot).unwrap();
+        }
+        assert_eq!(up.hits(), 25);
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
+
@@ -0,0 +1,442 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+};
+use serde::{Serialize, Deserialize};
+use std::{
+    error::Error, fs::File,
+    io::{BufReader, BufWriter, Write, ErrorKind},
+    path::Path,
+    sync::{Arc, Mutex},
+    collections::HashMap,
+};
+use tracing::warn;
+
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"ARBSNAP\0";
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SnapshotHeader {
+    magic: [u8; 8],
+    version: u32,
+}
+
+//一条记录就是某个 slot 拿到的一个账户，文件里按写入顺序排列
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotAccount {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub data: Vec<u8>,
+    pub executable: bool,
+    pub slot: u64,
+}
+
+impl SnapshotAccount {
+    pub fn new(pubkey: Pubkey, account: &Account, slot: u64) -> Self {
+        Self {
+            pubkey,
+            owner: account.owner,
+            lamports: account.lamports,
+            data: account.data.clone(),
+            executable: account.executable,
+            slot,
+        }
+    }
+
+    pub fn to_account(&self) -> Account {
+        Account {
+            lamports: self.lamports,
+            data: self.data.clone(),
+            owner: self.owner,
+            executable: self.executable,
+            rent_epoch: 0,
+        }
+    }
+}
+
+//header 后面是连续的 bincode 记录，可以边跑边写
+pub struct SnapshotWriter {
+    writer: BufWriter<File>,
+    count: usize,
+}
+
+impl SnapshotWriter {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .map_err(|e| format!("create snapshot {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(file);
+        bincode::serialize_into(&mut writer, &SnapshotHeader {
+            magic: SNAPSHOT_MAGIC,
+            version: SNAPSHOT_VERSION,
+        })?;
+        Ok(Self { writer, count: 0 })
+    }
+
+    pub fn write(&mut self, record: &SnapshotAccount) -> Result<(), Box<dyn Error>> {
+        bincode::serialize_into(&mut self.writer, record)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Drop for SnapshotWriter {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+//多个线程（各 venue loader、账户流）共用一个文件
+#[derive(Clone)]
+pub struct Recorder(Arc<Mutex<SnapshotWriter>>);
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(Self(Arc::new(Mutex::new(SnapshotWriter::create(path)?))))
+    }
+
+    //写失败只打印，不影响搜索
+    pub fn record(&self, pubkey: Pubkey, account: &Account, slot: u64) {
+        let mut writer = self.0.lock().unwrap();
+        if let Err(e) = writer.write(&SnapshotAccount::new(pubkey, account, slot)) {
+            warn!(%pubkey, error = %e, "snapshot write failed");
+        }
+    }
+
+    pub fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.0.lock().unwrap().flush()
+    }
+
+    pub fn count(&self) -> usize {
+        self.0.lock().unwrap().count()
+    }
+}
+
+pub struct SnapshotReader {
+    reader: BufReader<File>,
+}
+
+impl SnapshotReader {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format!("open snapshot {}: {}", path.display(), e))?;
+        let mut reader = BufReader::new(file);
+        let header: SnapshotHeader = bincode::deserialize_from(&mut reader)
+            .map_err(|e| format!("snapshot {}: bad header: {}", path.display(), e))?;
+        if header.magic != SNAPSHOT_MAGIC {
+            return Err(format!("{} is not an account snapshot", path.display()).into());
+        }
+        if header.version > SNAPSHOT_VERSION {
+            return Err(format!(
+                "snapshot {} has version {}, newest supported is {}",
+                path.display(), header.version, SNAPSHOT_VERSION,
+            ).into());
+        }
+        Ok(Self { reader })
+    }
+
+    pub fn read_all(self) -> Result<Vec<SnapshotAccount>, Box<dyn Error>> {
+        self.collect()
+    }
+}
+
+impl Iterator for SnapshotReader {
+    type Item = Result<SnapshotAccount, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match bincode::deserialize_from(&mut self.reader) {
+            Ok(record) => Some(Ok(record)),
+            Err(e) => match *e {
+                //读到文件末尾
+                bincode::ErrorKind::Io(ref io) if io.kind() == ErrorKind::UnexpectedEof => None,
+                _ => Some(Err(e.into())),
+            },
+        }
+    }
+}
+
+//每个账户取 until_slot（包含）之前最新的一条，None 表示取全部
+pub fn latest_accounts(
+    records: impl IntoIterator<Item = SnapshotAccount>,
+    until_slot: Option<u64>,
+) -> HashMap<Pubkey, SnapshotAccount> {
+    let mut accounts: HashMap<Pubkey, SnapshotAccount> = HashMap::new();
+    for record in records {
+        if until_slot.is_some_and(|until| record.slot > until) {
+            continue;
+        }
+        match accounts.get(&record.pubkey) {
+            Some(existing) if existing.slot > record.slot => {}
+            _ => {
+                accounts.insert(record.pubkey, record);
+            }
+        }
+    }
+    accounts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::{instruction::Instruction, program_pack::Pack};
+    use spl_token::state::{Account as TokenAccount, AccountState};
+    use crate::{
+        registry::{PairData, PoolType},
+        fetch::{BatchFetch, ReplayFetcher},
+        loader::{PoolLoader, SkipReason, load_pools},
+        pool::{PoolOperations, SlotRange, token_account_amount},
+    };
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/replay.snap");
+
+    fn key(byte: u8) -> Pubkey {
+        Pubkey::new_from_array([byte; 32])
+    }
+
+    fn record(pubkey: Pubkey, data: Vec<u8>, slot: u64) -> SnapshotAccount {
+        SnapshotAccount {
+            pubkey,
+            owner: spl_token::ID,
+            lamports: 2_039_280,
+            data,
+            executable: false,
+            slot,
+        }
+    }
+
+    fn token_account(mint: Pubkey, amount: u64) -> Vec<u8> {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint,
+            owner: key(0xee),
+            amount,
+            state: AccountState::Initialized,
+            ..Default::default()
+        }.pack_into_slice(&mut data);
+        data
+    }
+
+    //测试用的恒定乘积池：主账户是 mint_a | mint_b | vault_a | vault_b，依赖账户是两个 vault
+    #[derive(Debug)]
+    struct VaultPool {
+        pool_id: Pubkey,
+        mints: [Pubkey; 2],
+        vaults: [Pubkey; 2],
+        amounts: [u64; 2],
+        slot: u64,
+    }
+
+    impl PoolOperations for VaultPool {
+        fn calc_quote(&self, a_to_b: bool, amount_in: u64) -> u64 {
+            let (reserve_in, reserve_out) = if a_to_b {
+                (self.amounts[0], self.amounts[1])
+            } else {
+                (self.amounts[1], self.amounts[0])
+            };
+            (reserve_out as u128 * amount_in as u128 / (reserve_in as u128 + amount_in as u128)) as u64
+        }
+
+        fn get_mints(&self) -> Vec<Pubkey> {
+            self.mints.to_vec()
+        }
+
+        fn get_pool_id(&self) -> Pubkey {
+            self.pool_id
+        }
+
+        fn get_venue(&self) -> PoolType {
+            PoolType::RayAmm
+        }
+
+        fn accounts_to_watch(&self) -> Vec<Pubkey> {
+            self.vaults.to_vec()
+        }
+
+        fn update(&mut self, _pubkey: &Pubkey, _account: &Account, _slot: u64) -> bool {
+            false
+        }
+
+        fn slot_range(&self) -> Option<SlotRange> {
+            Some(SlotRange::new(self.slot))
+        }
+
+        fn swap_ix(&self, _owner: &Pubkey, _a_to_b: bool, _amount_in: u64, _min_amount_out: u64) -> Result<Vec<Instruction>, Box<dyn Error>> {
+            Err("not supported".into())
+        }
+
+        fn static_accounts(&self) -> Vec<Pubkey> {
+            vec![]
+        }
+    }
+
+    struct VaultPoolLoader;
+
+    impl PoolLoader for VaultPoolLoader {
+        type Primary = [Pubkey; 4];
+        type Pool = VaultPool;
+
+        fn venue(&self) -> PoolType {
+            PoolType::RayAmm
+        }
+
+        fn decode_primary(&self, _pool_id: &Pubkey, account: &Account) -> Result<[Pubkey; 4], SkipReason> {
+            if account.data.len() != 128 {
+                return Err(SkipReason::InvalidAccountData);
+            }
+            let mut keys = [Pubkey::default(); 4];
+            for (i, chunk) in account.data.chunks(32).enumerate() {
+                keys[i] = Pubkey::try_from(chunk).unwrap();
+            }
+            Ok(keys)
+        }
+
+        fn dependents(&self, _pool_id: &Pubkey, primary: &[Pubkey; 4]) -> Result<Vec<Pubkey>, SkipReason> {
+            Ok(primary[2..].to_vec())
+        }
+
+        fn build(
+            &self,
+            pool_id: Pubkey,
+            primary: [Pubkey; 4],
+            primary_slot: u64,
+            dependent_keys: Vec<Pubkey>,
+            dependents: &BatchFetch,
+        ) -> Result<VaultPool, SkipReason> {
+            let mut amounts = [0; 2];
+            let mut slot = primary_slot;
+            for (i, key) in dependent_keys.iter().enumerate() {
+                let (account, vault_slot) = dependents.get(key).ok_or(SkipReason::MissingDependency)?;
+                amounts[i] = token_account_amount(&account.data).ok_or(SkipReason::MissingDependency)?;
+                slot = slot.max(vault_slot);
+            }
+            Ok(VaultPool {
+                pool_id,
+                mints: [primary[0], primary[1]],
+                vaults: [primary[2], primary[3]],
+                amounts,
+                slot,
+            })
+        }
+    }
+
+    fn pair(pool_id: Pubkey) -> PairData {
+        PairData {
+            venue: PoolType::RayAmm,
+            pool_id,
+            mint_a: None,
+            mint_b: None,
+            decimals_a: None,
+            decimals_b: None,
+            symbol_a: None,
+            symbol_b: None,
+            tags: vec![],
+            enabled: true,
+        }
+    }
+
+    //池子 1 正常，vault_a 在 slot 120 有一次更新；池子 2 缺 vault；池子 3 数据长度不对；池子 4 不在快照里
+    fn fixture_records() -> Vec<SnapshotAccount> {
+        let (mint_a, mint_b) = (key(0xa0), key(0xb0));
+        let primary = |vault_a: Pubkey, vault_b: Pubkey| {
+            [mint_a, mint_b, vault_a, vault_b].iter().flat_map(|key| key.to_bytes()).collect::<Vec<_>>()
+        };
+        vec![
+            record(key(1), primary(key(11), key(12)), 100),
+            record(key(11), token_account(mint_a, 1_000_000), 100),
+            record(key(12), token_account(mint_b, 2_000_000), 100),
+            record(key(2), primary(key(21), key(22)), 100),
+            record(key(21), token_account(mint_a, 1_000_000), 100),
+            record(key(3), vec![0; 64], 100),
+            record(key(11), token_account(mint_a, 4_000_000), 120),
+        ]
+    }
+
+    //fixtures/replay.snap 由它生成：cargo test write_replay_fixture -- --ignored
+    #[test]
+    #[ignore]
+    fn write_replay_fixture() {
+        let mut writer = SnapshotWriter::create(FIXTURE).unwrap();
+        for record in fixture_records() {
+            writer.write(&record).unwrap();
+        }
+        writer.flush().unwrap();
+    }
+
+    #[test]
+    fn writer_reader_round_trip() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let records = fixture_records();
+        let mut writer = SnapshotWriter::create(file.path()).unwrap();
+        for record in &records {
+            writer.write(record).unwrap();
+        }
+        assert_eq!(writer.count(), records.len());
+        drop(writer);
+        assert_eq!(SnapshotReader::open(file.path()).unwrap().read_all().unwrap(), records);
+
+        //不是快照的文件、更新版本的快照都拒绝
+        std::fs::write(file.path(), b"not a snapshot file").unwrap();
+        assert!(SnapshotReader::open(file.path()).is_err());
+        let mut header = bincode::serialize(&SnapshotHeader { magic: SNAPSHOT_MAGIC, version: SNAPSHOT_VERSION + 1 }).unwrap();
+        header.extend(bincode::serialize(&records[0]).unwrap());
+        std::fs::write(file.path(), header).unwrap();
+        assert!(SnapshotReader::open(file.path()).is_err());
+    }
+
+    #[test]
+    fn latest_accounts_until_slot() {
+        let records = vec![
+            record(key(1), vec![30], 30),
+            record(key(1), vec![10], 10),
+            record(key(1), vec![20], 20),
+            record(key(2), vec![25], 25),
+        ];
+        let all = latest_accounts(records.clone(), None);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[&key(1)].data, vec![30]);
+        assert_eq!(all[&key(2)].data, vec![25]);
+
+        let until_20 = latest_accounts(records.clone(), Some(20));
+        assert_eq!(until_20.len(), 1);
+        assert_eq!(until_20[&key(1)].data, vec![20]);
+
+        assert!(latest_accounts(records, Some(5)).is_empty());
+    }
+
+    #[test]
+    fn replay_fixture_builds_pools() {
+        let pairs = [1, 2, 3, 4].map(|byte| pair(key(byte)));
+
+        let replay = ReplayFetcher::open(FIXTURE, Some(110)).unwrap();
+        let (pools, summary) = load_pools(&VaultPoolLoader, &replay, &pairs);
+        assert_eq!(pools.len(), 1);
+        assert_eq!(pools[0].pool_id, key(1));
+        assert_eq!(pools[0].mints, [key(0xa0), key(0xb0)]);
+        assert_eq!(pools[0].amounts, [1_000_000, 2_000_000]);
+        assert_eq!(pools[0].slot_range(), Some(SlotRange::new(100)));
+        assert_eq!(pools[0].calc_quote(true, 1_000_000), 1_000_000);
+        assert_eq!((summary.requested, summary.loaded), (4, 1));
+        assert_eq!(summary.skipped, vec![
+            (key(3), SkipReason::InvalidAccountData),
+            (key(4), SkipReason::AccountNotFound),
+            (key(2), SkipReason::MissingDependency),
+        ]);
+
+        //不限 slot 时用 vault_a 在 slot 120 的数据
+        let replay = ReplayFetcher::open(FIXTURE, None).unwrap();
+        let (pools, _) = load_pools(&VaultPoolLoader, &replay, &pairs);
+        assert_eq!(pools[0].amounts, [4_000_000, 2_000_000]);
+        assert_eq!(pools[0].slot_range(), Some(SlotRange::new(120)));
+    }
+}
@@ -0,0 +1,964 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+    commitment_config::{CommitmentConfig, CommitmentLevel},
+};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::RpcAccountInfoConfig,
+};
+use solana_account_decoder::UiAccountEncoding;
+use futures::{SinkExt, StreamExt, future::join_all, stream::SelectAll};
+use tokio::sync::mpsc::{
+    unbounded_channel, UnboundedReceiver, UnboundedSender, error::TryRecvError,
+};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::prelude::{
+    SubscribeRequest, SubscribeRequestFilterAccounts, SubscribeRequestFilterSlots,
+    CommitmentLevel as GeyserCommitmentLevel, subscribe_update::UpdateOneof,
+};
+use std::{
+    error::Error, sync::Arc,
+    collections::{HashMap, HashSet},
+    sync::mpsc::Sender,
+    thread,
+    time::Duration,
+};
+
+use tracing::{info, warn};
+use crate::{
+    config::{Config, SourceKind},
+    metrics::metrics,
+    rpc_pool::RpcPool,
+    stream::{AccountUpdate, StreamEvent},
+};
+
+//websocket / geyser / 轮询 三种数据源可以互换，都往同一个 channel 里发 StreamEvent
+pub trait AccountSource: Send {
+    fn name(&self) -> &'static str;
+
+    fn start(
+        self: Box<Self>,
+        keys: Vec<Pubkey>,
+        tx: Sender<StreamEvent>,
+        control: UnboundedReceiver<WatchCommand>,
+    ) -> thread::JoinHandle<Result<(), String>>;
+}
+
+#[derive(Debug, Clone)]
+pub enum WatchCommand {
+    Watch(Vec<Pubkey>),
+    Unwatch(Vec<Pubkey>),
+}
+
+pub struct SourceHandle {
+    pub name: &'static str,
+    control: UnboundedSender<WatchCommand>,
+    thread: thread::JoinHandle<Result<(), String>>,
+}
+
+impl SourceHandle {
+    pub fn start(
+        source: Box<dyn AccountSource>,
+        keys: Vec<Pubkey>,
+        tx: Sender<StreamEvent>,
+    ) -> Self {
+        let (control, control_rx) = unbounded_channel();
+        let name = source.name();
+        let thread = source.start(keys, tx, control_rx);
+        Self { name, control, thread }
+    }
+
+    pub fn watch(&self, keys: Vec<Pubkey>) {
+        if !keys.is_empty() {
+            let _ = self.control.send(WatchCommand::Watch(keys));
+        }
+    }
+
+    pub fn unwatch(&self, keys: Vec<Pubkey>) {
+        if !keys.is_empty() {
+            let _ = self.control.send(WatchCommand::Unwatch(keys));
+        }
+    }
+
+    pub fn join(self) -> Result<(), String> {
+        drop(self.control);
+        self.thread
+            .join()
+            .map_err(|_| format!("{} source thread panicked", self.name))?
+    }
+}
+
+//owners 是已加载池子的程序，geyser 按 owner 订阅时用
+pub fn build_source(
+    config: &Config,
+    rpc_client: Arc<RpcPool>,
+    owners: Vec<Pubkey>,
+) -> Result<Box<dyn AccountSource>, Box<dyn Error>> {
+    let commitment = config.commitment();
+    let source: Box<dyn AccountSource> = match config.stream.source {
+        SourceKind::Websocket => Box::new(WsSource {
+            ws_url: config.rpc.ws_url.clone().ok_or("websocket source needs rpc.ws_url")?,
+            commitment,
+            retry_interval: SOURCE_RETRY_INTERVAL,
+        }),
+        SourceKind::Geyser => Box::new(GeyserSource {
+            endpoint: config.stream.geyser_endpoint.clone().ok_or("geyser source needs stream.geyser_endpoint")?,
+            x_token: config.stream.geyser_x_token.clone(),
+            commitment,
+            owners,
+            retry_interval: SOURCE_RETRY_INTERVAL,
+        }),
+        SourceKind::Polling => Box::new(PollingSource {
+            rpc_client,
+            commitment,
+            batch_size: config.rpc.batch_size,
+            interval: Duration::from_millis(config.stream.poll_interval_ms),
+        }),
+    };
+    Ok(source)
+}
+
+fn spawn_runtime<F>(
+    name: &'static str,
+    f: impl FnOnce() -> F + Send + 'static,
+) -> thread::JoinHandle<Result<(), String>>
+where
+    F: std::future::Future<Output = Result<(), Box<dyn Error>>>,
+{
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .map_err(|e| e.to_string())?;
+            runtime.block_on(f()).map_err(|e| e.to_string())
+        })
+        .unwrap()
+}
+
+//-----------------------------------------------------------------------------
+
+//订阅失败的账户、断开的连接隔多久重试一次
+pub const SOURCE_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct WsSource {
+    pub ws_url: String,
+    pub commitment: CommitmentConfig,
+    pub retry_interval: Duration,
+}
+
+impl AccountSource for WsSource {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn start(
+        self: Box<Self>,
+        keys: Vec<Pubkey>,
+        tx: Sender<StreamEvent>,
+        control: UnboundedReceiver<WatchCommand>,
+    ) -> thread::JoinHandle<Result<(), String>> {
+        spawn_runtime("ws-source", move || async move {
+            self.run(keys, &tx, control).await
+        })
+    }
+}
+
+type UnsubscribeFn = Box<dyn FnOnce() -> futures::future::BoxFuture<'static, ()> + Send>;
+
+impl WsSource {
+    //连接断开后隔 retry_interval 重连，把所有 watched 的账户重新订阅一遍；
+    //第一次连不上直接返回错误，多半是配置问题
+    async fn run(
+        &self,
+        keys: Vec<Pubkey>,
+        tx: &Sender<StreamEvent>,
+        mut control: UnboundedReceiver<WatchCommand>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut watched: HashSet<Pubkey> = keys.into_iter().collect();
+        let mut connected = false;
+        loop {
+            match self.session(&mut watched, tx, &mut control, &mut connected).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => warn!("websocket closed, reconnecting"),
+                Err(e) if !connected => return Err(e),
+                Err(e) => warn!(error = %e, "websocket reconnect failed"),
+            }
+            metrics().source_reconnects.with_label_values(&[self.name()]).inc();
+            tokio::time::sleep(self.retry_interval).await;
+        }
+    }
+
+    //每个账户一个订阅，退订时调用 unsubscribe，对应的 stream 结束后会自动从 SelectAll 中移除
+    //单个账户订阅失败只打日志，留在 pending 里定期重试，不影响其他订阅
+    //slot 订阅结束说明连接断了，返回 Ok(false)；control 关闭或下游不收了返回 Ok(true)
+    async fn session(
+        &self,
+        watched: &mut HashSet<Pubkey>,
+        tx: &Sender<StreamEvent>,
+        control: &mut UnboundedReceiver<WatchCommand>,
+        connected: &mut bool,
+    ) -> Result<bool, Box<dyn Error>> {
+        let client = PubsubClient::new(&self.ws_url).await?;
+        let config = RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(self.commitment),
+            ..RpcAccountInfoConfig::default()
+        };
+        let subscribe = |key: Pubkey| {
+            let client = &client;
+            let config = config.clone();
+            async move {
+                let (stream, unsubscribe) = client.account_subscribe(&key, Some(config)).await?;
+                let stream = stream
+                    .filter_map(move |response| async move {
+                        let account = response.value.decode::<Account>()?;
+                        Some(StreamEvent::Account(AccountUpdate {
+                            pubkey: key,
+                            account,
+                            slot: response.context.slot,
+                        }))
+                    })
+                    .boxed_local();
+                Ok::<_, Box<dyn Error>>((stream, unsubscribe))
+            }
+        };
+
+        let (mut slots, _unsubscribe) = client.slot_subscribe().await?;
+        *connected = true;
+        let mut events = SelectAll::new();
+        let mut unsubscribes: HashMap<Pubkey, UnsubscribeFn> = HashMap::new();
+        let mut pending = watched.clone();
+        let mut resubscribe = true;
+        let mut retry = tokio::time::interval_at(tokio::time::Instant::now() + self.retry_interval, self.retry_interval);
+        loop {
+            if resubscribe {
+                let keys = pending.drain().collect::<Vec<_>>();
+                let results = join_all(keys.iter().map(|&key| subscribe(key))).await;
+                for (key, result) in keys.into_iter().zip(results) {
+                    match result {
+                        Ok((stream, unsubscribe)) => {
+                            events.push(stream);
+                            unsubscribes.insert(key, unsubscribe);
+                        }
+                        Err(e) => {
+                            warn!(account = %key, error = %e, "account subscribe failed, will retry");
+                            pending.insert(key);
+                        }
+                    }
+                }
+                info!(accounts = unsubscribes.len(), pending = pending.len(), "websocket subscribed");
+                resubscribe = false;
+            }
+
+            let event = tokio::select! {
+                command = control.recv() => {
+                    match command {
+                        Some(WatchCommand::Watch(keys)) => {
+                            pending.extend(keys.iter().filter(|key| !unsubscribes.contains_key(key)));
+                            watched.extend(keys);
+                            resubscribe = !pending.is_empty();
+                        }
+                        Some(WatchCommand::Unwatch(keys)) => {
+                            for key in keys {
+                                watched.remove(&key);
+                                pending.remove(&key);
+                                if let Some(unsubscribe) = unsubscribes.remove(&key) {
+                                    unsubscribe().await;
+                                }
+                            }
+                        }
+                        None => return Ok(true),
+                    }
+                    continue;
+                }
+                _ = retry.tick(), if !pending.is_empty() => {
+                    resubscribe = true;
+                    continue;
+                }
+                slot = slots.next() => match slot {
+                    Some(info) => StreamEvent::Slot(info.slot),
+                    None => return Ok(false),
+                },
+                Some(event) = events.next() => event,
+            };
+            if tx.send(event).is_err() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+pub struct GeyserSource {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    pub commitment: CommitmentConfig,
+    //池子程序，按 owner 订阅
+    pub owners: Vec<Pubkey>,
+    pub retry_interval: Duration,
+}
+
+impl AccountSource for GeyserSource {
+    fn name(&self) -> &'static str {
+        "geyser"
+    }
+
+    fn start(
+        self: Box<Self>,
+        keys: Vec<Pubkey>,
+        tx: Sender<StreamEvent>,
+        control: UnboundedReceiver<WatchCommand>,
+    ) -> thread::JoinHandle<Result<(), String>> {
+        spawn_runtime("geyser-source", move || async move {
+            self.run(keys, &tx, control).await
+        })
+    }
+}
+
+impl GeyserSource {
+    //池子程序的账户按 owner 订阅，vault、clock 这类不归池子程序的账户按 key 订阅；
+    //owner 订阅会收到程序的所有账户（包括 position），不在 keys 里的在客户端丢掉
+    fn subscribe_request(&self, keys: &HashSet<Pubkey>) -> SubscribeRequest {
+        let commitment = match self.commitment.commitment {
+            CommitmentLevel::Finalized => GeyserCommitmentLevel::Finalized,
+            CommitmentLevel::Confirmed => GeyserCommitmentLevel::Confirmed,
+            _ => GeyserCommitmentLevel::Processed,
+        };
+        let mut accounts = HashMap::new();
+        if !self.owners.is_empty() {
+            accounts.insert(
+                "owners".to_string(),
+                SubscribeRequestFilterAccounts {
+                    owner: self.owners.iter().map(|owner| owner.to_string()).collect(),
+                    ..SubscribeRequestFilterAccounts::default()
+                },
+            );
+        }
+        accounts.insert(
+            "watched".to_string(),
+            SubscribeRequestFilterAccounts {
+                account: keys.iter().map(|key| key.to_string()).collect(),
+                ..SubscribeRequestFilterAccounts::default()
+            },
+        );
+        let mut slots = HashMap::new();
+        slots.insert("slots".to_string(), SubscribeRequestFilterSlots::default());
+
+        SubscribeRequest {
+            accounts,
+            slots,
+            commitment: Some(commitment as i32),
+            ..SubscribeRequest::default()
+        }
+    }
+
+    //和 websocket 一样，流出错或结束后隔 retry_interval 重连，第一次连不上直接返回错误
+    async fn run(
+        &self,
+        keys: Vec<Pubkey>,
+        tx: &Sender<StreamEvent>,
+        mut control: UnboundedReceiver<WatchCommand>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut keys: HashSet<Pubkey> = keys.into_iter().collect();
+        let mut connected = false;
+        loop {
+            match self.session(&mut keys, tx, &mut control, &mut connected).await {
+                Ok(true) => return Ok(()),
+                Ok(false) => warn!("geyser stream closed, reconnecting"),
+                Err(e) if !connected => return Err(e),
+                Err(e) => warn!(error = %e, "geyser stream failed, reconnecting"),
+            }
+            metrics().source_reconnects.with_label_values(&[self.name()]).inc();
+            tokio::time::sleep(self.retry_interval).await;
+        }
+    }
+
+    //watch / unwatch 时发送新的 SubscribeRequest 替换原来的过滤条件
+    //流结束返回 Ok(false)；control 关闭或下游不收了返回 Ok(true)
+    async fn session(
+        &self,
+        keys: &mut HashSet<Pubkey>,
+        tx: &Sender<StreamEvent>,
+        control: &mut UnboundedReceiver<WatchCommand>,
+        connected: &mut bool,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut client = GeyserGrpcClient::build_from_shared(self.endpoint.clone())?
+            .x_token(self.x_token.clone())?
+            .connect()
+            .await?;
+        let (mut subscribe_tx, mut stream) = client
+            .subscribe_with_request(Some(self.subscribe_request(keys)))
+            .await?;
+        *connected = true;
+        info!(accounts = keys.len(), owners = self.owners.len(), "geyser subscribed");
+
+        loop {
+            let message = tokio::select! {
+                command = control.recv() => {
+                    match command {
+                        Some(WatchCommand::Watch(added)) => keys.extend(added),
+                        Some(WatchCommand::Unwatch(removed)) => {
+                            for key in removed {
+                                keys.remove(&key);
+                            }
+                        }
+                        None => return Ok(true),
+                    }
+                    subscribe_tx.send(self.subscribe_request(keys)).await?;
+                    continue;
+                }
+                message = stream.next() => match message {
+                    Some(message) => message?,
+                    None => return Ok(false),
+                },
+            };
+            let event = match message.update_oneof {
+                Some(UpdateOneof::Account(update)) => {
+                    let Some(info) = update.account else {
+                        continue;
+                    };
+                    let (Ok(pubkey), Ok(owner)) = (
+                        Pubkey::try_from(info.pubkey.as_slice()),
+                        Pubkey::try_from(info.owner.as_slice()),
+                    ) else {
+                        continue;
+                    };
+                    if !keys.contains(&pubkey) {
+                        continue;
+                    }
+                    StreamEvent::Account(AccountUpdate {
+                        pubkey,
+                        account: Account {
+                            lamports: info.lamports,
+                            data: info.data,
+                            owner,
+                            executable: info.executable,
+                            rent_epoch: info.rent_epoch,
+                        },
+                        slot: update.slot,
+                    })
+                }
+                Some(UpdateOneof::Slot(update)) => StreamEvent::Slot(update.slot),
+                _ => continue,
+            };
+            if tx.send(event).is_err() {
+                return Ok(true);
+            }
+        }
+    }
+}
+
+//-----------------------------------------------------------------------------
+
+pub struct PollingSource {
+    pub rpc_client: Arc<RpcPool>,
+    pub commitment: CommitmentConfig,
+    pub batch_size: usize,
+    pub interval: Duration,
+}
+
+impl AccountSource for PollingSource {
+    fn name(&self) -> &'static str {
+        "polling"
+    }
+
+    fn start(
+        self: Box<Self>,
+        keys: Vec<Pubkey>,
+        tx: Sender<StreamEvent>,
+        control: UnboundedReceiver<WatchCommand>,
+    ) -> thread::JoinHandle<Result<(), String>> {
+        thread::Builder::new()
+            .name("polling-source".to_string())
+            .spawn(move || self.run(keys, &tx, control).map_err(|e| e.to_string()))
+            .unwrap()
+    }
+}
+
+impl PollingSource {
+    //只发送 data 有变化的账户
+    fn run(
+        &self,
+        mut keys: Vec<Pubkey>,
+        tx: &Sender<StreamEvent>,
+        mut control: UnboundedReceiver<WatchCommand>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut last_data: HashMap<Pubkey, Vec<u8>> = HashMap::new();
+        loop {
+            loop {
+                match control.try_recv() {
+                    Ok(WatchCommand::Watch(added)) => {
+                        for key in added {
+                            if !keys.contains(&key) {
+                                keys.push(key);
+                            }
+                        }
+                    }
+                    Ok(WatchCommand::Unwatch(removed)) => {
+                        keys.retain(|key| !removed.contains(key));
+                        for key in removed {
+                            last_data.remove(&key);
+                        }
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            let mut max_slot = 0;
+            for chunk in keys.chunks(self.batch_size) {
+                //一批失败不结束数据源，下一轮再拉
+                let response = match self.rpc_client.call(|client| client.get_multiple_accounts_with_commitment(chunk, self.commitment)) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        warn!(accounts = chunk.len(), error = %e, "poll accounts failed");
+                        metrics().source_errors.with_label_values(&[self.name()]).inc();
+                        continue;
+                    }
+                };
+                let slot = response.context.slot;
+                max_slot = max_slot.max(slot);
+                for (pubkey, account) in chunk.iter().zip(response.value) {
+                    let Some(account) = account else {
+                        continue;
+                    };
+                    if last_data.get(pubkey) == Some(&account.data) {
+                        continue;
+                    }
+                    last_data.insert(*pubkey, account.data.clone());
+                    let update = AccountUpdate {
+                        pubkey: *pubkey,
+                        account,
+                        slot,
+                    };
+                    if tx.send(StreamEvent::Account(update)).is_err() {
+                        return Ok(());
+                    }
+                }
+            }
+            if max_slot > 0 && tx.send(StreamEvent::Slot(max_slot)).is_err() {
+                return Ok(());
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc_pool::stub::{result, Stub};
+    use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+    use serde_json::{json, Value};
+    use std::{
+        str::FromStr,
+        net::TcpListener,
+        sync::{Mutex, atomic::{AtomicUsize, Ordering}, mpsc::{channel, Receiver}},
+    };
+    use tungstenite::Message;
+    use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+    use tonic::{Request, Response, Status, Streaming};
+    use yellowstone_grpc_proto::prelude::{
+        geyser_server::{Geyser, GeyserServer},
+        SubscribeUpdate, SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdateSlot,
+        PingRequest, PongResponse, GetLatestBlockhashRequest, GetLatestBlockhashResponse,
+        GetBlockHeightRequest, GetBlockHeightResponse, GetSlotRequest, GetSlotResponse,
+        IsBlockhashValidRequest, IsBlockhashValidResponse, GetVersionRequest, GetVersionResponse,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    //websocket 节点的替身：订阅成功后马上推一条通知，账户数据就是 pubkey 本身；
+    //fail_first 里的账户第一次订阅返回错误；close_after 个账户订阅成功后断开第一条连接
+    fn mock_pubsub(fail_first: Vec<Pubkey>, close_after: Option<usize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let mut failed = HashSet::new();
+            for (connection, stream) in listener.incoming().enumerate() {
+                let mut ws = tungstenite::accept(stream.unwrap()).unwrap();
+                let close_after = close_after.filter(|_| connection == 0);
+                serve_pubsub(&mut ws, &fail_first, &mut failed, close_after);
+            }
+        });
+        url
+    }
+
+    fn serve_pubsub(
+        ws: &mut tungstenite::WebSocket<std::net::TcpStream>,
+        fail_first: &[Pubkey],
+        failed: &mut HashSet<Pubkey>,
+        close_after: Option<usize>,
+    ) {
+        let mut next_sid = 0u64;
+        let mut subscribed = 0;
+        while let Ok(message) = ws.read() {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let request: Value = serde_json::from_str(&text).unwrap();
+            let id = request["id"].clone();
+            let mut replies = vec![];
+            match request["method"].as_str().unwrap() {
+                "accountSubscribe" => {
+                    let key = Pubkey::from_str(request["params"][0].as_str().unwrap()).unwrap();
+                    if fail_first.contains(&key) && failed.insert(key) {
+                        replies.push(json!({"jsonrpc": "2.0", "id": id, "error": {"code": -32602, "message": "mock failure"}}));
+                    } else {
+                        next_sid += 1;
+                        subscribed += 1;
+                        replies.push(json!({"jsonrpc": "2.0", "id": id, "result": next_sid}));
+                        replies.push(json!({
+                            "jsonrpc": "2.0",
+                            "method": "accountNotification",
+                            "params": {
+                                "subscription": next_sid,
+                                "result": {
+                                    "context": {"slot": 100 + next_sid},
+                                    "value": {
+                                        "lamports": 1,
+                                        "data": [BASE64.encode(key.to_bytes()), "base64"],
+                                        "owner": Pubkey::default().to_string(),
+                                        "executable": false,
+                                        "rentEpoch": 0,
+                                        "space": 32,
+                                    },
+                                },
+                            },
+                        }));
+                    }
+                }
+                "slotSubscribe" => {
+                    next_sid += 1;
+                    replies.push(json!({"jsonrpc": "2.0", "id": id, "result": next_sid}));
+                    replies.push(json!({
+                        "jsonrpc": "2.0",
+                        "method": "slotNotification",
+                        "params": {"subscription": next_sid, "result": {"parent": 99, "root": 98, "slot": 100}},
+                    }));
+                }
+                _ => replies.push(json!({"jsonrpc": "2.0", "id": id, "result": true})),
+            }
+            for reply in replies {
+                if ws.send(Message::Text(reply.to_string())).is_err() {
+                    return;
+                }
+            }
+            if close_after == Some(subscribed) {
+                let _ = ws.close(None);
+                let _ = ws.flush();
+                return;
+            }
+        }
+    }
+
+    //收集账户更新直到 keys 都到齐，顺便记下收到的 slot
+    fn wait_for(rx: &Receiver<StreamEvent>, keys: &[Pubkey], slots: &mut Vec<u64>) -> HashMap<Pubkey, AccountUpdate> {
+        let mut updates = HashMap::new();
+        while !keys.iter().all(|key| updates.contains_key(key)) {
+            match rx.recv_timeout(TIMEOUT).expect("timed out waiting for account updates") {
+                StreamEvent::Account(update) => {
+                    updates.insert(update.pubkey, update);
+                }
+                StreamEvent::Slot(slot) => slots.push(slot),
+            }
+        }
+        updates
+    }
+
+    #[test]
+    fn ws_source_retries_failed_subscription() {
+        let (stable, flaky, added) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let source = WsSource {
+            ws_url: mock_pubsub(vec![flaky, added], None),
+            commitment: CommitmentConfig::processed(),
+            retry_interval: Duration::from_millis(50),
+        };
+        let (tx, rx) = channel();
+        let handle = SourceHandle::start(Box::new(source), vec![stable, flaky], tx);
+
+        let mut slots = vec![];
+        let updates = wait_for(&rx, &[stable, flaky], &mut slots);
+        assert_eq!(updates[&stable].account.data, stable.to_bytes().to_vec());
+        assert_eq!(updates[&flaky].account.data, flaky.to_bytes().to_vec());
+
+        //新加的账户同样失败一次后重试成功，已有的订阅不受影响
+        handle.watch(vec![added]);
+        let updates = wait_for(&rx, &[added], &mut slots);
+        assert!(updates[&added].slot > 100);
+        while let Ok(event) = rx.try_recv() {
+            if let StreamEvent::Slot(slot) = event {
+                slots.push(slot);
+            }
+        }
+        assert_eq!(slots, vec![100]);
+
+        handle.unwatch(vec![stable]);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn ws_source_resubscribes_after_disconnect() {
+        let (first, second, added) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let source = WsSource {
+            ws_url: mock_pubsub(vec![], Some(3)),
+            commitment: CommitmentConfig::processed(),
+            retry_interval: Duration::from_millis(50),
+        };
+        let reconnects = metrics().source_reconnects.with_label_values(&["websocket"]).get();
+        let (tx, rx) = channel();
+        let handle = SourceHandle::start(Box::new(source), vec![first, second], tx);
+
+        let mut slots = vec![];
+        wait_for(&rx, &[first, second], &mut slots);
+        //第三个订阅成功后服务端断开，重连后三个账户（包括断开前 watch 的）都重新订阅
+        handle.watch(vec![added]);
+        let updates = wait_for(&rx, &[first, second, added], &mut slots);
+        assert_eq!(updates[&added].account.data, added.to_bytes().to_vec());
+        assert_eq!(slots, vec![100, 100]);
+        assert!(metrics().source_reconnects.with_label_values(&["websocket"]).get() > reconnects);
+
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn polling_source_survives_rpc_errors() {
+        let key = Pubkey::new_unique();
+        //第一次请求返回 503，之后正常
+        let stub = Stub::start(move |hit, _| match hit {
+            0 => (503, String::new()),
+            _ => (200, result(json!({
+                "context": {"slot": 300 + hit},
+                "value": [{
+                    "lamports": 1,
+                    "data": [BASE64.encode(key.to_bytes()), "base64"],
+                    "owner": Pubkey::default().to_string(),
+                    "executable": false,
+                    "rentEpoch": 0,
+                    "space": 32,
+                }],
+            }))),
+        });
+        let source = PollingSource {
+            rpc_client: Arc::new(stub.pool()),
+            commitment: CommitmentConfig::processed(),
+            batch_size: 100,
+            interval: Duration::from_millis(10),
+        };
+        let errors = metrics().source_errors.with_label_values(&["polling"]).get();
+        let (tx, rx) = channel();
+        let handle = SourceHandle::start(Box::new(source), vec![key], tx);
+
+        let mut slots = vec![];
+        let updates = wait_for(&rx, &[key], &mut slots);
+        assert_eq!(updates[&key].slot, 301);
+        //失败的那一轮不发 slot
+        assert!(slots.is_empty());
+        assert!(metrics().source_errors.with_label_values(&["polling"]).get() > errors);
+        handle.join().unwrap();
+    }
+
+    //geyser 节点的替身：记下每次收到的 SubscribeRequest，每条流第一次推一条 slot，
+    //之后 owner 匹配的程序账户和 watched 里的账户各推一条更新（每条流只推一次），账户数据就是 pubkey 本身；
+    //fail_first 时第一条流推完第一次请求的更新后返回错误
+    #[derive(Clone, Default)]
+    struct MockGeyser {
+        requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+        program_accounts: Vec<(Pubkey, Pubkey)>,
+        fail_first: bool,
+        streams: Arc<AtomicUsize>,
+    }
+
+    fn account_update(owner: Pubkey, pubkey: Pubkey, slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Account(SubscribeUpdateAccount {
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: pubkey.to_bytes().to_vec(),
+                    lamports: 1,
+                    owner: owner.to_bytes().to_vec(),
+                    data: pubkey.to_bytes().to_vec(),
+                    ..SubscribeUpdateAccountInfo::default()
+                }),
+                slot,
+                ..SubscribeUpdateAccount::default()
+            })),
+            ..SubscribeUpdate::default()
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for MockGeyser {
+        type SubscribeStream = UnboundedReceiverStream<Result<SubscribeUpdate, Status>>;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Self::SubscribeStream>, Status> {
+            let mut requests = request.into_inner();
+            let recorded = self.requests.clone();
+            let program_accounts = self.program_accounts.clone();
+            let fail = self.fail_first && self.streams.fetch_add(1, Ordering::SeqCst) == 0;
+            let (tx, rx) = unbounded_channel();
+            tokio::spawn(async move {
+                let mut sent = HashSet::new();
+                while let Some(Ok(request)) = requests.next().await {
+                    let owners = request.accounts
+                        .values()
+                        .flat_map(|filter| filter.owner.iter().map(|owner| Pubkey::from_str(owner).unwrap()))
+                        .collect::<HashSet<_>>();
+                    let mut accounts = program_accounts
+                        .iter()
+                        .filter(|(owner, _)| owners.contains(owner))
+                        .cloned()
+                        .collect::<Vec<_>>();
+                    accounts.extend(request.accounts
+                        .values()
+                        .flat_map(|filter| filter.account.iter().map(|key| (Pubkey::default(), Pubkey::from_str(key).unwrap()))));
+                    recorded.lock().unwrap().push(request);
+                    if sent.is_empty() {
+                        let _ = tx.send(Ok(SubscribeUpdate {
+                            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot {
+                                slot: 200,
+                                ..SubscribeUpdateSlot::default()
+                            })),
+                            ..SubscribeUpdate::default()
+                        }));
+                    }
+                    for (owner, pubkey) in accounts {
+                        if sent.insert(pubkey) {
+                            let _ = tx.send(Ok(account_update(owner, pubkey, 200 + sent.len() as u64)));
+                        }
+                    }
+                    if fail {
+                        let _ = tx.send(Err(Status::unavailable("mock disconnect")));
+                        break;
+                    }
+                }
+            });
+            Ok(Response::new(UnboundedReceiverStream::new(rx)))
+        }
+
+        async fn ping(&self, _: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(&self, _: Request<GetSlotRequest>) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(&self, _: Request<GetVersionRequest>) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    fn serve_geyser(mock: MockGeyser) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        listener.set_nonblocking(true).unwrap();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+            runtime.block_on(async move {
+                let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener).unwrap());
+                tonic::transport::Server::builder()
+                    .add_service(GeyserServer::new(mock))
+                    .serve_with_incoming(incoming)
+                    .await
+                    .unwrap();
+            });
+        });
+        endpoint
+    }
+
+    fn geyser_source(endpoint: String, owners: Vec<Pubkey>) -> GeyserSource {
+        GeyserSource {
+            endpoint,
+            x_token: None,
+            commitment: CommitmentConfig::confirmed(),
+            owners,
+            retry_interval: Duration::from_millis(50),
+        }
+    }
+
+    #[test]
+    fn geyser_source_filters_program_accounts() {
+        let program = Pubkey::new_unique();
+        let (owned, stray, vault, added) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let mock = MockGeyser {
+            program_accounts: vec![(program, owned), (program, stray)],
+            ..MockGeyser::default()
+        };
+        let source = geyser_source(serve_geyser(mock.clone()), vec![program]);
+        let (tx, rx) = channel();
+        let handle = SourceHandle::start(Box::new(source), vec![owned, vault], tx);
+
+        let mut slots = vec![];
+        let updates = wait_for(&rx, &[owned, vault], &mut slots);
+        assert_eq!(updates[&owned].account.owner, program);
+        assert_eq!(updates[&vault].account.data, vault.to_bytes().to_vec());
+        handle.watch(vec![added]);
+        let updates = wait_for(&rx, &[added], &mut slots);
+        assert_eq!(updates[&added].slot, 204);
+        assert_eq!(slots, vec![200]);
+        handle.join().unwrap();
+        //程序的其它账户（stray）在客户端被丢掉
+        assert!(rx.try_iter().all(|event| !matches!(event, StreamEvent::Account(update) if update.pubkey == stray)));
+
+        //按 owner 订阅程序账户，watched 每次都是完整的 key 列表
+        let requests = mock.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests.iter().all(|request| request.accounts["owners"].owner == vec![program.to_string()]));
+        let mut keys = requests[1].accounts["watched"].account.clone();
+        keys.sort();
+        let mut expected = vec![owned.to_string(), vault.to_string(), added.to_string()];
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert_eq!(requests[0].commitment, Some(GeyserCommitmentLevel::Confirmed as i32));
+    }
+
+    #[test]
+    fn geyser_source_reconnects_after_stream_error() {
+        let key = Pubkey::new_unique();
+        let mock = MockGeyser { fail_first: true, ..MockGeyser::default() };
+        let source = geyser_source(serve_geyser(mock.clone()), vec![]);
+        let reconnects = metrics().source_reconnects.with_label_values(&["geyser"]).get();
+        let (tx, rx) = channel();
+        let handle = SourceHandle::start(Box::new(source), vec![key], tx);
+
+        let mut slots = vec![];
+        wait_for(&rx, &[key], &mut slots);
+        //第一条流出错后重新订阅，同一个账户又推了一次
+        wait_for(&rx, &[key], &mut slots);
+        assert_eq!(slots, vec![200, 200]);
+        assert_eq!(mock.streams.load(Ordering::SeqCst), 2);
+        assert!(metrics().source_reconnects.with_label_values(&["geyser"]).get() > reconnects);
+        handle.join().unwrap();
+
+        //没有 owner 时不发 owners 过滤条件
+        let requests = mock.requests.lock().unwrap();
+        assert!(requests.iter().all(|request| !request.accounts.contains_key("owners")));
+    }
+}
@@ -0,0 +1,144 @@
+use solana_sdk::{
+    pubkey::Pubkey,
+    account::Account,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::pool::PoolRef;
+
+#[derive(Debug, Clone)]
+pub struct AccountUpdate {
+    pub pubkey: Pubkey,
+    pub account: Account,
+    pub slot: u64,
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    Account(AccountUpdate),
+    Slot(u64),
+}
+
+//一个账户可能被多个池子依赖（比如 clock）
+#[derive(Default)]
+pub struct WatchIndex(HashMap<Pubkey, Vec<PoolRef>>);
+
+impl WatchIndex {
+    pub fn new() -> Self {
+        Self(HashMap::new())
+    }
+
+    pub fn add_pool(&mut self, pool: &PoolRef) {
+        for key in pool.borrow().accounts_to_watch() {
+            self.0.entry(key).or_default().push(pool.clone());
+        }
+    }
+
+    pub fn keys(&self) -> Vec<Pubkey> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn contains(&self, key: &Pubkey) -> bool {
+        self.0.contains_key(key)
+    }
+
+    //pool 更新后依赖的账户可能变化（比如 orca 换了 tick array），
+    //watch / unwatch 交给数据源去订阅或退订
+    pub fn apply(&mut self, update: &AccountUpdate) -> ApplyResult {
+        let mut result = ApplyResult::default();
+        let Some(pools) = self.0.get(&update.pubkey).cloned() else {
+            return result;
+        };
+        for pool_ref in pools {
+            let (pool_id, changed, before, after) = {
+                let mut pool = pool_ref.borrow_mut();
+                let before: HashSet<Pubkey> = pool.accounts_to_watch().into_iter().collect();
+                let changed = pool.update(&update.pubkey, &update.account, update.slot);
+                let after: HashSet<Pubkey> = pool.accounts_to_watch().into_iter().collect();
+                (pool.get_pool_id(), changed, before, after)
+            };
+            if changed {
+                result.touched.push(pool_id);
+            }
+            for key in after.difference(&before) {
+                let pools = self.0.entry(*key).or_default();
+                if pools.is_empty() {
+                    result.watch.push(*key);
+                }
+                pools.push(pool_ref.clone());
+            }
+            for key in before.difference(&after) {
+                if let Some(pools) = self.0.get_mut(key) {
+                    pools.retain(|pool| pool.borrow().get_pool_id() != pool_id);
+                    if pools.is_empty() {
+                        self.0.remove(key);
+                        result.unwatch.push(*key);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ApplyResult {
+    pub touched: Vec<Pubkey>,
+    pub watch: Vec<Pubkey>,
+    pub unwatch: Vec<Pubkey>,
+}
+
+impl ApplyResult {
+    pub fn merge(&mut self, other: ApplyResult) {
+        self.touched.extend(other.touched);
+        self.watch.extend(other.watch);
+        self.unwatch.extend(other.unwatch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{arb::Arbitrager, pool::mock::MockPool};
+
+    fn update(pubkey: Pubkey, amounts: [u64; 2], slot: u64) -> AccountUpdate {
+        AccountUpdate {
+            pubkey,
+            account: Account { data: MockPool::data(amounts), ..Account::default() },
+            slot,
+        }
+    }
+
+    #[test]
+    fn research_only_touched_pools() {
+        //两个互不相干的三角形，都从 base 出发
+        let base = Pubkey::new_unique();
+        let (a, b, c, d) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let pools = [(base, a), (a, b), (b, base), (base, c), (c, d), (d, base)]
+            .into_iter()
+            .map(|mints| MockPool::new(Pubkey::new_unique(), [mints.0, mints.1], [1_000_000, 1_000_000]).into_ref())
+            .collect::<Vec<_>>();
+        let pool_ids = pools.iter().map(|pool| pool.borrow().get_pool_id()).collect::<Vec<_>>();
+        let mut index = WatchIndex::new();
+        for pool in &pools {
+            index.add_pool(pool);
+        }
+        let arbitrager = Arbitrager::new(pools, 3, 0, 0, None);
+
+        //不认识的账户什么都不动
+        assert!(index.apply(&update(Pubkey::new_unique(), [1, 1], 1)).touched.is_empty());
+        //两边都变得有利可图，但只有 a-b 池子是这次更新的
+        let mut result = index.apply(&update(pool_ids[1], [1_000_000, 2_000_000], 10));
+        result.merge(index.apply(&update(pool_ids[4], [1_000_000, 2_000_000], 9)));
+        assert_eq!(result.touched, vec![pool_ids[1], pool_ids[4]]);
+        assert!(result.watch.is_empty() && result.unwatch.is_empty());
+
+        let touched = HashSet::from([pool_ids[1]]);
+        let mut error_pools = HashSet::new();
+        let all = arbitrager.search_and_report(&[base], 10_000, &mut error_pools, None, &mut []).unwrap();
+        let reported = arbitrager.search_and_report(&[base], 10_000, &mut error_pools, Some(&touched), &mut []).unwrap();
+        assert!(all.iter().any(|opportunity| opportunity.pool_path.contains(&pool_ids[4])));
+        assert!(!reported.is_empty());
+        assert!(reported.iter().all(|opportunity| opportunity.pool_path.contains(&pool_ids[1])));
+    }
+}
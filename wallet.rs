@@ -0,0 +1,358 @@
+use solana_sdk::{
+    pubkey,
+    pubkey::Pubkey,
+    instruction::Instruction,
+    signature::{Keypair, Signature, Signer, read_keypair_file},
+    transaction::Transaction,
+    commitment_config::CommitmentConfig,
+    system_instruction,
+};
+use spl_associated_token_account::instruction::create_associated_token_account_idempotent;
+use std::{
+    error::Error, sync::Arc, path::Path,
+    collections::{HashMap, HashSet},
+};
+
+use tracing::{info, warn, info_span};
+use crate::{
+    arb::Arbitrager,
+    config::WalletConfig,
+    fetch::{AccountFetcher, BatchFetcher, FetchedAccount},
+    pool::{PoolRef, token_account_amount, user_token_account},
+    rpc_pool::RpcPool,
+};
+
+pub const TOKEN_2022_PROGRAM_ID: Pubkey = pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+//一笔交易里创建的 ATA 数
+const CREATE_CHUNK: usize = 8;
+
+pub fn read_keypair(path: &Path) -> Result<Keypair, Box<dyn Error>> {
+    read_keypair_file(path).map_err(|e| format!("read keypair {}: {}", path.display(), e).into())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MintKind {
+    SplToken,
+    //各 venue 的 swap 指令都按 spl token 的 ATA 和程序构建，token-2022 的 mint 不管
+    Token2022,
+    NotMint,
+}
+
+pub fn mint_kind(owner: &Pubkey) -> MintKind {
+    if *owner == spl_token::ID {
+        MintKind::SplToken
+    } else if *owner == TOKEN_2022_PROGRAM_ID {
+        MintKind::Token2022
+    } else {
+        MintKind::NotMint
+    }
+}
+
+//WSOL 低于 wsol_min 时补到 wsol_target，SOL 至少留 sol_reserve 付手续费，返回要转入的数量
+pub fn wsol_top_up(config: &WalletConfig, wsol: u64, lamports: u64) -> u64 {
+    if config.wsol_min == 0 || wsol >= config.wsol_min {
+        return 0;
+    }
+    config.wsol_target.saturating_sub(wsol).min(lamports.saturating_sub(config.sol_reserve))
+}
+
+#[derive(Debug, Clone)]
+pub struct TokenBalance {
+    pub mint: Pubkey,
+    //和 swap 指令一样用 pool::user_token_account
+    pub account: Pubkey,
+    //None 表示 ATA 还不存在
+    pub amount: Option<u64>,
+}
+
+//钱包里各 mint 的 ATA 和余额，定期刷新；负责补建 ATA、补充 WSOL、把零碎 token 换回 base mint
+pub struct Wallet {
+    pub keypair: Arc<Keypair>,
+    rpc_client: Arc<RpcPool>,
+    commitment: CommitmentConfig,
+    fetcher: BatchFetcher,
+    config: WalletConfig,
+    //SOL 余额，lamports
+    pub lamports: u64,
+    balances: HashMap<Pubkey, TokenBalance>,
+    //不存在、token-2022 或者不是 mint 的地址，不再重复拉
+    skipped: HashSet<Pubkey>,
+}
+
+impl Wallet {
+    //余额查询和发送交易用 fetcher 的 rpc 和 commitment
+    pub fn new(keypair: Keypair, fetcher: BatchFetcher, config: WalletConfig) -> Self {
+        Self {
+            keypair: Arc::new(keypair),
+            rpc_client: fetcher.rpc_client.clone(),
+            commitment: fetcher.commitment,
+            fetcher,
+            config,
+            lamports: 0,
+            balances: HashMap::new(),
+            skipped: HashSet::new(),
+        }
+    }
+
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    pub fn get(&self, mint: &Pubkey) -> Option<&TokenBalance> {
+        self.balances.get(mint)
+    }
+
+    pub fn balance(&self, mint: &Pubkey) -> u64 {
+        self.get(mint).and_then(|balance| balance.amount).unwrap_or(0)
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = &TokenBalance> {
+        self.balances.values()
+    }
+
+    pub fn missing_accounts(&self) -> Vec<&TokenBalance> {
+        self.balances.values().filter(|balance| balance.amount.is_none()).collect()
+    }
+
+    //按 keys 的顺序返回；有 batch 拉不到时不能当作账户不存在，整次刷新失败
+    fn fetch(&self, keys: &[Pubkey]) -> Result<Vec<FetchedAccount>, Box<dyn Error>> {
+        let mut fetch = self.fetcher.fetch(keys);
+        if let Some((key, e)) = fetch.failed.iter().next() {
+            return Err(format!("fetch {}: {}", key, e).into());
+        }
+        Ok(keys.iter().filter_map(|key| fetch.accounts.remove(key)).collect())
+    }
+
+    //新 mint 先拉 mint 账户确认是 spl token，再一起拉 ATA 和 SOL 余额
+    pub fn refresh(&mut self, mints: &[Pubkey]) -> Result<(), Box<dyn Error>> {
+        let owner = self.pubkey();
+        let unknown = mints
+            .iter()
+            .filter(|mint| !self.balances.contains_key(mint) && !self.skipped.contains(mint))
+            .copied()
+            .collect::<Vec<_>>();
+        for fetched in self.fetch(&unknown)? {
+            let Some(account) = fetched.account else {
+                warn!(mint = %fetched.pubkey, "mint not found");
+                self.skipped.insert(fetched.pubkey);
+                continue;
+            };
+            match mint_kind(&account.owner) {
+                MintKind::SplToken => {}
+                MintKind::Token2022 => {
+                    warn!(mint = %fetched.pubkey, "token-2022 mints are not supported by the swap builders");
+                    self.skipped.insert(fetched.pubkey);
+                    continue;
+                }
+                MintKind::NotMint => {
+                    warn!(mint = %fetched.pubkey, owner = %account.owner, "mint is not owned by a token program");
+                    self.skipped.insert(fetched.pubkey);
+                    continue;
+                }
+            }
+            self.balances.insert(fetched.pubkey, TokenBalance {
+                mint: fetched.pubkey,
+                account: user_token_account(&owner, &fetched.pubkey),
+                amount: None,
+            });
+        }
+
+        let accounts = self.balances.values().map(|balance| balance.account).collect::<Vec<_>>();
+        let fetched = self.fetch(&accounts)?
+            .into_iter()
+            .map(|fetched| (fetched.pubkey, fetched.account))
+            .collect::<HashMap<_, _>>();
+        for balance in self.balances.values_mut() {
+            balance.amount = fetched
+                .get(&balance.account)
+                .and_then(|account| account.as_ref())
+                .and_then(|account| token_account_amount(&account.data));
+        }
+        self.lamports = self.rpc_client.call(|client| client.get_balance_with_commitment(&owner, self.commitment))?.value;
+        Ok(())
+    }
+
+    //返回新建的 ATA 数，每个要付一笔租金
+    pub fn create_missing_accounts(&mut self) -> Result<usize, Box<dyn Error>> {
+        let owner = self.pubkey();
+        let missing = self.missing_accounts().into_iter().cloned().collect::<Vec<_>>();
+        for chunk in missing.chunks(CREATE_CHUNK) {
+            let instructions = chunk
+                .iter()
+                .map(|balance| create_associated_token_account_idempotent(&owner, &owner, &balance.mint, &spl_token::ID))
+                .collect::<Vec<_>>();
+            self.send(&instructions)?;
+            for balance in chunk {
+                info!(account = %balance.account, mint = %balance.mint, "created token account");
+                self.balances.get_mut(&balance.mint).unwrap().amount = Some(0);
+            }
+        }
+        Ok(missing.len())
+    }
+
+    //按 wsol_top_up 把 SOL 包成 WSOL，返回转入的数量
+    pub fn top_up_wsol(&mut self) -> Result<u64, Box<dyn Error>> {
+        let native_mint = spl_token::native_mint::ID;
+        let wsol = self.balance(&native_mint);
+        let amount = wsol_top_up(&self.config, wsol, self.lamports);
+        if amount == 0 {
+            if wsol < self.config.wsol_min {
+                warn!(wsol, wsol_min = self.config.wsol_min, lamports = self.lamports, "wsol below minimum, but no SOL above the reserve to wrap");
+            }
+            return Ok(0);
+        }
+        let owner = self.pubkey();
+        let account = user_token_account(&owner, &native_mint);
+        self.send(&[
+            create_associated_token_account_idempotent(&owner, &owner, &native_mint, &spl_token::ID),
+            system_instruction::transfer(&owner, &account, amount),
+            spl_token::instruction::sync_native(&spl_token::ID, &account)?,
+        ])?;
+        self.lamports -= amount;
+        self.balances.insert(native_mint, TokenBalance {
+            mint: native_mint,
+            account,
+            amount: Some(wsol + amount),
+        });
+        Ok(amount)
+    }
+
+    //非 base mint 的余额按单跳最优报价换回 base mint，报价低于 sweep_min_out 的不换，返回发出的交易数
+    pub fn sweep_dust(&mut self, arbitrager: &Arbitrager, base_mints: &[Pubkey]) -> Result<usize, Box<dyn Error>> {
+        let owner = self.pubkey();
+        let dust = self.balances
+            .values()
+            .filter(|balance| !base_mints.contains(&balance.mint))
+            .filter_map(|balance| Some((balance.mint, balance.amount.filter(|&amount| amount > 0)?)))
+            .collect::<Vec<_>>();
+        let mut swept = 0;
+        for (mint, amount) in dust {
+            let Some((pool, a_to_b, amount_out)) = best_exit(arbitrager, &mint, amount, base_mints) else {
+                continue;
+            };
+            if amount_out < self.config.sweep_min_out {
+                continue;
+            }
+            let min_amount_out = (amount_out as u128 * (10_000 - self.config.sweep_slippage_bps) as u128 / 10_000) as u64;
+            let instructions = pool.borrow().swap_ix(&owner, a_to_b, amount, min_amount_out)?;
+            match self.send(&instructions) {
+                Ok(signature) => {
+                    info!(%mint, amount, pool_id = %pool.borrow().get_pool_id(), amount_out, %signature, "swept dust");
+                    self.balances.get_mut(&mint).unwrap().amount = Some(0);
+                    swept += 1;
+                }
+                Err(e) => warn!(%mint, error = %e, "sweep failed"),
+            }
+        }
+        Ok(swept)
+    }
+
+    //定期维护，各步骤失败只打日志；只管 base mint 出发的路径上的 mint
+    pub fn maintain(&mut self, arbitrager: &Arbitrager, base_mints: &[Pubkey]) {
+        let _span = info_span!("wallet", owner = %self.pubkey()).entered();
+        if let Err(e) = self.refresh(&arbitrager.path_mints(base_mints)) {
+            warn!(error = %e, "refresh wallet failed");
+            return;
+        }
+        if self.config.create_accounts {
+            if let Err(e) = self.create_missing_accounts() {
+                warn!(error = %e, "create token accounts failed");
+            }
+        }
+        match self.top_up_wsol() {
+            Ok(0) => {}
+            Ok(amount) => info!(lamports = amount, "wrapped sol into wsol"),
+            Err(e) => warn!(error = %e, "top up wsol failed"),
+        }
+        if self.config.sweep {
+            if let Err(e) = self.sweep_dust(arbitrager, base_mints) {
+                warn!(error = %e, "sweep dust failed");
+            }
+        }
+    }
+
+    fn send(&self, instructions: &[Instruction]) -> Result<Signature, Box<dyn Error>> {
+        let recent_blockhash = self.rpc_client.call(|client| client.get_latest_blockhash())?;
+        let transaction = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.pubkey()),
+            &[self.keypair.as_ref()],
+            recent_blockhash,
+        );
+        Ok(self.rpc_client.call(|client| client.send_and_confirm_transaction(&transaction))?)
+    }
+}
+
+//mint 直接连到某个 base mint 的池子里报价最高的
+fn best_exit(arbitrager: &Arbitrager, mint: &Pubkey, amount: u64, base_mints: &[Pubkey]) -> Option<(PoolRef, bool, u64)> {
+    let src = *arbitrager.mint2idx.get(mint)?;
+    let edges = arbitrager.graph.0.get(&src)?;
+    base_mints
+        .iter()
+        .filter_map(|base_mint| edges.0.get(arbitrager.mint2idx.get(base_mint)?))
+        .flatten()
+        .filter_map(|pool| {
+            let a_to_b = pool.borrow().get_mints()[0] == *mint;
+            let amount_out = pool.borrow().calc_quote(a_to_b, amount);
+            (amount_out > 0).then(|| (pool.clone(), a_to_b, amount_out))
+        })
+        .max_by_key(|(_, _, amount_out)| *amount_out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RpcConfig;
+
+    fn wallet() -> Wallet {
+        let config = RpcConfig { url: "http://127.0.0.1:1".to_string(), ..RpcConfig::default() };
+        let rpc = Arc::new(RpcPool::from_config(&config, CommitmentConfig::confirmed()).unwrap());
+        let fetcher = BatchFetcher { rpc_client: rpc, commitment: CommitmentConfig::confirmed(), batch_size: 100, batch_retries: 0 };
+        Wallet::new(Keypair::new(), fetcher, WalletConfig::default())
+    }
+
+    #[test]
+    fn detects_token_program() {
+        assert_eq!(mint_kind(&spl_token::ID), MintKind::SplToken);
+        assert_eq!(mint_kind(&TOKEN_2022_PROGRAM_ID), MintKind::Token2022);
+        assert_eq!(mint_kind(&solana_sdk::system_program::ID), MintKind::NotMint);
+    }
+
+    #[test]
+    fn missing_accounts_are_the_untracked_atas() {
+        let mut wallet = wallet();
+        let owner = wallet.pubkey();
+        let mints = [Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique()];
+        for (mint, amount) in mints.iter().zip([None, Some(0), Some(5)]) {
+            wallet.balances.insert(*mint, TokenBalance { mint: *mint, account: user_token_account(&owner, mint), amount });
+        }
+        let missing = wallet.missing_accounts();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].mint, mints[0]);
+        //和 swap 指令用的是同一个 ATA
+        assert_eq!(missing[0].account, user_token_account(&owner, &mints[0]));
+        assert_eq!(wallet.balance(&mints[0]), 0);
+        assert_eq!(wallet.balance(&mints[2]), 5);
+        assert_eq!(wallet.balance(&Pubkey::new_unique()), 0);
+    }
+
+    #[test]
+    fn wsol_top_up_clamps() {
+        let config = WalletConfig {
+            wsol_min: 100,
+            wsol_target: 1_000,
+            sol_reserve: 500,
+            ..WalletConfig::default()
+        };
+        //不低于 wsol_min 时不补
+        assert_eq!(wsol_top_up(&config, 100, 10_000), 0);
+        //补到 wsol_target
+        assert_eq!(wsol_top_up(&config, 40, 10_000), 960);
+        //SOL 只够留 sol_reserve 以外的部分
+        assert_eq!(wsol_top_up(&config, 40, 800), 300);
+        assert_eq!(wsol_top_up(&config, 40, 500), 0);
+        assert_eq!(wsol_top_up(&config, 40, 100), 0);
+        //wsol_min 为 0 表示不补
+        assert_eq!(wsol_top_up(&WalletConfig { wsol_min: 0, ..config }, 0, 10_000), 0);
+    }
+}